@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file contains the subcommand for listing what this build of snpguest supports, for automation to branch on.
+
+use super::*;
+
+use display::OutputFormat;
+use fetch::ProcType;
+use verify::VerifyOutput;
+
+use serde_json::{json, Value};
+
+// Names of the values of a CLI enum, as they are given on the command line.
+fn value_names<T: ValueEnum>() -> Vec<String> {
+    T::value_variants()
+        .iter()
+        .filter_map(|value| value.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+// Capabilities of this build as a JSON object.
+fn capabilities() -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "processor_models": ProcType::ALL.map(|proc_type| proc_type.name()),
+        "report_versions": report::REPORT_VERSIONS,
+        "cert_formats": value_names::<CertFormat>(),
+        "output_formats": {
+            "display": value_names::<OutputFormat>(),
+            "verify": value_names::<VerifyOutput>(),
+        },
+        "features": {
+            "kds_fetch": true,
+            "hyperv": cfg!(feature = "hyperv"),
+        },
+    })
+}
+
+// Print the capabilities of this build in console
+pub fn cmd(quiet: bool) -> Result<()> {
+    if !quiet {
+        println!(
+            "{}",
+            json::to_json(&capabilities()).context("Could not serialize capabilities to JSON.")?
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = capabilities();
+        assert_eq!(capabilities["processor_models"][0], "milan");
+        assert!(capabilities["processor_models"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("turin")));
+        assert_eq!(capabilities["report_versions"], json!([2, 3]));
+        assert_eq!(
+            capabilities["output_formats"]["verify"],
+            json!(["text", "json", "yaml", "eat"])
+        );
+        assert_eq!(capabilities["features"]["hyperv"], cfg!(feature = "hyperv"));
+    }
+}
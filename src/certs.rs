@@ -6,15 +6,24 @@ use crate::fetch::Endorsement;
 use super::*;
 
 use std::{
+    env,
+    ffi::OsString,
     fs,
     io::{ErrorKind, Read, Write},
     path::{Path, PathBuf},
     str::FromStr,
 };
 
+use log::debug;
+
+use openssl::{asn1::Asn1Time, x509::X509};
+
 use sev::{
     certs::snp::{ca, Certificate, Chain},
-    firmware::{guest::Firmware, host::CertType},
+    firmware::{
+        guest::Firmware,
+        host::{CertTableEntry, CertType},
+    },
 };
 
 pub struct CertPaths {
@@ -23,7 +32,7 @@ pub struct CertPaths {
     pub vek_path: PathBuf,
 }
 
-#[derive(ValueEnum, Clone, Copy)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CertFormat {
     /// Certificates are encoded in PEM format.
     Pem,
@@ -57,8 +66,6 @@ pub fn convert_path_to_cert(
     cert_path: &PathBuf,
     cert_type: &str,
 ) -> Result<Certificate, anyhow::Error> {
-    let mut buf = vec![];
-
     let mut current_file = if cert_path.as_os_str().is_empty() {
         match fs::File::open(format!("./certs/{cert_type}.pem")) {
             Ok(file) => file,
@@ -77,15 +84,42 @@ pub fn convert_path_to_cert(
                 }
             },
         }
+    } else if cert_path.as_os_str() == "-" {
+        return convert_reader_to_cert(std::io::stdin().lock(), cert_type);
     } else {
         fs::File::open(cert_path).context(format!("Could not open provided {cert_type} file"))?
     };
 
-    current_file
+    debug!(
+        "Reading {cert_type} certificate from {}",
+        cert_path.display()
+    );
+    convert_reader_to_cert(&mut current_file, cert_type)
+}
+
+// Read a PEM or DER certificate from any reader, such as stdin. The whole stream is read
+// before parsing, so the encoding can be detected on streams that can't be rewound.
+pub fn convert_reader_to_cert<R: Read>(
+    mut reader: R,
+    cert_type: &str,
+) -> Result<Certificate, anyhow::Error> {
+    let mut buf = vec![];
+    reader
         .read_to_end(&mut buf)
         .context(format!("Could not read contents of {cert_type} file"))?;
+    debug!("Parsing {cert_type} certificate ({} bytes)", buf.len());
 
-    Ok(Certificate::from_bytes(&buf)?)
+    cert_from_bytes(&buf)
+}
+
+// Parse a certificate in PEM or DER. Certificate::from_bytes panics on data shorter than a
+// PEM header, so the encoding is told apart here.
+pub fn cert_from_bytes(bytes: &[u8]) -> Result<Certificate> {
+    if bytes.starts_with(b"-----BEGIN CERTIFICATE-----") {
+        Ok(Certificate::from_pem(bytes)?)
+    } else {
+        Ok(Certificate::from_der(bytes)?)
+    }
 }
 
 // Tryfrom function that takes in 3 certificate paths returns a snp Certificate Chain
@@ -117,18 +151,93 @@ impl TryFrom<CertPaths> for Chain {
     }
 }
 
-// Function used to write provided cert into desired directory.
-pub fn write_cert(
+// Pick the encoding to write certificates in, from the encoding argument of a subcommand or
+// the global --cert-format, defaulting to PEM.
+pub fn resolve_cert_format(
+    encoding: Option<CertFormat>,
+    cert_format: Option<CertFormat>,
+) -> Result<CertFormat> {
+    match (encoding, cert_format) {
+        (Some(encoding), Some(cert_format)) if encoding != cert_format => Err(anyhow::anyhow!(
+            "The encoding {encoding} conflicts with --cert-format {cert_format}."
+        )),
+        (encoding, cert_format) => Ok(encoding.or(cert_format).unwrap_or(CertFormat::Pem)),
+    }
+}
+
+// Environment variable holding the certs-dir to use when a command isn't given one.
+pub const CERTS_DIR_ENV: &str = "SNPGUEST_CERTS_DIR";
+
+// Pick the certs-dir from the argument of a command, falling back to SNPGUEST_CERTS_DIR.
+pub fn resolve_certs_dir(certs_dir: Option<PathBuf>) -> Option<PathBuf> {
+    certs_dir_or_env(certs_dir, |name| env::var_os(name))
+}
+
+// Same as resolve_certs_dir, failing when there is no certs-dir at all.
+pub fn require_certs_dir(certs_dir: Option<PathBuf>) -> Result<PathBuf> {
+    require_certs_dir_or_env(certs_dir, |name| env::var_os(name))
+}
+
+// Pick the certs-dir the way resolve_certs_dir does, with the environment read through var.
+fn certs_dir_or_env(
+    certs_dir: Option<PathBuf>,
+    var: impl FnOnce(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    certs_dir.or_else(|| {
+        var(CERTS_DIR_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+fn require_certs_dir_or_env(
+    certs_dir: Option<PathBuf>,
+    var: impl FnOnce(&str) -> Option<OsString>,
+) -> Result<PathBuf> {
+    certs_dir_or_env(certs_dir, var).ok_or_else(|| {
+        anyhow::anyhow!("No certificate directory provided, and {CERTS_DIR_ENV} is not set.")
+    })
+}
+
+// Path a certificate is written to in a directory, named the way find_cert_in_dir looks for it.
+pub fn cert_path(
     path: &Path,
     cert_type: &CertType,
-    data: &[u8],
     encoding: CertFormat,
     endorser: &Endorsement,
-) -> Result<()> {
-    // Get cert type into str
-    let cert: Certificate = Certificate::from_bytes(data)?;
+) -> PathBuf {
+    path.join(format!("{}.{encoding}", cert_name(cert_type, endorser)))
+}
+
+// Refuse to replace an existing certificate unless overwriting was asked for.
+pub fn check_overwrite(cert_path: &Path, overwrite: bool) -> Result<()> {
+    if cert_path.exists() && !overwrite {
+        return Err(anyhow::anyhow!(
+            "{} already exists. Use --force to overwrite it.",
+            cert_path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+// The certificate at a path, if it parses and is within its validity period.
+pub fn read_valid_cert_file(cert_path: &Path) -> Option<X509> {
+    let bytes = fs::read(cert_path).ok()?;
+    let cert = if bytes.starts_with(b"-----BEGIN CERTIFICATE-----") {
+        X509::from_pem(&bytes)
+    } else {
+        X509::from_der(&bytes)
+    }
+    .ok()?;
+    let now = Asn1Time::days_from_now(0).ok()?;
 
-    let cert_str: String = match (cert_type, endorser) {
+    (cert.not_before() <= now && cert.not_after() >= now).then_some(cert)
+}
+
+// File name of a certificate, without its extension.
+fn cert_name(cert_type: &CertType, endorser: &Endorsement) -> String {
+    match (cert_type, endorser) {
         (CertType::ASK, Endorsement::Vlek) => "asvk".to_string(),
         (_, _) => match cert_type {
             CertType::Empty => "empty".to_string(),
@@ -139,16 +248,33 @@ pub fn write_cert(
             CertType::CRL => "crl".to_string(),
             CertType::OTHER(uuid) => format!("other-{uuid}"),
         },
-    };
+    }
+}
+
+// Function used to write provided cert into desired directory. An existing certificate is
+// only replaced when overwrite is set.
+pub fn write_cert(
+    path: &Path,
+    cert_type: &CertType,
+    data: &[u8],
+    encoding: CertFormat,
+    endorser: &Endorsement,
+    overwrite: bool,
+) -> Result<()> {
+    // Get cert type into str
+    let cert: Certificate = cert_from_bytes(data)?;
+
+    let cert_str: String = cert_name(cert_type, endorser);
 
     let bytes: Vec<u8> = match encoding {
         CertFormat::Pem => cert.to_pem()?,
         CertFormat::Der => cert.to_der()?,
     };
 
-    let cert_path: PathBuf = path.join(format!("{cert_str}.{encoding}"));
+    let cert_path: PathBuf = cert_path(path, cert_type, encoding, endorser);
 
     // Write cert into directory
+    check_overwrite(&cert_path, overwrite)?;
     let mut file = if cert_path.exists() {
         std::fs::OpenOptions::new()
             .write(true)
@@ -165,18 +291,71 @@ pub fn write_cert(
     Ok(())
 }
 
+// Write the certificates of a certificate table into the directory, named after the role
+// their subject gives them. Entries that aren't certificates of an SNP chain are skipped.
+pub fn write_cert_table(dir: &Path, certs: &[CertTableEntry], encoding: CertFormat) -> Result<()> {
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The host provided no certificates. Populate the host certificate cache, or fetch the certificates from the KDS with snpguest fetch."
+        ));
+    }
+
+    fs::create_dir_all(dir).context("Could not create certs folder")?;
+    for entry in certs {
+        let parsed = cert_from_bytes(&entry.data)
+            .and_then(|cert| verify::cert_role(&cert).map(|role| (cert, role)));
+        let (cert, role) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                debug!(
+                    "Skipping {:?} entry of the certificate table: {err:#}",
+                    entry.cert_type
+                );
+                continue;
+            }
+        };
+        let bytes = match encoding {
+            CertFormat::Pem => cert.to_pem()?,
+            CertFormat::Der => cert.to_der()?,
+        };
+        let path = dir.join(format!("{role}.{encoding}"));
+        fs::write(&path, bytes).context(format!("Could not write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CertificatesArgs {
+    #[command(subcommand)]
+    pub cmd: Option<CertificatesCmd>,
+
     /// Specify encoding to use for certificates.
     #[arg(value_name = "encoding", required = true)]
-    pub encoding: CertFormat,
+    pub encoding: Option<CertFormat>,
 
-    /// Directory to store certificates in. Required if requesting an extended-report.
-    #[arg(value_name = "certs-dir", required = true)]
-    pub certs_dir: PathBuf,
+    /// Directory to store certificates in, defaulting to $SNPGUEST_CERTS_DIR.
+    #[arg(value_name = "certs-dir")]
+    pub certs_dir: Option<PathBuf>,
 }
 
-pub fn get_ext_certs(args: CertificatesArgs) -> Result<()> {
+#[derive(Subcommand)]
+pub enum CertificatesCmd {
+    /// Convert a certificate between PEM and DER.
+    Convert(convert::Args),
+}
+
+pub fn get_ext_certs(args: CertificatesArgs, cert_format: Option<CertFormat>) -> Result<()> {
+    if let Some(cmd) = args.cmd {
+        return match cmd {
+            CertificatesCmd::Convert(args) => convert::convert_cert(args, cert_format),
+        };
+    }
+
+    let encoding = resolve_cert_format(args.encoding, cert_format)?;
+    let certs_dir = require_certs_dir(args.certs_dir)?;
+
     let mut sev_fw: Firmware = Firmware::open().context("failed to open SEV firmware device.")?;
 
     // Generate random request data
@@ -188,8 +367,8 @@ pub fn get_ext_certs(args: CertificatesArgs) -> Result<()> {
         .context("Failed to get extended report.")?;
 
     // Create certificate directory if missing
-    if !args.certs_dir.exists() {
-        fs::create_dir(&args.certs_dir).context("Could not create certs folder")?;
+    if !certs_dir.exists() {
+        fs::create_dir(&certs_dir).context("Could not create certs folder")?;
     };
 
     // If certificates are present, write certs into directory
@@ -202,11 +381,12 @@ pub fn get_ext_certs(args: CertificatesArgs) -> Result<()> {
                 endorsement = Endorsement::Vlek;
             }
             write_cert(
-                &args.certs_dir,
+                &certs_dir,
                 &cert.cert_type,
                 &cert.data,
-                args.encoding,
+                encoding,
                 &endorsement,
+                true,
             )
         })?;
     } else {
@@ -215,3 +395,225 @@ pub fn get_ext_certs(args: CertificatesArgs) -> Result<()> {
 
     Ok(())
 }
+
+mod convert {
+    use super::*;
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Path to the certificate to convert, in PEM or DER, or "-" to read it from stdin.
+        #[arg(value_name = "in", required = true)]
+        pub input: PathBuf,
+
+        /// Path to write the converted certificate to. Defaults to <role>.<encoding> next to
+        /// the input, and to that name inside it when a directory is given.
+        #[arg(value_name = "out")]
+        pub output: Option<PathBuf>,
+
+        /// Encoding to convert the certificate to.
+        #[arg(long, value_name = "to", required = true)]
+        pub to: CertFormat,
+
+        /// Overwrite the output if it already exists.
+        #[arg(long)]
+        pub force: bool,
+    }
+
+    // Path to write the converted certificate to, named after its role unless given a file.
+    fn output_path(args: &Args, role: &str, encoding: CertFormat) -> PathBuf {
+        let name = format!("{role}.{encoding}");
+        match &args.output {
+            Some(output) if output.is_dir() => output.join(name),
+            Some(output) => output.clone(),
+            None => args
+                .input
+                .parent()
+                .map(|dir| dir.join(&name))
+                .unwrap_or_else(|| PathBuf::from(name)),
+        }
+    }
+
+    pub fn convert_cert(args: Args, cert_format: Option<CertFormat>) -> Result<()> {
+        let encoding = resolve_cert_format(Some(args.to), cert_format)?;
+
+        let cert = convert_path_to_cert(&args.input, "input")?;
+        // Refuse anything but a certificate of an SNP chain
+        let role = verify::cert_role(&cert).context("The input is not an SNP certificate")?;
+
+        let path = output_path(&args, role, encoding);
+        check_overwrite(&path, args.force)?;
+
+        let bytes = match encoding {
+            CertFormat::Pem => cert.to_pem(),
+            CertFormat::Der => cert.to_der(),
+        }
+        .context(format!("Could not encode the {role} as {encoding}"))?;
+        fs::write(&path, bytes).context(format!("Could not write {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_cert_format() {
+        assert_eq!(resolve_cert_format(None, None).unwrap(), CertFormat::Pem);
+        assert_eq!(
+            resolve_cert_format(None, Some(CertFormat::Der)).unwrap(),
+            CertFormat::Der
+        );
+        assert_eq!(
+            resolve_cert_format(Some(CertFormat::Der), None).unwrap(),
+            CertFormat::Der
+        );
+        assert!(resolve_cert_format(Some(CertFormat::Der), Some(CertFormat::Pem)).is_err());
+    }
+
+    #[test]
+    fn test_resolve_certs_dir() {
+        let set = |name: &str| (name == CERTS_DIR_ENV).then(|| OsString::from("/env/certs"));
+        let empty = |_: &str| Some(OsString::new());
+        let unset = |_: &str| None;
+
+        assert_eq!(
+            certs_dir_or_env(Some(PathBuf::from("certs")), set),
+            Some(PathBuf::from("certs"))
+        );
+        assert_eq!(
+            certs_dir_or_env(None, set),
+            Some(PathBuf::from("/env/certs"))
+        );
+        assert_eq!(certs_dir_or_env(None, empty), None);
+
+        assert!(require_certs_dir_or_env(None, unset)
+            .unwrap_err()
+            .to_string()
+            .contains(CERTS_DIR_ENV));
+        assert_eq!(
+            require_certs_dir_or_env(None, set).unwrap(),
+            PathBuf::from("/env/certs")
+        );
+    }
+
+    #[test]
+    fn test_convert_reader_to_cert() {
+        use sev::certs::snp::builtin::milan::ARK;
+
+        let der = Certificate::from_pem(ARK).unwrap().to_der().unwrap();
+        for bytes in [ARK.to_vec(), der.clone()] {
+            let cert = convert_reader_to_cert(bytes.as_slice(), "ark").unwrap();
+            assert_eq!(cert.to_der().unwrap(), der);
+        }
+        assert!(convert_reader_to_cert(&b"short"[..], "ark").is_err());
+    }
+
+    #[test]
+    fn test_convert_cert() {
+        use sev::certs::snp::builtin::milan::ARK;
+
+        let dir = std::env::temp_dir().join(format!("snpguest-convert-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("root.pem");
+        fs::write(&input, ARK).unwrap();
+
+        let args = |to: CertFormat, force: bool| convert::Args {
+            input: input.clone(),
+            output: None,
+            to,
+            force,
+        };
+
+        // Named after the role read from the subject
+        convert::convert_cert(args(CertFormat::Der, false), None).unwrap();
+        let der = fs::read(dir.join("ark.der")).unwrap();
+        assert_eq!(der, Certificate::from_pem(ARK).unwrap().to_der().unwrap());
+
+        let err = convert::convert_cert(args(CertFormat::Der, false), None).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        convert::convert_cert(args(CertFormat::Der, true), None).unwrap();
+
+        fs::write(&input, b"garbage").unwrap();
+        assert!(convert::convert_cert(args(CertFormat::Der, true), None).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_cert_table() {
+        use sev::certs::snp::builtin::milan::{ARK, ASK};
+
+        let dir = std::env::temp_dir().join(format!("snpguest-cert-table-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let err = write_cert_table(&dir, &[], CertFormat::Pem).unwrap_err();
+        assert!(err.to_string().contains("snpguest fetch"));
+
+        // Roles come from the subjects, not the table entry types
+        let certs = [
+            CertTableEntry::new(CertType::ASK, ARK.to_vec()),
+            CertTableEntry::new(CertType::ARK, ASK.to_vec()),
+            CertTableEntry::new(CertType::VCEK, b"short".to_vec()),
+        ];
+        write_cert_table(&dir, &certs, CertFormat::Der).unwrap();
+        assert_eq!(
+            fs::read(dir.join("ark.der")).unwrap(),
+            Certificate::from_pem(ARK).unwrap().to_der().unwrap()
+        );
+        assert!(dir.join("ask.der").exists());
+        assert!(!dir.join("vcek.der").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_cert_overwrite() {
+        use sev::certs::snp::builtin::milan::ASK;
+
+        let dir = std::env::temp_dir().join(format!("snpguest-write-cert-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = cert_path(&dir, &CertType::ASK, CertFormat::Pem, &Endorsement::Vlek);
+        assert_eq!(path, dir.join("asvk.pem"));
+        assert!(read_valid_cert_file(&path).is_none());
+
+        write_cert(
+            &dir,
+            &CertType::ASK,
+            ASK,
+            CertFormat::Pem,
+            &Endorsement::Vlek,
+            false,
+        )
+        .unwrap();
+        assert!(read_valid_cert_file(&path).is_some());
+        let err = write_cert(
+            &dir,
+            &CertType::ASK,
+            ASK,
+            CertFormat::Pem,
+            &Endorsement::Vlek,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        write_cert(
+            &dir,
+            &CertType::ASK,
+            ASK,
+            CertFormat::Pem,
+            &Endorsement::Vlek,
+            true,
+        )
+        .unwrap();
+
+        fs::write(&path, b"not a certificate").unwrap();
+        assert!(read_valid_cert_file(&path).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file contains the claims of a verified attestation report, and their JSON and CBOR (EAT) encodings.
+
+use super::*;
+
+use fetch::Tcb;
+use policy::decode_policy;
+
+use ciborium::value::{Integer, Value as CborValue};
+use serde_json::{json, Map, Value as JsonValue};
+use sev::firmware::guest::AttestationReport;
+
+// EAT claim keys (RFC 9711) for the claims that map onto a report field.
+const EAT_NONCE: i64 = 10;
+const EAT_DBGSTAT: i64 = 263;
+
+// EAT debug status values.
+const DBGSTAT_ENABLED: u64 = 0;
+const DBGSTAT_DISABLED: u64 = 1;
+
+// Value of a claim, kept apart from its encoding so JSON and CBOR can lay out bytes differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClaimValue {
+    Bytes(Vec<u8>),
+    Uint(u64),
+    Bool(bool),
+    Text(String),
+    Map(Vec<(String, ClaimValue)>),
+}
+
+// A claim, along with its EAT key when it is a standard EAT claim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub name: &'static str,
+    pub eat_key: Option<i64>,
+    pub value: ClaimValue,
+}
+
+// Claims about an attestation report and the outcome of its verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claims(pub Vec<Claim>);
+
+impl Claims {
+    pub fn new(
+        att_report: &AttestationReport,
+        reported_tcb: Option<&Tcb>,
+        current_tcb: Option<&Tcb>,
+        vek_type: Option<&str>,
+        outcome: Result<(), &anyhow::Error>,
+    ) -> Claims {
+        let policy = decode_policy(att_report.policy.0);
+        let claim = |name, value| Claim {
+            name,
+            eat_key: None,
+            value,
+        };
+
+        let mut policy_claims = vec![
+            ("raw".to_string(), ClaimValue::Uint(att_report.policy.0)),
+            (
+                "abi_major".to_string(),
+                ClaimValue::Uint(policy.abi_major.into()),
+            ),
+            (
+                "abi_minor".to_string(),
+                ClaimValue::Uint(policy.abi_minor.into()),
+            ),
+        ];
+        policy_claims.extend(
+            policy
+                .flags()
+                .iter()
+                .map(|(name, value)| (name.to_string(), ClaimValue::Bool(*value))),
+        );
+
+        let mut claims = vec![
+            Claim {
+                name: "eat_nonce",
+                eat_key: Some(EAT_NONCE),
+                value: ClaimValue::Bytes(att_report.report_data.to_vec()),
+            },
+            Claim {
+                name: "dbgstat",
+                eat_key: Some(EAT_DBGSTAT),
+                value: ClaimValue::Uint(if policy.debug_allowed {
+                    DBGSTAT_ENABLED
+                } else {
+                    DBGSTAT_DISABLED
+                }),
+            },
+            claim(
+                "measurement",
+                ClaimValue::Bytes(att_report.measurement.to_vec()),
+            ),
+            claim("policy", ClaimValue::Map(policy_claims)),
+        ];
+
+        let component_name = |name: &str| name.to_lowercase().replace(' ', "_");
+        let tcb_claim = |tcb: &Tcb| {
+            ClaimValue::Map(
+                tcb.components()
+                    .into_iter()
+                    .map(|(name, value)| (component_name(name), ClaimValue::Uint(value.into())))
+                    .collect(),
+            )
+        };
+        if let Some(tcb) = reported_tcb {
+            claims.push(claim("reported_tcb", tcb_claim(tcb)));
+        }
+        if let Some(tcb) = current_tcb {
+            claims.push(claim("current_tcb", tcb_claim(tcb)));
+        }
+        // How each reported TCB component relates to the current one
+        if let (Some(reported), Some(current)) = (reported_tcb, current_tcb) {
+            let relations = reported
+                .compare(current)
+                .into_iter()
+                .map(|(name, _, _, relation)| {
+                    (
+                        component_name(name),
+                        ClaimValue::Text(component_name(&relation.to_string())),
+                    )
+                })
+                .collect();
+            claims.push(claim("tcb_relation", ClaimValue::Map(relations)));
+        }
+        if let Some(vek_type) = vek_type {
+            claims.push(claim("vek", ClaimValue::Text(vek_type.to_string())));
+        }
+
+        claims.push(claim("verified", ClaimValue::Bool(outcome.is_ok())));
+        if let Err(err) = outcome {
+            claims.push(claim("error", ClaimValue::Text(format!("{err:#}"))));
+        }
+
+        Claims(claims)
+    }
+
+    // JSON object keyed by claim name, with bytes hex encoded.
+    pub fn to_json(&self) -> JsonValue {
+        fn json(value: &ClaimValue) -> JsonValue {
+            match value {
+                ClaimValue::Bytes(bytes) => JsonValue::String(hex::encode(bytes)),
+                ClaimValue::Uint(value) => JsonValue::from(*value),
+                ClaimValue::Bool(value) => JsonValue::Bool(*value),
+                ClaimValue::Text(text) => JsonValue::String(text.clone()),
+                ClaimValue::Map(entries) => JsonValue::Object(
+                    entries
+                        .iter()
+                        .map(|(name, value)| (name.clone(), json(value)))
+                        .collect::<Map<_, _>>(),
+                ),
+            }
+        }
+
+        JsonValue::Object(
+            self.0
+                .iter()
+                .map(|claim| (claim.name.to_string(), json(&claim.value)))
+                .collect(),
+        )
+    }
+
+    // CBOR map keyed by EAT claim key where there is one, and by claim name otherwise.
+    pub fn to_eat(&self) -> Result<Vec<u8>> {
+        fn cbor(value: &ClaimValue) -> CborValue {
+            match value {
+                ClaimValue::Bytes(bytes) => CborValue::Bytes(bytes.clone()),
+                ClaimValue::Uint(value) => CborValue::Integer(Integer::from(*value)),
+                ClaimValue::Bool(value) => CborValue::Bool(*value),
+                ClaimValue::Text(text) => CborValue::Text(text.clone()),
+                ClaimValue::Map(entries) => CborValue::Map(
+                    entries
+                        .iter()
+                        .map(|(name, value)| (CborValue::Text(name.clone()), cbor(value)))
+                        .collect(),
+                ),
+            }
+        }
+
+        let map = CborValue::Map(
+            self.0
+                .iter()
+                .map(|claim| {
+                    let key = match claim.eat_key {
+                        Some(key) => CborValue::Integer(Integer::from(key)),
+                        None => CborValue::Text(claim.name.to_string()),
+                    };
+                    (key, cbor(&claim.value))
+                })
+                .collect(),
+        );
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&map, &mut bytes).context("Could not encode claims as CBOR.")?;
+
+        Ok(bytes)
+    }
+}
+
+// Concise claims of a report that passed verification, for a policy engine. Each claim is
+// marked verified only when the checks that were run cover it: every report field needs the
+// signature, and the TCB and signer also need the TCB to have matched the VEK.
+pub fn verified_claims(
+    att_report: &AttestationReport,
+    reported_tcb: Option<&Tcb>,
+    vek_type: Option<&str>,
+    signature_verified: bool,
+    tcb_verified: bool,
+) -> JsonValue {
+    let policy = decode_policy(att_report.policy.0);
+    let mut policy_json: Map<String, JsonValue> = policy
+        .flags()
+        .iter()
+        .map(|(name, value)| (name.to_string(), JsonValue::Bool(*value)))
+        .collect();
+    policy_json.insert("abi".to_string(), policy.abi().to_string().into());
+
+    let claim = |value: JsonValue, verified: bool| json!({ "value": value, "verified": verified });
+    let vek_verified = signature_verified && tcb_verified;
+
+    json!({
+        "verdict": if vek_verified { "verified" } else { "partially_verified" },
+        "claims": {
+            "measurement": claim(hex::encode(att_report.measurement).into(), signature_verified),
+            "policy": claim(policy_json.into(), signature_verified),
+            "report_data": claim(hex::encode(att_report.report_data).into(), signature_verified),
+            "vmpl": claim(att_report.vmpl.into(), signature_verified),
+            "tcb": claim(json!(reported_tcb), vek_verified),
+            "signer": claim(json!(vek_type), vek_verified),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims() {
+        let mut att_report = AttestationReport::default();
+        att_report.report_data = [0xAA; 64];
+        // SMT allowed, debug allowed
+        att_report.policy.0 = 0x000b_0000;
+        let tcb = Tcb {
+            fmc: None,
+            bootloader: 3,
+            tee: 0,
+            snp: 8,
+            microcode: 115,
+        };
+
+        let current_tcb = Tcb { snp: 9, ..tcb };
+
+        let claims = Claims::new(
+            &att_report,
+            Some(&tcb),
+            Some(&current_tcb),
+            Some("VCEK"),
+            Ok(()),
+        );
+        let json = claims.to_json();
+        assert_eq!(json["eat_nonce"], hex::encode([0xAA; 64]));
+        assert_eq!(json["dbgstat"], DBGSTAT_ENABLED);
+        assert_eq!(json["policy"]["debug"], true);
+        assert_eq!(json["reported_tcb"]["boot_loader"], 3);
+        assert_eq!(json["current_tcb"]["snp"], 9);
+        assert_eq!(json["tcb_relation"]["snp"], "downgrade");
+        assert_eq!(json["tcb_relation"]["microcode"], "equal");
+        assert_eq!(json["verified"], true);
+        assert!(json.get("error").is_none());
+
+        let eat: CborValue =
+            ciborium::de::from_reader(claims.to_eat().unwrap().as_slice()).unwrap();
+        let entries = eat.as_map().unwrap();
+        let nonce = entries
+            .iter()
+            .find(|(key, _)| *key == CborValue::Integer(Integer::from(EAT_NONCE)))
+            .unwrap();
+        assert_eq!(nonce.1, CborValue::Bytes(vec![0xAA; 64]));
+
+        let err = anyhow::anyhow!("VEK did NOT sign the Attestation Report!");
+        let json = Claims::new(&att_report, None, None, None, Err(&err)).to_json();
+        assert_eq!(json["verified"], false);
+        assert_eq!(json["error"], err.to_string());
+    }
+
+    #[test]
+    fn test_verified_claims() {
+        let mut att_report = AttestationReport::default();
+        att_report.vmpl = 1;
+        att_report.policy.0 = 0x0003_0133;
+        let tcb = Tcb {
+            fmc: None,
+            bootloader: 3,
+            tee: 0,
+            snp: 8,
+            microcode: 115,
+        };
+
+        let json = verified_claims(&att_report, Some(&tcb), Some("VCEK"), true, true);
+        assert_eq!(json["verdict"], "verified");
+        let claims = &json["claims"];
+        assert_eq!(claims["measurement"]["value"], hex::encode([0; 48]));
+        assert_eq!(claims["policy"]["value"]["abi"], "1.51");
+        assert_eq!(claims["policy"]["value"]["debug"], false);
+        assert_eq!(claims["vmpl"], json!({ "value": 1, "verified": true }));
+        assert_eq!(claims["tcb"]["value"]["snp"], 8);
+        assert_eq!(
+            claims["signer"],
+            json!({ "value": "VCEK", "verified": true })
+        );
+
+        // With the signature alone, the VEK wasn't matched to the TCB of the report
+        let json = verified_claims(&att_report, Some(&tcb), Some("VCEK"), true, false);
+        assert_eq!(json["verdict"], "partially_verified");
+        assert_eq!(json["claims"]["measurement"]["verified"], true);
+        assert_eq!(json["claims"]["tcb"]["verified"], false);
+        assert_eq!(json["claims"]["signer"]["verified"], false);
+
+        // With the TCB alone, no field of the report is covered by the signature
+        let json = verified_claims(&att_report, Some(&tcb), Some("VCEK"), false, true);
+        assert_eq!(json["claims"]["report_data"]["verified"], false);
+        assert_eq!(json["claims"]["tcb"]["verified"], false);
+    }
+}
@@ -1,9 +1,23 @@
 // SPDX-License-Identifier: Apache-2.0
-// This file contains the subcommands for displaying attestation reports and derived keys.
+// This file contains the subcommands for displaying attestation reports, derived keys and certificates.
 
 use super::*;
 use std::path::PathBuf;
 
+pub use report_display::report_json;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text.
+    Text,
+
+    /// Structured JSON.
+    Json,
+
+    /// The JSON structure as YAML.
+    Yaml,
+}
+
 #[derive(Subcommand)]
 pub enum DisplayCmd {
     /// Display an attestation report in console.
@@ -11,22 +25,155 @@ pub enum DisplayCmd {
 
     /// Display the derived key in console.
     Key(key_display::Args),
+
+    /// Display the SHA-384 digest the attestation report signature covers.
+    ReportDigest(report_digest::Args),
+
+    /// Display the fields that differ between two attestation reports.
+    ReportDiff(report_diff::Args),
+
+    /// Display a certificate, and optionally the extensions it carries.
+    Cert(cert_display::Args),
 }
 
 pub fn cmd(cmd: DisplayCmd, quiet: bool) -> Result<()> {
     match cmd {
         DisplayCmd::Report(args) => report_display::display_attestation_report(args, quiet),
         DisplayCmd::Key(args) => key_display::display_derived_key(args, quiet),
+        DisplayCmd::ReportDigest(args) => report_digest::display_report_digest(args, quiet),
+        DisplayCmd::ReportDiff(args) => report_diff::display_report_diff(args, quiet),
+        DisplayCmd::Cert(args) => cert_display::display_cert(args, quiet),
     }
 }
 mod report_display {
     use super::*;
+    use fetch::ProcType;
+    use serde_json::{json, Value};
+    use sev::firmware::{guest::AttestationReport, host::TcbVersion};
 
     #[derive(Parser)]
     pub struct Args {
         /// Path to attestation report to display.
         #[arg(value_name = "att-report-path", required = true)]
         pub att_report_path: PathBuf,
+
+        /// Format to display the report in.
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        pub output: OutputFormat,
+
+        /// Print only the value of this field of the report, such as measurement or chip_id.
+        #[arg(long, value_name = "field", conflicts_with = "output")]
+        pub field: Option<String>,
+    }
+
+    // Lay out a TCB version by component, falling back to the raw bytes if it doesn't fit
+    // the layout of the processor generation.
+    fn tcb_json(proc_model: &ProcType, tcb: &TcbVersion) -> Value {
+        match proc_model.decode_tcb(tcb) {
+            Ok(tcb) => serde_json::to_value(tcb).unwrap_or_default(),
+            Err(_) => json!({
+                "raw": hex::encode(bincode::serialize(tcb).unwrap_or_default()),
+            }),
+        }
+    }
+
+    // Structured view of every field of the attestation report.
+    pub fn report_json(att_report: &AttestationReport) -> Value {
+        let proc_model = report::get_processor_model(att_report).unwrap_or(ProcType::Milan);
+        let policy = policy::decode_policy(att_report.policy.0);
+
+        json!({
+            "version": att_report.version,
+            "guest_svn": att_report.guest_svn,
+            "policy": {
+                "raw": format!("{:#x}", att_report.policy.0),
+                "abi_major": policy.abi_major,
+                "abi_minor": policy.abi_minor,
+                "smt_allowed": policy.smt_allowed,
+                "migrate_ma_allowed": policy.migrate_ma_allowed,
+                "debug_allowed": policy.debug_allowed,
+                "single_socket_required": policy.single_socket_required,
+                "cxl_allowed": policy.cxl_allowed,
+                "mem_aes_256_xts": policy.mem_aes_256_xts,
+                "rapl_dis": policy.rapl_dis,
+                "ciphertext_hiding": policy.ciphertext_hiding,
+            },
+            "family_id": hex::encode(att_report.family_id),
+            "image_id": hex::encode(att_report.image_id),
+            "vmpl": att_report.vmpl,
+            "sig_algo": att_report.sig_algo,
+            "current_tcb": tcb_json(&proc_model, &att_report.current_tcb),
+            "plat_info": format!("{:#x}", att_report.plat_info.0),
+            "report_data": hex::encode(att_report.report_data),
+            "measurement": hex::encode(att_report.measurement),
+            "host_data": hex::encode(att_report.host_data),
+            "id_key_digest": hex::encode(att_report.id_key_digest),
+            "author_key_digest": hex::encode(att_report.author_key_digest),
+            "report_id": hex::encode(att_report.report_id),
+            "report_id_ma": hex::encode(att_report.report_id_ma),
+            "reported_tcb": tcb_json(&proc_model, &att_report.reported_tcb),
+            "chip_id": hex::encode(att_report.chip_id),
+            "committed_tcb": tcb_json(&proc_model, &att_report.committed_tcb),
+            "current_version": format!(
+                "{}.{}.{}",
+                att_report.current_major, att_report.current_minor, att_report.current_build
+            ),
+            "committed_version": format!(
+                "{}.{}.{}",
+                att_report.committed_major, att_report.committed_minor, att_report.committed_build
+            ),
+            "launch_tcb": tcb_json(&proc_model, &att_report.launch_tcb),
+            "signature": {
+                "r": hex::encode(att_report.signature.r()),
+                "s": hex::encode(att_report.signature.s()),
+            },
+        })
+    }
+
+    // Value of a single field of the report, undecorated: bytes in hex, numbers in decimal,
+    // and the policy and TCB versions as their raw hex value.
+    fn report_field(att_report: &AttestationReport, field: &str) -> Result<String> {
+        let raw_tcb = |tcb: &TcbVersion| -> Result<String> {
+            let bytes: [u8; 8] = bincode::serialize(tcb)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Could not encode TCB version."))?;
+            Ok(format!("{:#018x}", u64::from_le_bytes(bytes)))
+        };
+
+        match field {
+            "current_tcb" => return raw_tcb(&att_report.current_tcb),
+            "reported_tcb" => return raw_tcb(&att_report.reported_tcb),
+            "committed_tcb" => return raw_tcb(&att_report.committed_tcb),
+            "launch_tcb" => return raw_tcb(&att_report.launch_tcb),
+            "signature" => {
+                return Ok(format!(
+                    "{}{}",
+                    hex::encode(att_report.signature.r()),
+                    hex::encode(att_report.signature.s())
+                ))
+            }
+            _ => {}
+        }
+
+        let report = report_json(att_report);
+        match report.get(field) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(Value::Object(value)) if value.contains_key("raw") => Ok(value["raw"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| value["raw"].to_string())),
+            Some(value) => Ok(value.to_string()),
+            None => {
+                let fields: Vec<&str> = report
+                    .as_object()
+                    .map(|fields| fields.keys().map(String::as_str).collect())
+                    .unwrap_or_default();
+                Err(anyhow::anyhow!(
+                    "Unknown report field {field}. Valid fields are: {}.",
+                    fields.join(", ")
+                ))
+            }
+        }
     }
 
     // Print attestation report in console
@@ -34,12 +181,81 @@ mod report_display {
         let att_report = report::read_report(args.att_report_path)
             .context("Could not open attestation report")?;
 
+        if let Some(field) = &args.field {
+            let value = report_field(&att_report, field)?;
+            if !quiet {
+                println!("{value}");
+            }
+            return Ok(());
+        }
+
         if !quiet {
-            println!("{}", att_report);
+            match args.output {
+                OutputFormat::Text => println!("{}", att_report),
+                OutputFormat::Json => println!(
+                    "{}",
+                    json::to_json(&report_json(&att_report))
+                        .context("Could not serialize attestation report to JSON.")?
+                ),
+                OutputFormat::Yaml => print!(
+                    "{}",
+                    serde_yaml::to_string(&report_json(&att_report))
+                        .context("Could not serialize attestation report to YAML.")?
+                ),
+            }
         };
 
         Ok(())
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_report_json() {
+            let mut att_report = AttestationReport::default();
+            att_report.version = 2;
+            att_report.measurement = [0xab; 48];
+            att_report.reported_tcb = TcbVersion::new(3, 0, 8, 115);
+
+            let value = report_json(&att_report);
+            assert_eq!(value["version"], 2);
+            assert_eq!(value["measurement"], "ab".repeat(48));
+            assert_eq!(value["reported_tcb"]["snp"], 8);
+            assert_eq!(value["reported_tcb"]["fmc"], Value::Null);
+            assert_eq!(value["policy"]["debug_allowed"], false);
+
+            // Hex values made of digits are quoted in YAML, so they read back as strings
+            att_report.measurement = [0x11; 48];
+            let value = report_json(&att_report);
+            let yaml = serde_yaml::to_string(&value).unwrap();
+            assert_eq!(serde_yaml::from_str::<Value>(&yaml).unwrap(), value);
+        }
+
+        #[test]
+        fn test_report_field() {
+            let mut att_report = AttestationReport::default();
+            att_report.vmpl = 2;
+            att_report.chip_id = [0xcd; 64];
+            att_report.policy.0 = 0x3_0000;
+            att_report.reported_tcb = TcbVersion::new(3, 0, 8, 115);
+
+            assert_eq!(report_field(&att_report, "vmpl").unwrap(), "2");
+            assert_eq!(
+                report_field(&att_report, "chip_id").unwrap(),
+                "cd".repeat(64)
+            );
+            assert_eq!(report_field(&att_report, "policy").unwrap(), "0x30000");
+            assert_eq!(
+                report_field(&att_report, "reported_tcb").unwrap(),
+                "0x7308000000000003"
+            );
+
+            let err = report_field(&att_report, "nonce").unwrap_err();
+            assert!(err.to_string().contains("measurement"));
+        }
+    }
 }
 
 mod key_display {
@@ -72,3 +288,320 @@ mod key_display {
         Ok(())
     }
 }
+
+mod report_digest {
+    use super::*;
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Path to attestation report to compute the digest of.
+        #[arg(value_name = "att-report-path", required = true)]
+        pub att_report_path: PathBuf,
+    }
+
+    // Print the hex encoded digest of the signed part of the report in console
+    pub fn display_report_digest(args: Args, quiet: bool) -> Result<()> {
+        let att_report = report::read_report(args.att_report_path)
+            .context("Could not open attestation report")?;
+        let digest = report::report_digest(&att_report)?;
+
+        if !quiet {
+            println!("{}", hex::encode(digest));
+        }
+
+        Ok(())
+    }
+}
+
+mod report_diff {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Path to the first attestation report.
+        #[arg(value_name = "att-report-a", required = true)]
+        pub att_report_a: PathBuf,
+
+        /// Path to the second attestation report.
+        #[arg(value_name = "att-report-b", required = true)]
+        pub att_report_b: PathBuf,
+
+        /// Format to display the differences in.
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        pub output: OutputFormat,
+    }
+
+    // Fields that differ between two reports, as (field, a, b), with nested fields such as the
+    // TCB components named like reported_tcb.snp.
+    fn diff_fields(prefix: &str, a: &Value, b: &Value, diff: &mut Vec<(String, Value, Value)>) {
+        match (a, b) {
+            (Value::Object(a), Value::Object(b)) => {
+                for (name, value_a) in a {
+                    let value_b = b.get(name).unwrap_or(&Value::Null);
+                    let field = if prefix.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{prefix}.{name}")
+                    };
+                    diff_fields(&field, value_a, value_b, diff);
+                }
+            }
+            _ if a != b => diff.push((prefix.to_string(), a.clone(), b.clone())),
+            _ => (),
+        }
+    }
+
+    // Print the fields that differ between two reports, failing when there are any
+    pub fn display_report_diff(args: Args, quiet: bool) -> Result<()> {
+        let read = |path: &PathBuf| {
+            report::read_report(path.clone())
+                .with_context(|| format!("Could not open attestation report {}", path.display()))
+        };
+        let report_a = report_display::report_json(&read(&args.att_report_a)?);
+        let report_b = report_display::report_json(&read(&args.att_report_b)?);
+
+        let mut diff = Vec::new();
+        diff_fields("", &report_a, &report_b, &mut diff);
+
+        if !quiet {
+            match args.output {
+                OutputFormat::Text if diff.is_empty() => println!("identical"),
+                OutputFormat::Text => {
+                    for (field, a, b) in &diff {
+                        println!("{field}: {a} -> {b}");
+                    }
+                }
+                OutputFormat::Json | OutputFormat::Yaml => {
+                    let differences: Vec<Value> = diff
+                        .iter()
+                        .map(|(field, a, b)| json!({ "field": field, "a": a, "b": b }))
+                        .collect();
+                    let value = json!({
+                        "identical": diff.is_empty(),
+                        "differences": differences,
+                    });
+                    if args.output == OutputFormat::Yaml {
+                        print!(
+                            "{}",
+                            serde_yaml::to_string(&value)
+                                .context("Could not serialize report differences to YAML.")?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            json::to_json(&value)
+                                .context("Could not serialize report differences to JSON.")?
+                        );
+                    }
+                }
+            }
+        }
+
+        if !diff.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The attestation reports differ in {} fields.",
+                diff.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use sev::firmware::{guest::AttestationReport, host::TcbVersion};
+
+        #[test]
+        fn test_diff_fields() {
+            let att_report = AttestationReport::default();
+            let mut other = att_report;
+            other.measurement = [0xab; 48];
+            other.reported_tcb = TcbVersion::new(0, 0, 8, 0);
+
+            let mut diff = Vec::new();
+            let report = report_display::report_json(&att_report);
+            diff_fields("", &report, &report, &mut diff);
+            assert!(diff.is_empty());
+
+            diff_fields("", &report, &report_display::report_json(&other), &mut diff);
+            let fields: Vec<&str> = diff.iter().map(|(field, _, _)| field.as_str()).collect();
+            assert_eq!(fields, ["measurement", "reported_tcb.snp"]);
+            assert_eq!(diff[1].1, 0);
+            assert_eq!(diff[1].2, 8);
+        }
+    }
+}
+
+mod cert_display {
+    use super::*;
+    use oids::SnpOid;
+
+    use asn1_rs::FromDer;
+    use serde_json::{json, Value};
+    use x509_parser::{
+        certificate::X509Certificate,
+        extensions::X509Extension,
+        objects::{oid2sn, oid_registry},
+    };
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Path of the certificate to display, PEM or DER, or "-" to read it from stdin.
+        #[arg(value_name = "cert-path", required = true)]
+        pub cert_path: PathBuf,
+
+        /// Also display the SNP extensions with their decoded values, and the other extensions.
+        #[arg(long)]
+        pub oids: bool,
+
+        /// Format to display the certificate in.
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        pub output: OutputFormat,
+    }
+
+    // Value of an extension: integers as numbers, ASCII strings as text, and the bytes of octet
+    // strings, or of any other encoding such as the raw hardware ID of legacy VCEKs, as hex.
+    fn extension_value(ext: &X509Extension) -> Value {
+        match ext.value {
+            [0x2, len, bytes @ ..] if bytes.len() == *len as usize && bytes.len() <= 8 => bytes
+                .iter()
+                .fold(0u64, |value, byte| value << 8 | u64::from(*byte))
+                .into(),
+            [0x4, len, bytes @ ..] if bytes.len() == *len as usize => hex::encode(bytes).into(),
+            // IA5String, such as the product name of a VCEK
+            [0x16, len, bytes @ ..] if bytes.len() == *len as usize && bytes.is_ascii() => {
+                String::from_utf8_lossy(bytes).into()
+            }
+            raw => hex::encode(raw).into(),
+        }
+    }
+
+    // Fields of a certificate, with its SNP and other extensions when asked for.
+    fn cert_json(cert: &X509Certificate, oids: bool) -> Value {
+        let validity = cert.validity();
+        let mut value = json!({
+            "subject": cert.subject().to_string(),
+            "issuer": cert.issuer().to_string(),
+            "serial": hex::encode(cert.raw_serial()),
+            "not_before": validity.not_before.to_string(),
+            "not_after": validity.not_after.to_string(),
+        });
+        if !oids {
+            return value;
+        }
+
+        let (mut snp_oids, mut other_oids) = (Vec::new(), Vec::new());
+        for ext in cert.extensions() {
+            let oid = ext.oid.to_id_string();
+            match SnpOid::from_oid(&ext.oid) {
+                Some(snp_oid) => snp_oids.push(json!({
+                    "oid": oid,
+                    "name": snp_oid.name(),
+                    "value": extension_value(ext),
+                })),
+                None => other_oids.push(json!({
+                    "oid": oid,
+                    "name": oid2sn(&ext.oid, oid_registry()).ok(),
+                    "value": extension_value(ext),
+                })),
+            }
+        }
+        value["snp_oids"] = snp_oids.into();
+        value["other_oids"] = other_oids.into();
+
+        value
+    }
+
+    fn print_extensions(title: &str, extensions: &Value) {
+        println!("{title}:");
+        let extensions = extensions.as_array().map(Vec::as_slice).unwrap_or_default();
+        if extensions.is_empty() {
+            println!("  none");
+        }
+        for ext in extensions {
+            let value = match &ext["value"] {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            match ext["name"].as_str() {
+                Some(name) => println!(
+                    "  {name} ({}): {value}",
+                    ext["oid"].as_str().unwrap_or_default()
+                ),
+                None => println!("  {}: {value}", ext["oid"].as_str().unwrap_or_default()),
+            }
+        }
+    }
+
+    // Print a certificate in console, along with its extensions when asked for
+    pub fn display_cert(args: Args, quiet: bool) -> Result<()> {
+        let cert = certs::convert_path_to_cert(&args.cert_path, "cert")?;
+        let der = cert
+            .to_der()
+            .context("Could not convert certificate to der.")?;
+        let (_, x509) =
+            X509Certificate::from_der(&der).context("Could not create X509Certificate from der")?;
+        let value = cert_json(&x509, args.oids);
+
+        if quiet {
+            return Ok(());
+        }
+        match args.output {
+            OutputFormat::Text => {
+                for (field, name) in [
+                    ("subject", "Subject"),
+                    ("issuer", "Issuer"),
+                    ("serial", "Serial"),
+                    ("not_before", "Not Before"),
+                    ("not_after", "Not After"),
+                ] {
+                    println!("{name}: {}", value[field].as_str().unwrap_or_default());
+                }
+                if args.oids {
+                    print_extensions("SNP extensions", &value["snp_oids"]);
+                    print_extensions("Other extensions", &value["other_oids"]);
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json::to_json(&value).context("Could not serialize certificate to JSON.")?
+            ),
+            OutputFormat::Yaml => print!(
+                "{}",
+                serde_yaml::to_string(&value)
+                    .context("Could not serialize certificate to YAML.")?
+            ),
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use sev::certs::snp::builtin;
+
+        #[test]
+        fn test_cert_json() {
+            let der = certs::cert_from_bytes(builtin::milan::ASK)
+                .unwrap()
+                .to_der()
+                .unwrap();
+            let (_, ask) = X509Certificate::from_der(&der).unwrap();
+
+            let value = cert_json(&ask, false);
+            assert!(value["subject"].as_str().unwrap().contains("SEV-Milan"));
+            assert!(value.get("snp_oids").is_none());
+
+            // The ASK carries none of the SNP extensions of a VEK
+            let value = cert_json(&ask, true);
+            assert_eq!(value["snp_oids"], json!([]));
+            let other_oids = value["other_oids"].as_array().unwrap();
+            assert!(other_oids
+                .iter()
+                .any(|ext| ext["name"] == "keyUsage" && ext["oid"] == "2.5.29.15"));
+        }
+    }
+}
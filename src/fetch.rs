@@ -5,13 +5,42 @@ use super::*;
 
 use core::fmt;
 
-use std::{fs, path::PathBuf, str::FromStr};
+use clap::builder::{PossibleValue, TypedValueParser, ValueParserFactory};
 
-use reqwest::blocking::{get, Response};
+use std::{
+    env, fs,
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
-use sev::firmware::host::CertType;
+use openssl::{
+    asn1::Asn1Time,
+    x509::{CrlStatus, X509Crl, X509},
+};
 
-use certs::{write_cert, CertFormat};
+use reqwest::{
+    blocking::{Client, Response},
+    NoProxy, Proxy, StatusCode, Url,
+};
+
+use sev::firmware::{
+    guest::AttestationReport,
+    host::{CertType, TcbVersion},
+};
+
+use certs::{
+    cert_path, check_overwrite, read_valid_cert_file, resolve_cert_format, write_cert, CertFormat,
+};
+
+use log::{debug, trace};
+use oids::SnpOid;
+use serde::Serialize;
+
+pub use cert_authority::fetch_ca_chain;
+pub use vcek::fetch_report_vcek;
 
 #[derive(Subcommand)]
 pub enum FetchCmd {
@@ -20,6 +49,9 @@ pub enum FetchCmd {
 
     /// Fetch the VCEK from the KDS.
     Vcek(vcek::Args),
+
+    /// Fetch the VCEKs for a directory of attestation reports from the KDS.
+    VcekBatch(vcek_batch::Args),
 }
 
 #[derive(ValueEnum, Debug, Clone, PartialEq, Eq)]
@@ -51,41 +83,317 @@ impl FromStr for Endorsement {
         }
     }
 }
-#[derive(ValueEnum, Debug, Clone)]
+// Parsed with FromStr rather than ValueEnum, so arguments accept the aliases of each model.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProcType {
-    /// 3rd Gen AMD EPYC Processor (Standard)
     Milan,
-
-    /// 4th Gen AMD EPYC Processor (Standard)
     Genoa,
-
-    /// 4th Gen AMD EPYC Processor (Performance)
     Bergamo,
-
-    /// 4th Gen AMD EPYC Processor (Edge)
     Siena,
+    Turin,
+}
+
+// TCB components of a report, decoded according to the layout of the processor generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Tcb {
+    pub fmc: Option<u8>,
+    pub bootloader: u8,
+    pub tee: u8,
+    pub snp: u8,
+    pub microcode: u8,
+}
+
+impl Tcb {
+    // Value of a TCB component, or None for the FMC of generations without one.
+    pub fn get(&self, component: SnpOid) -> Option<u8> {
+        match component {
+            SnpOid::Fmc => self.fmc,
+            SnpOid::BootLoader => Some(self.bootloader),
+            SnpOid::Tee => Some(self.tee),
+            SnpOid::Snp => Some(self.snp),
+            SnpOid::Ucode => Some(self.microcode),
+            SnpOid::HwId => None,
+        }
+    }
+
+    // Components of the TCB along with their values, in layout order, skipping the FMC when
+    // absent.
+    pub fn values(&self) -> Vec<(SnpOid, u8)> {
+        SnpOid::TCB
+            .into_iter()
+            .filter_map(|component| Some((component, self.get(component)?)))
+            .collect()
+    }
+
+    // Named components of the TCB, in layout order, skipping the FMC when absent.
+    pub fn components(&self) -> Vec<(&'static str, u8)> {
+        self.values()
+            .into_iter()
+            .map(|(component, value)| (component.name(), value))
+            .collect()
+    }
+
+    // Each component of the TCB next to the one of the current TCB, and how they relate.
+    pub fn compare(&self, current: &Tcb) -> Vec<(&'static str, u8, u8, TcbRelation)> {
+        self.components()
+            .into_iter()
+            .zip(current.components())
+            .map(|((name, reported), (_, current))| {
+                (name, reported, current, TcbRelation::of(reported, current))
+            })
+            .collect()
+    }
+}
+
+// Canonical form of a TCB, such as "bootloader=3,tee=0,snp=8,microcode=115", in the format
+// --min-tcb takes.
+impl fmt::Display for Tcb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let components: Vec<String> = self
+            .values()
+            .into_iter()
+            .map(|(component, value)| format!("{}={value}", component.key()))
+            .collect();
+
+        write!(f, "{}", components.join(","))
+    }
+}
+
+// A TCB is below another when none of its components is newer and at least one is older.
+// TCBs of different layouts, or with components newer and older than the other, are unordered.
+impl PartialOrd for Tcb {
+    fn partial_cmp(&self, other: &Tcb) -> Option<std::cmp::Ordering> {
+        if self.fmc.is_some() != other.fmc.is_some() {
+            return None;
+        }
+
+        let mut ordering = std::cmp::Ordering::Equal;
+        for ((_, value), (_, other_value)) in self.values().into_iter().zip(other.values()) {
+            match (ordering, value.cmp(&other_value)) {
+                (_, std::cmp::Ordering::Equal) => (),
+                (std::cmp::Ordering::Equal, component) => ordering = component,
+                (ordering, component) if ordering == component => (),
+                _ => return None,
+            }
+        }
+
+        Some(ordering)
+    }
+}
+
+// How the reported TCB of a report relates to the current TCB of the platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcbRelation {
+    Equal,
+    // The reported TCB is older than the current TCB, as in a stale or replayed report, or
+    // one taken before a firmware update was committed. --no-downgrade rejects it.
+    Downgrade,
+    // The reported TCB is newer than the current TCB, which the firmware never reports.
+    ReportedNewer,
+}
+
+impl TcbRelation {
+    pub fn of(reported: u8, current: u8) -> TcbRelation {
+        match current.cmp(&reported) {
+            std::cmp::Ordering::Equal => TcbRelation::Equal,
+            std::cmp::Ordering::Greater => TcbRelation::Downgrade,
+            std::cmp::Ordering::Less => TcbRelation::ReportedNewer,
+        }
+    }
+}
+
+impl fmt::Display for TcbRelation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TcbRelation::Equal => write!(f, "equal"),
+            TcbRelation::Downgrade => write!(f, "downgrade"),
+            TcbRelation::ReportedNewer => write!(f, "reported newer"),
+        }
+    }
 }
 
 impl ProcType {
-    fn to_kds_url(&self) -> String {
+    pub const ALL: [ProcType; 5] = [
+        ProcType::Milan,
+        ProcType::Genoa,
+        ProcType::Bergamo,
+        ProcType::Siena,
+        ProcType::Turin,
+    ];
+
+    // Name of the model on the command line.
+    pub fn name(&self) -> &'static str {
         match self {
-            ProcType::Genoa | ProcType::Siena | ProcType::Bergamo => &ProcType::Genoa,
-            _ => self,
+            ProcType::Milan => "milan",
+            ProcType::Genoa => "genoa",
+            ProcType::Bergamo => "bergamo",
+            ProcType::Siena => "siena",
+            ProcType::Turin => "turin",
+        }
+    }
+
+    // Description of the model in the help.
+    fn description(&self) -> &'static str {
+        match self {
+            ProcType::Milan => "3rd Gen AMD EPYC Processor (Standard)",
+            ProcType::Genoa => "4th Gen AMD EPYC Processor (Standard)",
+            ProcType::Bergamo => "4th Gen AMD EPYC Processor (Performance)",
+            ProcType::Siena => "4th Gen AMD EPYC Processor (Edge)",
+            ProcType::Turin => "5th Gen AMD EPYC Processor (Standard)",
+        }
+    }
+
+    // Product name of the generation in KDS URLs, in the casing the KDS expects. Siena and
+    // Bergamo parts are endorsed under Genoa.
+    pub fn kds_path(&self) -> &'static str {
+        match self {
+            ProcType::Milan => "Milan",
+            ProcType::Genoa | ProcType::Siena | ProcType::Bergamo => "Genoa",
+            ProcType::Turin => "Turin",
+        }
+    }
+
+    // Whether the generation reports a firmware mask code (FMC) TCB component.
+    pub fn has_fmc(&self) -> bool {
+        matches!(self, ProcType::Turin)
+    }
+
+    // Length of the chip ID the generation reports, which is also the hardware ID of its
+    // VCEK. Turin only uses the first 8 bytes of the chip ID field.
+    pub fn chip_id_len(&self) -> usize {
+        match self {
+            ProcType::Turin => 8,
+            _ => 64,
+        }
+    }
+
+    // Earliest attestation report version produced by the generation.
+    pub fn min_report_version(&self) -> u32 {
+        match self {
+            ProcType::Turin => 3,
+            _ => 2,
+        }
+    }
+
+    // Decode a TCB version according to the layout used by the generation.
+    pub fn decode_tcb(&self, tcb: &TcbVersion) -> Result<Tcb> {
+        let raw: Vec<u8> = bincode::serialize(tcb).context("Could not serialize TCB version.")?;
+
+        if self.has_fmc() {
+            // FMC, Boot Loader, TEE, SNP, 3 reserved bytes, Microcode
+            if raw[4..7].iter().any(|b| *b != 0) {
+                return Err(anyhow::anyhow!(
+                    "The TCB version has reserved bytes set that {self} does not define."
+                ));
+            }
+            Ok(Tcb {
+                fmc: Some(raw[0]),
+                bootloader: raw[1],
+                tee: raw[2],
+                snp: raw[3],
+                microcode: raw[7],
+            })
+        } else {
+            // Boot Loader, TEE, 4 reserved bytes, SNP, Microcode
+            if raw[2..6].iter().any(|b| *b != 0) {
+                return Err(anyhow::anyhow!(
+                    "The TCB version carries components (such as an FMC) that {self} does not define."
+                ));
+            }
+            Ok(Tcb {
+                fmc: None,
+                bootloader: tcb.bootloader,
+                tee: tcb.tee,
+                snp: tcb.snp,
+                microcode: tcb.microcode,
+            })
         }
-        .to_string()
     }
 }
 
+// Spellings of each processor model besides its name: the X (3D V-Cache) parts, the EPYC
+// series and the core generation, where only one model has it.
+const PROC_TYPE_ALIASES: [(&str, ProcType); 14] = [
+    ("milanx", ProcType::Milan),
+    ("7003", ProcType::Milan),
+    ("7003x", ProcType::Milan),
+    ("zen3", ProcType::Milan),
+    ("genoax", ProcType::Genoa),
+    ("9004", ProcType::Genoa),
+    ("9004x", ProcType::Genoa),
+    ("zen4", ProcType::Genoa),
+    ("97x4", ProcType::Bergamo),
+    ("8004", ProcType::Siena),
+    ("turindense", ProcType::Turin),
+    ("9005", ProcType::Turin),
+    ("zen5", ProcType::Turin),
+    ("zen5c", ProcType::Turin),
+];
+
+// Case insensitive, ignoring an AMD EPYC prefix and any spaces, dashes or underscores, so
+// "Genoa", "GENOA", "Milan-X" and "AMD EPYC 9004" are all accepted.
 impl FromStr for ProcType {
     type Err = anyhow::Error;
     fn from_str(input: &str) -> Result<ProcType, anyhow::Error> {
-        match input.to_lowercase().as_str() {
-            "milan" => Ok(ProcType::Milan),
-            "genoa" => Ok(ProcType::Genoa),
-            "bergamo" => Ok(ProcType::Bergamo),
-            "siena" => Ok(ProcType::Siena),
-            _ => Err(anyhow::anyhow!("Processor type not found!")),
-        }
+        let normalized: String = input
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .collect::<String>()
+            .to_lowercase();
+        let normalized = normalized.strip_prefix("amd").unwrap_or(&normalized);
+        let normalized = normalized.strip_prefix("epyc").unwrap_or(normalized);
+
+        ProcType::ALL
+            .iter()
+            .map(|proc_type| (proc_type.name(), proc_type))
+            .chain(PROC_TYPE_ALIASES.iter().map(|(alias, proc_type)| (*alias, proc_type)))
+            .find(|(name, _)| *name == normalized)
+            .map(|(_, proc_type)| proc_type.clone())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown processor model {input}. Valid values are milan, genoa, bergamo, siena and turin, or an EPYC series such as 9004."
+                )
+            })
+    }
+}
+
+// Parse a processor model argument with FromStr, so the aliases are accepted, while still
+// listing the models in the help.
+#[derive(Clone)]
+pub struct ProcTypeParser;
+
+impl TypedValueParser for ProcTypeParser {
+    type Value = ProcType;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<ProcType, clap::Error> {
+        let arg = arg.map_or_else(|| "...".to_string(), ToString::to_string);
+        let value = value.to_string_lossy();
+        value.parse().map_err(|err| {
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidValue,
+                format!("invalid value '{value}' for '{arg}': {err}\n"),
+            )
+            .with_cmd(cmd)
+        })
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(ProcType::ALL.iter().map(|proc_type| {
+            PossibleValue::new(proc_type.name()).help(proc_type.description())
+        })))
+    }
+}
+
+impl ValueParserFactory for ProcType {
+    type Parser = ProcTypeParser;
+
+    fn value_parser() -> ProcTypeParser {
+        ProcTypeParser
     }
 }
 
@@ -96,206 +404,1652 @@ impl fmt::Display for ProcType {
             ProcType::Genoa => write!(f, "Genoa"),
             ProcType::Bergamo => write!(f, "Bergamo"),
             ProcType::Siena => write!(f, "Siena"),
+            ProcType::Turin => write!(f, "Turin"),
         }
     }
 }
 
-pub fn cmd(cmd: FetchCmd) -> Result<()> {
+pub fn cmd(
+    cmd: FetchCmd,
+    cert_format: Option<CertFormat>,
+    timeout: u64,
+    quiet: bool,
+) -> Result<()> {
     match cmd {
-        FetchCmd::CA(args) => cert_authority::fetch_ca(args),
-        FetchCmd::Vcek(args) => vcek::fetch_vcek(args),
+        FetchCmd::CA(mut args) => {
+            args.kds.timeout = timeout;
+            cert_authority::fetch_ca(args, cert_format, quiet)
+        }
+        FetchCmd::Vcek(mut args) => {
+            args.kds.timeout = timeout;
+            vcek::fetch_vcek(args, cert_format)
+        }
+        FetchCmd::VcekBatch(mut args) => {
+            args.kds.timeout = timeout;
+            vcek_batch::fetch_vcek_batch(args, cert_format, quiet)
+        }
+    }
+}
+
+const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
+
+// Seconds to wait for a network request when --timeout isn't given.
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+#[derive(Parser)]
+pub struct KdsArgs {
+    /// Base URL of the KDS, or of a mirror of it.
+    #[arg(long, value_name = "kds-url", value_parser = parse_kds_url, default_value = KDS_CERT_SITE)]
+    pub kds_url: String,
+
+    /// Proxy to send KDS requests through, overriding the HTTPS_PROXY environment variable.
+    #[arg(long, value_name = "proxy", value_parser = parse_proxy_url)]
+    pub proxy: Option<String>,
+
+    // Seconds to wait to connect to and hear back from the KDS, set from the global --timeout.
+    #[arg(skip = DEFAULT_TIMEOUT)]
+    pub timeout: u64,
+
+    /// Times to retry a KDS request that was rate limited or hit a server error.
+    #[arg(long, value_name = "max-retries", default_value_t = 3)]
+    pub max_retries: u32,
+
+    /// Delay before the first retry in milliseconds, doubling with every retry.
+    #[arg(long, value_name = "retry-base-ms", default_value_t = 500)]
+    pub retry_base_ms: u64,
+
+    /// Don't show download progress on stderr, even when it is a terminal.
+    #[arg(long)]
+    pub no_progress: bool,
+}
+
+// Whether a KDS response is worth retrying: rate limiting or a server error.
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Proxy a request to the URL goes through, with the environment read through var.
+fn proxy_for_env(
+    proxy: Option<&str>,
+    url: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_string();
+    let no_proxy = var("NO_PROXY")
+        .or_else(|| var("no_proxy"))
+        .unwrap_or_default();
+    if no_proxy
+        .split(',')
+        .any(|entry| no_proxy_matches(entry.trim(), &host))
+    {
+        return None;
+    }
+    if let Some(proxy) = proxy {
+        return Some(proxy.to_string());
+    }
+
+    let vars: &[&str] = if url.starts_with("https:") {
+        &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+    } else {
+        &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+    };
+    vars.iter()
+        .filter_map(|name| var(name))
+        .find(|proxy| !proxy.is_empty())
+}
+
+// Whether a NO_PROXY entry covers a host, the way the HTTP client matches them: "*" covers
+// every host, an IP address only itself, and a domain itself and its subdomains.
+fn no_proxy_matches(entry: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches([']', '.']);
+    if entry.is_empty() {
+        return false;
+    }
+    if entry == "*" {
+        return true;
+    }
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return entry.trim_start_matches('[').trim_end_matches(']') == host;
     }
+
+    let domain = entry.trim_start_matches('.');
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+// Longest delay before a retry, however long the KDS asks to wait.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// Delay before a retry, taken from a Retry-After header in seconds when the KDS sends one,
+// and otherwise backing off exponentially from the base delay. Either is capped at
+// MAX_RETRY_DELAY.
+fn retry_delay(attempt: u32, base_ms: u64, retry_after: Option<&str>) -> Duration {
+    let delay = match retry_after.and_then(|value| value.trim().parse::<u64>().ok()) {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => Duration::from_millis(base_ms.saturating_mul(2u64.saturating_pow(attempt))),
+    };
+
+    delay.min(MAX_RETRY_DELAY)
+}
+
+// Progress line for a download, with the total size when the KDS sent a Content-Length.
+fn progress_line(url: &str, downloaded: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) => format!("{url}: {downloaded}/{total} bytes"),
+        None => format!("{url}: {downloaded} bytes"),
+    }
+}
+
+// Pick the encoding of fetched certificates, which must be given ahead of the processor model
+// or with the global --cert-format.
+pub fn require_encoding(
+    encoding: Option<CertFormat>,
+    cert_format: Option<CertFormat>,
+) -> Result<CertFormat> {
+    if encoding.is_none() && cert_format.is_none() {
+        return Err(anyhow::anyhow!(
+            "No encoding given. Give it ahead of the processor model, or with --cert-format."
+        ));
+    }
+
+    resolve_cert_format(encoding, cert_format)
+}
+
+// Create the directory fetched certificates are written to, if missing.
+fn create_output_dir(output_dir: &Path) -> Result<()> {
+    fs::create_dir_all(output_dir).context("Could not create certs folder")
+}
+
+// Check that a KDS base URL is a plain http(s) URL, and drop any trailing slash.
+fn parse_kds_url(input: &str) -> Result<String> {
+    let url = reqwest::Url::parse(input).context("KDS URL is not a valid URL")?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(anyhow::anyhow!(
+            "KDS URL must be an http or https URL with a host."
+        ));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(anyhow::anyhow!(
+            "KDS URL must not have a query or fragment."
+        ));
+    }
+
+    Ok(input.trim_end_matches('/').to_string())
+}
+
+// Check that a proxy URL can be used by the HTTP client.
+fn parse_proxy_url(input: &str) -> Result<String> {
+    reqwest::Proxy::all(input).context("Proxy is not a valid URL")?;
+
+    Ok(input.to_string())
+}
+
+impl KdsArgs {
+    // Full URL of a path on the KDS.
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.kds_url)
+    }
+
+    // Proxy requests to the URL go through, if any. Without --proxy the HTTP client picks
+    // the proxy up from the environment. Either way, hosts listed in NO_PROXY go direct.
+    fn proxy_for(&self, url: &str) -> Option<String> {
+        proxy_for_env(self.proxy.as_deref(), url, |var| env::var(var).ok())
+    }
+
+    // Send a GET request for a path on the KDS, retrying responses that are rate limited or
+    // server errors. The last response is returned once the retries run out.
+    fn get(&self, path: &str) -> Result<Response> {
+        let url = self.url(path);
+
+        let timeout = Duration::from_secs(self.timeout);
+        let mut client = Client::builder().connect_timeout(timeout).timeout(timeout);
+        if let Some(proxy) = &self.proxy {
+            client = client.proxy(Proxy::all(proxy)?.no_proxy(NoProxy::from_env()));
+        }
+        let client = client.build().context("Could not build HTTP client")?;
+
+        let mut attempt = 0;
+        loop {
+            debug!("Requesting {url}");
+            let start = Instant::now();
+            let rsp = client.get(&url).send().map_err(|err| {
+                let reason = if err.is_timeout() {
+                    format!(
+                        "No response from {url} after {:.1} seconds",
+                        start.elapsed().as_secs_f64()
+                    )
+                } else if err.is_connect() {
+                    match self.proxy_for(&url) {
+                        Some(proxy) => format!("Proxy {proxy} is unreachable"),
+                        None => format!("KDS at {} is unreachable", self.kds_url),
+                    }
+                } else {
+                    format!("Request to {url} failed")
+                };
+                anyhow::Error::new(err).context(reason)
+            })?;
+            debug!("{url} returned {}", rsp.status());
+
+            if attempt >= self.max_retries || !is_retryable(rsp.status()) {
+                return Ok(rsp);
+            }
+
+            let retry_after = rsp
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok());
+            let delay = retry_delay(attempt, self.retry_base_ms, retry_after);
+            debug!("Retrying {url} in {} ms", delay.as_millis());
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    // Read the body of a KDS response. While reading, the bytes downloaded so far are shown
+    // on stderr if it is a terminal, independent of --quiet.
+    fn body(&self, mut rsp: Response) -> Result<Vec<u8>> {
+        let url = rsp.url().to_string();
+        if self.no_progress || !std::io::stderr().is_terminal() {
+            return Ok(rsp
+                .bytes()
+                .with_context(|| format!("Could not read the response from {url}"))?
+                .to_vec());
+        }
+
+        let total = rsp.content_length();
+        let mut body = Vec::new();
+        let mut chunk = [0u8; 8192];
+        let mut stderr = std::io::stderr();
+
+        loop {
+            let read = rsp
+                .read(&mut chunk)
+                .with_context(|| format!("Could not read the response from {url}"))?;
+            if read == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..read]);
+            trace!("Read {read} bytes from {url}");
+            write!(
+                stderr,
+                "\r{}",
+                progress_line(&url, body.len() as u64, total)
+            )?;
+        }
+        if !body.is_empty() {
+            writeln!(stderr)?;
+        }
+
+        Ok(body)
+    }
+}
+
+#[derive(Parser)]
+pub struct CacheArgs {
+    /// Directory to cache certificates fetched from the KDS in [default: $XDG_CACHE_HOME/snpguest].
+    #[arg(long, value_name = "cache-dir")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Always request the certificates from the KDS, without reading or updating the cache.
+    #[arg(long, conflicts_with = "cache_dir")]
+    pub no_cache: bool,
+}
+
+impl CacheArgs {
+    // Directory of the cache, or None if caching is disabled or there is no place for it.
+    fn dir(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+
+        self.cache_dir.clone().or_else(|| {
+            env::var_os("XDG_CACHE_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                .map(|dir| dir.join("snpguest"))
+        })
+    }
+}
+
+// KDS URLs of the certificates that verify a report: its VCEK, which is the only VEK the KDS
+// serves, and the CA chain of the key it was signed with.
+pub fn report_urls(
+    kds: &KdsArgs,
+    processor_model: &ProcType,
+    att_report: &AttestationReport,
+) -> Result<Vec<(&'static str, String)>> {
+    let endorser = report::get_signing_key(att_report)?.unwrap_or(Endorsement::Vcek);
+    let mut urls = Vec::new();
+    if endorser == Endorsement::Vcek {
+        let base = Url::parse(&kds.kds_url).context("KDS URL is not a valid URL")?;
+        urls.push((
+            "VCEK",
+            vcek::vcek_url_for_report(att_report, processor_model.clone(), &base)?.to_string(),
+        ));
+    }
+    urls.push((
+        match endorser {
+            Endorsement::Vcek => "ARK and ASK",
+            Endorsement::Vlek => "ARK and ASVK",
+        },
+        kds.url(&cert_authority::ca_path(processor_model, &endorser)),
+    ));
+
+    Ok(urls)
+}
+
+// Whether any of the cached certificates has expired or been revoked by the KDS CRL.
+fn is_stale(certs: &[X509], crl: &X509Crl) -> Result<bool> {
+    let now = Asn1Time::days_from_now(0)?;
+
+    Ok(certs.iter().any(|cert| {
+        cert.not_after() < now || !matches!(crl.get_by_cert(cert), CrlStatus::NotRevoked)
+    }))
+}
+
+// Write a cache entry through a temporary file, so concurrent fetches never read a partial one.
+fn write_cache(cache_path: &Path, bytes: &[u8]) -> Result<()> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent).context("Could not create cache directory")?;
+    }
+    let tmp_path = cache_path.with_extension(format!(
+        "tmp-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, cache_path)?;
+
+    Ok(())
+}
+
+// Reuse a KDS response from the cache if its certificates are still good, otherwise request
+// it again and update the cache. The CRL is given the cached certificates, and a cached entry
+// that can't be checked against it is treated as stale.
+fn fetch_cached(
+    cache_path: Option<PathBuf>,
+    parse: fn(&[u8]) -> Result<Vec<X509>, openssl::error::ErrorStack>,
+    crl: impl FnOnce(&[X509]) -> Result<X509Crl>,
+    request: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    let Some(cache_path) = cache_path else {
+        return request();
+    };
+
+    if let Ok(cached) = fs::read(&cache_path) {
+        match parse(&cached) {
+            Ok(certs) if !certs.is_empty() => {
+                match crl(&certs).and_then(|crl| is_stale(&certs, &crl)) {
+                    Ok(false) => {
+                        debug!("Using cached {}", cache_path.display());
+                        return Ok(cached);
+                    }
+                    Ok(true) => debug!("Cached {} is stale", cache_path.display()),
+                    Err(err) => debug!(
+                        "Could not check cached {} against the CRL, requesting it again: {err:#}",
+                        cache_path.display()
+                    ),
+                }
+            }
+            _ => debug!("Cached {} is unusable", cache_path.display()),
+        }
+    }
+
+    let bytes = request()?;
+    write_cache(&cache_path, &bytes).context("Could not write certificate to cache")?;
+    debug!("Cached {}", cache_path.display());
+
+    Ok(bytes)
+}
+
+// Whether a CRL was issued and signed by the ARK.
+fn crl_signed_by(crl: &X509Crl, ark: &X509) -> Result<bool> {
+    Ok(
+        crl.issuer_name().try_cmp(ark.subject_name())? == std::cmp::Ordering::Equal
+            && crl.verify(ark.public_key()?.as_ref())?,
+    )
+}
+
+// Request the CRL of a processor model from the KDS, reusing a cached copy until its next update.
+// Either must be signed by the ARK.
+fn request_crl_kds(
+    kds: &KdsArgs,
+    processor_model: &ProcType,
+    endorser: &Endorsement,
+    ark: &X509,
+    cache_dir: Option<&Path>,
+) -> Result<X509Crl> {
+    let endorser = endorser.to_string().to_lowercase();
+    let cache_path = cache_dir.map(|dir| {
+        dir.join(processor_model.kds_path())
+            .join(format!("{endorser}-crl.der"))
+    });
+
+    if let Some(cached) = cache_path.as_ref().and_then(|path| fs::read(path).ok()) {
+        if let Ok(crl) = X509Crl::from_der(&cached) {
+            let now = Asn1Time::days_from_now(0)?;
+            if crl.next_update().is_some_and(|next| next > now) && crl_signed_by(&crl, ark)? {
+                debug!("Using cached {endorser} CRL");
+                return Ok(crl);
+            }
+        }
+    }
+
+    // Should make -> https://kdsintf.amd.com/vcek/v1/{SEV_PROD_NAME}/crl
+    let path = format!("/{endorser}/v1/{}/crl", processor_model.kds_path());
+    let rsp: Response = kds
+        .get(&path)
+        .context("Unable to send request for CRL to URL")?;
+    let body = match rsp.status() {
+        StatusCode::OK => kds.body(rsp).context("Unable to parse AMD CRL")?,
+        status => {
+            return Err(anyhow::anyhow!(
+                "Unable to fetch CRL, KDS returned {status}"
+            ))
+        }
+    };
+    let crl = X509Crl::from_der(&body).context("Unable to parse AMD CRL")?;
+    if !crl_signed_by(&crl, ark)? {
+        return Err(anyhow::anyhow!(
+            "The {endorser} CRL from the KDS was not signed by the AMD ARK."
+        ));
+    }
+
+    if let Some(cache_path) = cache_path {
+        write_cache(&cache_path, &body).context("Could not write CRL to cache")?;
+    }
+
+    Ok(crl)
 }
 
 mod cert_authority {
     use super::*;
-    use openssl::x509::X509;
-    use reqwest::StatusCode;
 
     #[derive(Parser)]
+    #[command(allow_missing_positional = true)]
     pub struct Args {
-        /// Specify encoding to use for certificates.
-        #[arg(value_name = "encoding", required = true)]
-        pub encoding: CertFormat,
+        /// Specify encoding to use for certificates. Can be left out when the global
+        /// --cert-format is given.
+        #[arg(value_name = "encoding")]
+        pub encoding: Option<CertFormat>,
 
         /// Specify the processor model for the certificate chain.
         #[arg(value_name = "processor-model", required = true)]
         pub processor_model: ProcType,
 
-        /// Directory to store the certificates in.
-        #[arg(value_name = "certs-dir", required = true)]
-        pub certs_dir: PathBuf,
+        /// Directory to store the certificates in, created if missing. Defaults to
+        /// $SNPGUEST_CERTS_DIR.
+        #[arg(long, value_name = "output-dir")]
+        pub output_dir: Option<PathBuf>,
+
+        /// Overwrite certificates already in the output directory. Otherwise the fetch is
+        /// skipped when they are all present and valid.
+        #[arg(long)]
+        pub force: bool,
 
         /// Specify which endorsement certificate chain to pull, either VCEK or VLEK.
-        #[arg(short, long, value_name = "endorser", default_value_t = Endorsement::Vcek)]
+        #[arg(short, long, value_name = "endorser", default_value = "vcek")]
         pub endorser: Endorsement,
+
+        /// Print the KDS URL that would be requested instead of fetching anything.
+        #[arg(long)]
+        pub dry_run: bool,
+
+        #[command(flatten)]
+        pub kds: KdsArgs,
+
+        #[command(flatten)]
+        pub cache: CacheArgs,
     }
 
-    // Function to build kds request for ca chain and return a vector with the 2 certs (ASK & ARK)
-    pub fn request_ca_kds(
-        processor_model: ProcType,
-        endorser: &Endorsement,
-    ) -> Result<Vec<X509>, anyhow::Error> {
-        const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
+    // Path of the ca chain on the KDS.
+    pub fn ca_path(processor_model: &ProcType, endorser: &Endorsement) -> String {
         const KDS_CERT_CHAIN: &str = "cert_chain";
 
         // Should make -> https://kdsintf.amd.com/vcek/v1/{SEV_PROD_NAME}/cert_chain
-        let url: String = format!(
-            "{KDS_CERT_SITE}/{}/v1/{}/{KDS_CERT_CHAIN}",
+        format!(
+            "/{}/v1/{}/{KDS_CERT_CHAIN}",
             endorser.to_string().to_lowercase(),
-            processor_model.to_kds_url()
-        );
+            processor_model.kds_path()
+        )
+    }
 
-        let rsp: Response = get(url).context("Unable to send request for certs to URL")?;
+    // Function to build kds request for ca chain and return the PEM body with the 2 certs (ASK & ARK)
+    fn request_ca_kds(
+        kds: &KdsArgs,
+        processor_model: &ProcType,
+        endorser: &Endorsement,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let path = ca_path(processor_model, endorser);
+
+        let rsp: Response = kds
+            .get(&path)
+            .context("Unable to send request for certs to URL")?;
 
         match rsp.status() {
             StatusCode::OK => {
                 // Parse the request
-                let body = rsp
-                    .bytes()
-                    .context("Unable to parse AMD certificate chain")?
-                    .to_vec();
-
-                let certificates = X509::stack_from_pem(&body)?;
+                let body = kds
+                    .body(rsp)
+                    .context("Unable to parse AMD certificate chain")?;
 
-                Ok(certificates)
+                Ok(body)
             }
-            status => Err(anyhow::anyhow!("Unable to fetch certificate: {:?}", status)),
+            status => Err(anyhow::anyhow!(
+                "Unable to fetch certificate, KDS returned {status}"
+            )),
+        }
+    }
+
+    // Whether the ARK of a chain, held after the ASK (or ASVK), is the AMD ARK bundled with
+    // snpguest for the processor model.
+    fn is_bundled_ark(certs: &[X509], processor_model: &ProcType) -> Result<bool> {
+        let [_, ark, ..] = certs else {
+            return Err(anyhow::anyhow!("The chain does not hold the ARK."));
+        };
+        let model = verify::bundled_ark_model(&sev::certs::snp::Certificate::from(ark.clone()))?;
+
+        Ok(model == Some(processor_model.kds_path()))
+    }
+
+    // Request the ca chain from the cache or the kds, returned as the ARK and the ASK (or ASVK).
+    // A mirror or the cache could hold any chain, so its ARK must be the one bundled with
+    // snpguest. Models without one are only fetched from the AMD KDS, and never cached.
+    pub fn fetch_ca_chain(
+        kds: &KdsArgs,
+        cache: &CacheArgs,
+        processor_model: &ProcType,
+        endorser: &Endorsement,
+    ) -> Result<(X509, X509)> {
+        let pinned = !matches!(processor_model, ProcType::Turin);
+        if !pinned && kds.kds_url != KDS_CERT_SITE {
+            return Err(anyhow::anyhow!(
+                "No {processor_model} ARK is bundled with snpguest to check the chain of a KDS mirror against. Fetch it from {KDS_CERT_SITE}."
+            ));
+        }
+
+        let cache_dir = cache.dir().filter(|_| pinned);
+        let cache_path = cache_dir.as_ref().map(|dir| {
+            dir.join(processor_model.kds_path()).join(format!(
+                "{}-cert_chain.pem",
+                endorser.to_string().to_lowercase()
+            ))
+        });
+        let body = fetch_cached(
+            cache_path,
+            X509::stack_from_pem,
+            |certs| {
+                if !is_bundled_ark(certs, processor_model)? {
+                    return Err(anyhow::anyhow!(
+                        "The ARK of the cached chain is not the AMD {processor_model} ARK."
+                    ));
+                }
+                request_crl_kds(
+                    kds,
+                    processor_model,
+                    endorser,
+                    &certs[1],
+                    cache_dir.as_deref(),
+                )
+            },
+            || {
+                let body = request_ca_kds(kds, processor_model, endorser)?;
+                if pinned && !is_bundled_ark(&X509::stack_from_pem(&body)?, processor_model)? {
+                    return Err(anyhow::anyhow!(
+                        "The ARK from the KDS is not the AMD {processor_model} ARK bundled with snpguest."
+                    ));
+                }
+                Ok(body)
+            },
+        )?;
+        let mut certificates = X509::stack_from_pem(&body)?;
+        if certificates.len() < 2 {
+            return Err(anyhow::anyhow!(
+                "AMD certificate chain does not contain both the ASK and the ARK."
+            ));
         }
+
+        let ark_cert = certificates.swap_remove(1);
+        let ask_cert = certificates.swap_remove(0);
+
+        Ok((ark_cert, ask_cert))
     }
 
     // Fetch the ca from the kds and write it into the certs directory
-    pub fn fetch_ca(args: Args) -> Result<()> {
-        // Get certs from kds
-        let certificates = request_ca_kds(args.processor_model, &args.endorser)?;
+    pub fn fetch_ca(args: Args, cert_format: Option<CertFormat>, quiet: bool) -> Result<()> {
+        let encoding = require_encoding(args.encoding, cert_format)?;
 
-        // Create certs directory if missing
-        if !args.certs_dir.exists() {
-            fs::create_dir(&args.certs_dir).context("Could not create certs folder")?;
+        if args.dry_run {
+            println!(
+                "{}",
+                args.kds
+                    .url(&ca_path(&args.processor_model, &args.endorser))
+            );
+            return Ok(());
+        }
+
+        // Create certs directory if missing, and don't leave a new ARK next to an old ASK
+        let output_dir = certs::require_certs_dir(args.output_dir)?;
+        create_output_dir(&output_dir)?;
+        let paths = [CertType::ARK, CertType::ASK]
+            .map(|cert_type| cert_path(&output_dir, &cert_type, encoding, &args.endorser));
+        if !args.force && is_present_ca_chain(&paths, &args.processor_model, &args.endorser) {
+            if !quiet {
+                for path in &paths {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    println!("{name} already present and valid; skipping");
+                }
+            }
+            return Ok(());
+        }
+        for path in &paths {
+            check_overwrite(path, args.force)?;
         }
 
-        let ark_cert = &certificates[1];
-        let ask_cert = &certificates[0];
+        // Get certs from the cache or the kds
+        let (ark_cert, ask_cert) = fetch_ca_chain(
+            &args.kds,
+            &args.cache,
+            &args.processor_model,
+            &args.endorser,
+        )?;
 
         write_cert(
-            &args.certs_dir,
+            &output_dir,
             &CertType::ARK,
             &ark_cert.to_pem()?,
-            args.encoding,
+            encoding,
             &args.endorser,
+            args.force,
         )?;
         write_cert(
-            &args.certs_dir,
+            &output_dir,
             &CertType::ASK,
             &ask_cert.to_pem()?,
-            args.encoding,
+            encoding,
             &args.endorser,
+            args.force,
         )?;
 
         Ok(())
     }
+
+    // Whether the ARK and ASK (or ASVK) at the paths are a valid chain of the processor model
+    // and endorser, named as the KDS names them, so fetching them again can be skipped.
+    fn is_present_ca_chain(
+        [ark_path, ask_path]: &[PathBuf; 2],
+        processor_model: &ProcType,
+        endorser: &Endorsement,
+    ) -> bool {
+        let (Some(ark), Some(ask)) = (
+            read_valid_cert_file(ark_path),
+            read_valid_cert_file(ask_path),
+        ) else {
+            return false;
+        };
+
+        let model = processor_model.kds_path();
+        let ask_name = match endorser {
+            Endorsement::Vcek => format!("SEV-{model}"),
+            Endorsement::Vlek => format!("SEV-VLEK-{model}"),
+        };
+        let common_name = |cert: &X509| {
+            cert.subject_name()
+                .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+                .next()
+                .and_then(|entry| entry.data().as_utf8().ok())
+                .map(|name| name.to_string())
+        };
+        if common_name(&ark) != Some(format!("ARK-{model}")) || common_name(&ask) != Some(ask_name)
+        {
+            return false;
+        }
+
+        let (ark, ask) = (
+            sev::certs::snp::Certificate::from(ark),
+            sev::certs::snp::Certificate::from(ask),
+        );
+        sev::certs::snp::Verifiable::verify((&ark, &ask)).is_ok()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use sev::certs::snp::builtin::{genoa, milan};
+
+        #[test]
+        fn test_is_present_ca_chain() {
+            let dir = std::env::temp_dir().join(format!("snpguest-ca-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let paths = [dir.join("ark.pem"), dir.join("ask.pem")];
+            let is_present = |model| is_present_ca_chain(&paths, &model, &Endorsement::Vcek);
+            assert!(!is_present(ProcType::Milan));
+
+            fs::write(&paths[0], milan::ARK).unwrap();
+            fs::write(&paths[1], milan::ASK).unwrap();
+            assert!(is_present(ProcType::Milan));
+            // The Milan chain is not the Genoa one, nor an ASVK
+            assert!(!is_present(ProcType::Genoa));
+            assert!(!is_present_ca_chain(
+                &paths,
+                &ProcType::Milan,
+                &Endorsement::Vlek
+            ));
+
+            // Nor is a Genoa ARK next to an ASK it didn't sign
+            fs::write(&paths[0], genoa::ARK).unwrap();
+            assert!(!is_present(ProcType::Genoa));
+            fs::write(&paths[1], genoa::ASK).unwrap();
+            assert!(is_present(ProcType::Bergamo));
+
+            // A wrong chain already in place is not skipped, nor silently replaced
+            fs::write(&paths[0], milan::ARK).unwrap();
+            fs::write(&paths[1], milan::ASK).unwrap();
+            let args =
+                Args::parse_from(["ca", "pem", "genoa", "--output-dir", dir.to_str().unwrap()]);
+            let err = fetch_ca(args, None, true).unwrap_err();
+            assert!(err.to_string().contains("--force"), "{err}");
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn test_is_bundled_ark() {
+            let chain = |ask: &[u8], ark: &[u8]| {
+                vec![X509::from_pem(ask).unwrap(), X509::from_pem(ark).unwrap()]
+            };
+
+            assert!(is_bundled_ark(&chain(milan::ASK, milan::ARK), &ProcType::Milan).unwrap());
+            assert!(is_bundled_ark(&chain(genoa::ASK, genoa::ARK), &ProcType::Siena).unwrap());
+            // The ARK of another generation, or a certificate the ARK signed, is not the ARK
+            assert!(!is_bundled_ark(&chain(genoa::ASK, genoa::ARK), &ProcType::Milan).unwrap());
+            assert!(!is_bundled_ark(&chain(milan::ASK, milan::ASK), &ProcType::Milan).unwrap());
+            assert!(is_bundled_ark(&chain(milan::ASK, milan::ARK)[..1], &ProcType::Milan).is_err());
+        }
+    }
 }
 
 mod vcek {
-    use reqwest::StatusCode;
-
     use super::*;
 
+    // The encoding can be left out ahead of the processor model, which clap can't tell apart
+    // from a missing report. The positionals are shifted into place by positionals().
     #[derive(Parser)]
+    #[command(
+        override_usage = "snpguest fetch vcek [OPTIONS] [encoding] <processor-model> <att-report-path>"
+    )]
     pub struct Args {
-        /// Specify encoding to use for certificates.
+        /// Specify encoding to use for certificates. Can be left out when the global
+        /// --cert-format is given.
         #[arg(value_name = "encoding", required = true)]
-        pub encoding: CertFormat,
+        pub encoding: String,
 
         /// Specify the processor model for the certificate chain.
         #[arg(value_name = "processor-model", required = true)]
-        pub processor_model: ProcType,
+        pub processor_model: String,
+
+        /// Directory to store the certificates in, created if missing. Defaults to
+        /// $SNPGUEST_CERTS_DIR.
+        #[arg(long, value_name = "output-dir")]
+        pub output_dir: Option<PathBuf>,
 
-        /// Directory to store the certificates in.
-        #[arg(value_name = "certs-dir", required = true)]
-        pub certs_dir: PathBuf,
+        /// Overwrite certificates already in the output directory.
+        #[arg(long)]
+        pub force: bool,
 
         /// Path to attestation report to use to request VCEK.
-        #[arg(value_name = "att-report-path", required = true)]
-        pub att_report_path: PathBuf,
+        #[arg(value_name = "att-report-path")]
+        pub att_report_path: Option<PathBuf>,
+
+        /// Print the KDS URL that would be requested instead of fetching anything.
+        #[arg(long)]
+        pub dry_run: bool,
+
+        #[command(flatten)]
+        pub kds: KdsArgs,
+
+        #[command(flatten)]
+        pub cache: CacheArgs,
     }
 
-    // Function to request vcek from KDS. Return vcek in der format.
-    pub fn request_vcek_kds(
-        processor_model: ProcType,
-        att_report_path: PathBuf,
-    ) -> Result<Vec<u8>, anyhow::Error> {
+    // Path of the vcek of a report on the KDS, from its chip ID and reported TCB.
+    pub fn vcek_path(processor_model: &ProcType, att_report: &AttestationReport) -> Result<String> {
         // KDS URL parameters
-        const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
         const KDS_VCEK: &str = "/vcek/v1";
 
-        // Grab attestation report if path provided, request report if no path is provided
-        let att_report = if !att_report_path.exists() {
-            return Err(anyhow::anyhow!("No attestation report in provided path."));
-        } else {
-            report::read_report(att_report_path).context("Could not open attestation report")?
-        };
-
         // Use attestation report to get data for URL
-        let hw_id: String = hex::encode(att_report.chip_id);
-
-        let vcek_url: String = format!(
-            "{KDS_CERT_SITE}{KDS_VCEK}/{}/\
-            {hw_id}?blSPL={:02}&teeSPL={:02}&snpSPL={:02}&ucodeSPL={:02}",
-            processor_model.to_kds_url(),
-            att_report.reported_tcb.bootloader,
-            att_report.reported_tcb.tee,
-            att_report.reported_tcb.snp,
-            att_report.reported_tcb.microcode
+        let reported_tcb = processor_model.decode_tcb(&att_report.reported_tcb)?;
+
+        Ok(match reported_tcb.fmc {
+            Some(fmc) => format!(
+                "{KDS_VCEK}/{}/\
+                {}?fmcSPL={:02}&blSPL={:02}&teeSPL={:02}&snpSPL={:02}&ucodeSPL={:02}",
+                processor_model.kds_path(),
+                hex::encode(&att_report.chip_id[..processor_model.chip_id_len()]),
+                fmc,
+                reported_tcb.bootloader,
+                reported_tcb.tee,
+                reported_tcb.snp,
+                reported_tcb.microcode
+            ),
+            None => format!(
+                "{KDS_VCEK}/{}/\
+                {}?blSPL={:02}&teeSPL={:02}&snpSPL={:02}&ucodeSPL={:02}",
+                processor_model.kds_path(),
+                hex::encode(att_report.chip_id),
+                reported_tcb.bootloader,
+                reported_tcb.tee,
+                reported_tcb.snp,
+                reported_tcb.microcode
+            ),
+        })
+    }
+
+    // URL of the vcek of a report on the KDS at the base URL, encoding its chip ID and each
+    // component of its reported TCB.
+    pub fn vcek_url_for_report(
+        att_report: &AttestationReport,
+        model: ProcType,
+        base: &Url,
+    ) -> Result<Url> {
+        let url = format!(
+            "{}{}",
+            base.as_str().trim_end_matches('/'),
+            vcek_path(&model, att_report)?
         );
 
+        Url::parse(&url).context(format!("Could not build the VCEK URL {url}"))
+    }
+
+    // Function to request vcek from KDS. Return vcek in der format.
+    fn request_vcek_kds(
+        kds: &KdsArgs,
+        processor_model: &ProcType,
+        att_report: &AttestationReport,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let vcek_path = vcek_path(processor_model, att_report)?;
+
         // VCEK in DER format
-        let vcek_rsp: Response = get(vcek_url).context("Unable to send request for VCEK")?;
+        let vcek_rsp: Response = kds
+            .get(&vcek_path)
+            .context("Unable to send request for VCEK")?;
 
         match vcek_rsp.status() {
             StatusCode::OK => {
-                let vcek_rsp_bytes: Vec<u8> =
-                    vcek_rsp.bytes().context("Unable to parse VCEK")?.to_vec();
+                let vcek_rsp_bytes: Vec<u8> = kds.body(vcek_rsp).context("Unable to parse VCEK")?;
                 Ok(vcek_rsp_bytes)
             }
-            status => Err(anyhow::anyhow!("Unable to fetch VCEK from URL: {status:?}")),
+            status => Err(anyhow::anyhow!(
+                "Unable to fetch VCEK, KDS returned {status}"
+            )),
+        }
+    }
+
+    // Request the vcek for a report from the cache or the kds, keyed by chip ID and reported TCB
+    pub fn fetch_report_vcek(
+        kds: &KdsArgs,
+        cache: &CacheArgs,
+        processor_model: &ProcType,
+        att_report: &AttestationReport,
+    ) -> Result<Vec<u8>> {
+        let reported_tcb = bincode::serialize(&att_report.reported_tcb)
+            .context("Could not serialize TCB version.")?;
+        let cache_dir = cache.dir();
+        let cache_path = cache_dir.as_ref().map(|dir| {
+            dir.join(processor_model.kds_path()).join(format!(
+                "vcek-{}-{}.der",
+                hex::encode(att_report.chip_id),
+                hex::encode(reported_tcb)
+            ))
+        });
+
+        fetch_cached(
+            cache_path,
+            |der| X509::from_der(der).map(|cert| vec![cert]),
+            |_| {
+                let (ark, _) = fetch_ca_chain(kds, cache, processor_model, &Endorsement::Vcek)?;
+                request_crl_kds(
+                    kds,
+                    processor_model,
+                    &Endorsement::Vcek,
+                    &ark,
+                    cache_dir.as_deref(),
+                )
+            },
+            || request_vcek_kds(kds, processor_model, att_report),
+        )
+    }
+
+    impl Args {
+        // The encoding, processor model and report path given as positionals. The encoding is
+        // absent when only two are given, and must then come from --cert-format.
+        pub fn positionals(
+            &self,
+            cert_format: Option<CertFormat>,
+        ) -> Result<(CertFormat, ProcType, PathBuf)> {
+            let (encoding, processor_model, att_report_path) = match &self.att_report_path {
+                Some(att_report_path) => (
+                    Some(&self.encoding),
+                    &self.processor_model,
+                    att_report_path.clone(),
+                ),
+                None => (None, &self.encoding, PathBuf::from(&self.processor_model)),
+            };
+            let encoding = encoding
+                .map(|encoding| {
+                    encoding.parse::<CertFormat>().with_context(|| {
+                        format!("Invalid encoding {encoding}, expected pem or der")
+                    })
+                })
+                .transpose()?;
+            let processor_model = ProcType::from_str(processor_model)?;
+
+            Ok((
+                require_encoding(encoding, cert_format)?,
+                processor_model,
+                att_report_path,
+            ))
         }
     }
 
     // Function to request vcek from kds and write it into file
-    pub fn fetch_vcek(args: Args) -> Result<()> {
-        // Request vcek
-        let vcek = request_vcek_kds(args.processor_model, args.att_report_path)?;
+    pub fn fetch_vcek(args: Args, cert_format: Option<CertFormat>) -> Result<()> {
+        let (encoding, processor_model, att_report_path) = args.positionals(cert_format)?;
 
-        if !args.certs_dir.exists() {
-            fs::create_dir(&args.certs_dir).context("Could not create certs folder")?;
+        // Grab attestation report if path provided
+        let att_report = if !att_report_path.exists() {
+            return Err(anyhow::anyhow!("No attestation report in provided path."));
+        } else {
+            report::read_report(att_report_path).context("Could not open attestation report")?
+        };
+
+        if args.dry_run {
+            println!(
+                "{}",
+                args.kds.url(&vcek_path(&processor_model, &att_report)?)
+            );
+            return Ok(());
         }
 
+        let output_dir = certs::require_certs_dir(args.output_dir)?;
+        create_output_dir(&output_dir)?;
+        check_overwrite(
+            &cert_path(&output_dir, &CertType::VCEK, encoding, &Endorsement::Vcek),
+            args.force,
+        )?;
+
+        // Request vcek
+        let vcek = fetch_report_vcek(&args.kds, &args.cache, &processor_model, &att_report)?;
+
         write_cert(
-            &args.certs_dir,
+            &output_dir,
             &CertType::VCEK,
             &vcek,
-            args.encoding,
+            encoding,
             &Endorsement::Vcek,
+            args.force,
         )?;
 
         Ok(())
     }
 }
+mod vcek_batch {
+    use super::*;
+    use sev::certs::snp::Certificate;
+    use std::{collections::BTreeMap, sync::Mutex, thread};
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Directory of attestation reports to request VCEKs for.
+        #[arg(value_name = "reports-dir", required = true)]
+        pub reports_dir: PathBuf,
+
+        /// Directory to store the VCEKs in.
+        #[arg(value_name = "out-dir", required = true)]
+        pub out_dir: PathBuf,
+
+        /// Processor model of the reports. Detected from each report when not provided, and a
+        /// report it can't be detected from fails like an unreadable one.
+        #[arg(short, long, value_name = "processor-model")]
+        pub processor_model: Option<ProcType>,
+
+        /// Number of VCEKs to request at the same time.
+        #[arg(long, value_name = "concurrency", default_value_t = 4)]
+        pub concurrency: usize,
+
+        /// Print the KDS URLs that would be requested instead of fetching anything.
+        #[arg(long)]
+        pub dry_run: bool,
+
+        /// Skip the reports that can't be read instead of aborting, failing at the end with a
+        /// summary of those skipped.
+        #[arg(long)]
+        pub continue_on_error: bool,
+
+        #[command(flatten)]
+        pub kds: KdsArgs,
+
+        #[command(flatten)]
+        pub cache: CacheArgs,
+    }
+
+    // A VCEK to request, shared by every report with the same chip ID and reported TCB.
+    struct Request {
+        name: String,
+        report_path: PathBuf,
+        processor_model: ProcType,
+        att_report: AttestationReport,
+    }
+
+    // Reports skipped with --continue-on-error, and why.
+    type Skipped = Vec<(PathBuf, anyhow::Error)>;
+
+    // Read every report in the directory and collect the distinct VCEKs they need, along with
+    // the reports skipped.
+    fn collect_requests(args: &Args) -> Result<(Vec<Request>, Skipped)> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&args.reports_dir)
+            .context("Could not read reports directory")?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+
+        let mut requests = BTreeMap::new();
+        let mut skipped = Vec::new();
+        for path in paths {
+            let request = read_request(args, &path)
+                .with_context(|| format!("Could not open attestation report {}", path.display()));
+            match request {
+                Ok(request) => {
+                    requests.entry(request.name.clone()).or_insert(request);
+                }
+                Err(err) if args.continue_on_error => skipped.push((path, err)),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok((requests.into_values().collect(), skipped))
+    }
+
+    // The VCEK a report needs, named by the chip ID and reported TCB of the report.
+    fn read_request(args: &Args, path: &Path) -> Result<Request> {
+        let att_report = report::read_report(path.to_path_buf())?;
+        let processor_model = match &args.processor_model {
+            Some(processor_model) => processor_model.clone(),
+            None => report::get_processor_model(&att_report)
+                .context("Provide the processor model with --processor-model to fetch its VCEK.")?,
+        };
+        let reported_tcb = bincode::serialize(&att_report.reported_tcb)
+            .context("Could not serialize TCB version.")?;
+        // Fail on a TCB the KDS can't be asked for now, rather than when fetching
+        vcek::vcek_path(&processor_model, &att_report)?;
+
+        Ok(Request {
+            name: format!(
+                "{}-{}",
+                hex::encode(att_report.chip_id),
+                hex::encode(reported_tcb)
+            ),
+            report_path: path.to_path_buf(),
+            processor_model,
+            att_report,
+        })
+    }
+
+    // Request the VCEK of every report in the directory with a bounded pool of workers
+    pub fn fetch_vcek_batch(
+        args: Args,
+        cert_format: Option<CertFormat>,
+        quiet: bool,
+    ) -> Result<()> {
+        let encoding = resolve_cert_format(None, cert_format)?;
+        if args.concurrency == 0 {
+            return Err(anyhow::anyhow!("Concurrency must be at least 1."));
+        }
+
+        let (requests, skipped) = collect_requests(&args)?;
+        if !quiet {
+            for (path, err) in &skipped {
+                eprintln!("ERROR: Skipping {}: {err:#}", path.display());
+            }
+        }
+
+        if args.dry_run {
+            for request in &requests {
+                let path = vcek::vcek_path(&request.processor_model, &request.att_report)?;
+                println!("{}", args.kds.url(&path));
+            }
+            return skipped_result(&skipped);
+        }
+
+        if !args.out_dir.exists() {
+            fs::create_dir(&args.out_dir).context("Could not create certs folder")?;
+        }
+
+        let next = AtomicUsize::new(0);
+        let failures = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..args.concurrency.min(requests.len()) {
+                scope.spawn(|| {
+                    while let Some(request) = requests.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let result = fetch_report_vcek(
+                            &args.kds,
+                            &args.cache,
+                            &request.processor_model,
+                            &request.att_report,
+                        )
+                        .and_then(|vcek| {
+                            let cert = Certificate::from_bytes(&vcek)?;
+                            let bytes = match encoding {
+                                CertFormat::Pem => cert.to_pem()?,
+                                CertFormat::Der => cert.to_der()?,
+                            };
+                            let path = args.out_dir.join(format!("{}.{encoding}", request.name));
+                            fs::write(path, bytes).context("Unable to write VCEK")
+                        });
+
+                        if let Err(err) = result {
+                            failures.lock().unwrap().push((request, err));
+                        }
+                    }
+                });
+            }
+        });
+
+        let failures = failures.into_inner().unwrap();
+        if !quiet {
+            for (request, err) in &failures {
+                eprintln!(
+                    "ERROR: Could not fetch VCEK {} for {}: {err:#}",
+                    request.name,
+                    request.report_path.display()
+                );
+            }
+            println!(
+                "Fetched {} of {} VCEKs.",
+                requests.len() - failures.len(),
+                requests.len()
+            );
+            if !skipped.is_empty() {
+                println!("Skipped {} unreadable reports.", skipped.len());
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} of {} VCEKs could not be fetched.",
+                failures.len(),
+                requests.len()
+            ));
+        }
+        skipped_result(&skipped)
+    }
+
+    // Fail a batch once it has run when any of its reports were skipped.
+    fn skipped_result(skipped: &[(PathBuf, anyhow::Error)]) -> Result<()> {
+        if skipped.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "{} attestation reports could not be read.",
+                skipped.len()
+            ))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_collect_requests_dedupes() {
+            let dir = std::env::temp_dir().join(format!("snpguest-batch-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            let mut att_report = AttestationReport::default();
+            att_report.version = 2;
+            att_report.chip_id = [0x11; 64];
+            let same = bincode::serialize(&att_report).unwrap();
+            att_report.chip_id = [0x22; 64];
+            let other = bincode::serialize(&att_report).unwrap();
+            fs::write(dir.join("a.bin"), &same).unwrap();
+            fs::write(dir.join("b.bin"), &same).unwrap();
+            fs::write(dir.join("c.bin"), &other).unwrap();
+
+            // Version 2 reports don't tell their processor model
+            let args = Args::parse_from(["vcek-batch", dir.to_str().unwrap(), "out"]);
+            let err = collect_requests(&args).err().unwrap();
+            assert!(format!("{err:#}").contains("--processor-model"), "{err:#}");
+            let args = Args::parse_from([
+                "vcek-batch",
+                dir.to_str().unwrap(),
+                "out",
+                "--continue-on-error",
+            ]);
+            let (requests, skipped) = collect_requests(&args).unwrap();
+            assert!(requests.is_empty());
+            assert_eq!(skipped.len(), 3);
+
+            let args =
+                Args::parse_from(["vcek-batch", dir.to_str().unwrap(), "out", "-p", "milan"]);
+            let (requests, skipped) = collect_requests(&args).unwrap();
+            assert_eq!(requests.len(), 2);
+            assert!(skipped.is_empty());
+            assert!(requests[0].name.starts_with(&"11".repeat(64)));
+            assert_eq!(requests[0].report_path, dir.join("a.bin"));
+            assert_eq!(requests[0].processor_model, ProcType::Milan);
+
+            // A corrupt report aborts the batch, unless it is skipped
+            fs::write(dir.join("d.bin"), b"corrupt").unwrap();
+            assert!(collect_requests(&args).is_err());
+            let args = Args::parse_from([
+                "vcek-batch",
+                dir.to_str().unwrap(),
+                "out",
+                "-p",
+                "milan",
+                "--continue-on-error",
+            ]);
+            let (requests, skipped) = collect_requests(&args).unwrap();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(skipped.len(), 1);
+            assert_eq!(skipped[0].0, dir.join("d.bin"));
+            assert!(skipped_result(&skipped).is_err());
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
 #[cfg(test)]
 mod tests {
-    use super::ProcType;
+    use super::{
+        fetch_cached, fs, is_retryable, parse_kds_url, progress_line, proxy_for_env, report_urls,
+        retry_delay, vcek, AttestationReport, Duration, KdsArgs, Parser, ProcType, StatusCode, Tcb,
+        TcbVersion, Url, X509,
+    };
 
     #[test]
     fn test_kds_prod_name_milan_base() {
         let milan_proc: ProcType = ProcType::Milan;
-        assert_eq!(milan_proc.to_kds_url(), ProcType::Milan.to_string());
+        assert_eq!(milan_proc.kds_path(), "Milan");
     }
 
     #[test]
     fn test_kds_prod_name_genoa_base() {
-        assert_eq!(ProcType::Genoa.to_kds_url(), ProcType::Genoa.to_string());
-        assert_eq!(ProcType::Siena.to_kds_url(), ProcType::Genoa.to_string());
-        assert_eq!(ProcType::Bergamo.to_kds_url(), ProcType::Genoa.to_string());
+        assert_eq!(ProcType::Genoa.kds_path(), "Genoa");
+        assert_eq!(ProcType::Siena.kds_path(), "Genoa");
+        assert_eq!(ProcType::Bergamo.kds_path(), "Genoa");
+        // The model is still printed as the part it is
+        assert_eq!(ProcType::Bergamo.to_string(), "Bergamo");
+    }
+
+    #[test]
+    fn test_proc_type_from_str() {
+        for (spellings, expected) in [
+            (
+                &[
+                    "milan",
+                    "Milan",
+                    "MILAN",
+                    "milan-x",
+                    "EPYC 7003",
+                    "7003X",
+                    "zen3",
+                ][..],
+                ProcType::Milan,
+            ),
+            (
+                &[
+                    "genoa",
+                    "Genoa",
+                    "GENOA",
+                    "Genoa-X",
+                    "AMD EPYC 9004",
+                    "9004x",
+                    "Zen4",
+                ],
+                ProcType::Genoa,
+            ),
+            (
+                &["bergamo", "BERGAMO", "epyc-97x4", "97X4"],
+                ProcType::Bergamo,
+            ),
+            (
+                &["siena", "Siena", "amd_epyc_8004", "8004"],
+                ProcType::Siena,
+            ),
+            (
+                &[
+                    "turin",
+                    "TURIN",
+                    "Turin Dense",
+                    "EPYC 9005",
+                    "zen5",
+                    "Zen5c",
+                ],
+                ProcType::Turin,
+            ),
+        ] {
+            for spelling in spellings {
+                assert_eq!(
+                    spelling.parse::<ProcType>().unwrap(),
+                    expected,
+                    "{spelling}"
+                );
+            }
+        }
+
+        let err = "naples".parse::<ProcType>().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("milan, genoa, bergamo, siena and turin"));
+        assert!("zen4c".parse::<ProcType>().is_err());
+
+        // Arguments accept the aliases too
+        let args = clap::Command::new("kds").arg(
+            clap::Arg::new("model")
+                .long("model")
+                .value_parser(clap::value_parser!(ProcType)),
+        );
+        let matches = args
+            .try_get_matches_from(["kds", "--model", "Milan-X"])
+            .unwrap();
+        assert_eq!(matches.get_one::<ProcType>("model"), Some(&ProcType::Milan));
+    }
+
+    #[test]
+    fn test_kds_prod_name_turin_base() {
+        assert_eq!(ProcType::Turin.kds_path(), "Turin");
+    }
+
+    #[test]
+    fn test_decode_tcb_milan_layout() {
+        let tcb = TcbVersion::new(3, 0, 8, 115);
+        assert_eq!(
+            ProcType::Genoa.decode_tcb(&tcb).unwrap(),
+            Tcb {
+                fmc: None,
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_tcb_turin_layout() {
+        let tcb: TcbVersion = bincode::deserialize(&[1, 2, 3, 4, 0, 0, 0, 5]).unwrap();
+        assert_eq!(
+            ProcType::Turin.decode_tcb(&tcb).unwrap(),
+            Tcb {
+                fmc: Some(1),
+                bootloader: 2,
+                tee: 3,
+                snp: 4,
+                microcode: 5,
+            }
+        );
+
+        // A Turin layout TCB claims components a Milan TCB does not have.
+        assert!(ProcType::Milan.decode_tcb(&tcb).is_err());
+    }
+
+    #[test]
+    fn test_tcb_format_and_order() {
+        let tcb = Tcb {
+            fmc: None,
+            bootloader: 3,
+            tee: 0,
+            snp: 8,
+            microcode: 115,
+        };
+        assert_eq!(tcb.to_string(), "bootloader=3,tee=0,snp=8,microcode=115");
+        assert_eq!(
+            Tcb {
+                fmc: Some(1),
+                ..tcb
+            }
+            .to_string(),
+            "fmc=1,bootloader=3,tee=0,snp=8,microcode=115"
+        );
+        assert_eq!(
+            serde_json::to_value(tcb).unwrap(),
+            serde_json::json!({
+                "fmc": null,
+                "bootloader": 3,
+                "tee": 0,
+                "snp": 8,
+                "microcode": 115,
+            })
+        );
+
+        let newer = Tcb { snp: 9, ..tcb };
+        assert!(tcb < newer);
+        assert!(newer >= tcb);
+        assert_eq!(tcb.partial_cmp(&tcb), Some(std::cmp::Ordering::Equal));
+        // Components moving in both directions, or a different layout, are unordered
+        let mixed = Tcb {
+            microcode: 114,
+            ..newer
+        };
+        assert_eq!(tcb.partial_cmp(&mixed), None);
+        assert_eq!(
+            tcb.partial_cmp(&Tcb {
+                fmc: Some(0),
+                ..tcb
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_vcek_path() {
+        let mut att_report = AttestationReport::default();
+        att_report.chip_id = [0x11; 64];
+        att_report.reported_tcb = TcbVersion::new(3, 0, 8, 115);
+
+        assert_eq!(
+            vcek::vcek_path(&ProcType::Milan, &att_report).unwrap(),
+            format!(
+                "/vcek/v1/Milan/{}?blSPL=03&teeSPL=00&snpSPL=08&ucodeSPL=115",
+                "11".repeat(64)
+            )
+        );
+    }
+
+    #[test]
+    fn test_vcek_url_for_report() {
+        let hw_id = "d49554ec717f4e5b0fe6b143bcf0405bd7ae304727edf46603f2a76aef6a3abc\
+                     15d7af38db757039029f0efacfd08e244324884738c72b082e2f87a44d541eb6";
+        let mut att_report = AttestationReport::default();
+        att_report.chip_id = hex::decode(hw_id).unwrap().try_into().unwrap();
+        att_report.reported_tcb = TcbVersion::new(3, 0, 8, 115);
+
+        let base = Url::parse("https://kdsintf.amd.com/").unwrap();
+        assert_eq!(
+            vcek::vcek_url_for_report(&att_report, ProcType::Milan, &base)
+                .unwrap()
+                .as_str(),
+            format!(
+                "https://kdsintf.amd.com/vcek/v1/Milan/{hw_id}?blSPL=03&teeSPL=00&snpSPL=08&ucodeSPL=115"
+            )
+        );
+
+        // Turin VCEKs are looked up by the first 8 bytes of the chip ID and the FMC too
+        att_report.reported_tcb = bincode::deserialize(&[1, 2, 3, 4, 0, 0, 0, 5]).unwrap();
+        let base = Url::parse("http://mirror:8080/amd").unwrap();
+        assert_eq!(
+            vcek::vcek_url_for_report(&att_report, ProcType::Turin, &base)
+                .unwrap()
+                .as_str(),
+            format!(
+                "http://mirror:8080/amd/vcek/v1/Turin/{}?fmcSPL=01&blSPL=02&teeSPL=03&snpSPL=04&ucodeSPL=05",
+                &hw_id[..16]
+            )
+        );
+    }
+
+    #[test]
+    fn test_report_urls() {
+        let kds = KdsArgs::parse_from(["kds", "--kds-url", "http://mirror:8080"]);
+        let mut att_report = AttestationReport::default();
+        att_report.chip_id = [0x11; 64];
+
+        let urls = report_urls(&kds, &ProcType::Genoa, &att_report).unwrap();
+        assert_eq!(urls.len(), 2);
+        assert_eq!(urls[0].0, "VCEK");
+        assert!(urls[0]
+            .1
+            .starts_with("http://mirror:8080/vcek/v1/Genoa/1111"));
+        assert_eq!(
+            urls[1],
+            (
+                "ARK and ASK",
+                "http://mirror:8080/vcek/v1/Genoa/cert_chain".to_string()
+            )
+        );
+
+        // The KDS doesn't serve VLEKs, so only the CA chain is printed
+        let mut bytes = bincode::serialize(&att_report).unwrap();
+        bytes[0x48] = 1 << 2;
+        let att_report: AttestationReport = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(
+            report_urls(&kds, &ProcType::Genoa, &att_report).unwrap(),
+            vec![(
+                "ARK and ASVK",
+                "http://mirror:8080/vlek/v1/Genoa/cert_chain".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_proxy_for_env() {
+        let env = |no_proxy: &'static str| {
+            move |var: &str| match var {
+                "HTTPS_PROXY" => Some("http://env-proxy:3128".to_string()),
+                "NO_PROXY" => Some(no_proxy.to_string()),
+                _ => None,
+            }
+        };
+        let kds = "https://kdsintf.amd.com/vcek/v1/Milan/cert_chain";
+        let proxy = |no_proxy, flag| proxy_for_env(flag, kds, env(no_proxy));
+
+        assert_eq!(proxy("", None).as_deref(), Some("http://env-proxy:3128"));
+        assert_eq!(
+            proxy("localhost", Some("http://flag:8080")).as_deref(),
+            Some("http://flag:8080")
+        );
+        // The KDS host, one of its parent domains or a wildcard bypass either proxy
+        for no_proxy in ["kdsintf.amd.com", "localhost, .amd.com", "amd.com", "*"] {
+            assert_eq!(proxy(no_proxy, None), None, "{no_proxy}");
+            assert_eq!(
+                proxy(no_proxy, Some("http://flag:8080")),
+                None,
+                "{no_proxy}"
+            );
+        }
+        assert!(proxy("md.com", None).is_some());
+        assert!(proxy_for_env(None, "https://10.0.0.1/", env("0.0.1")).is_some());
+        assert!(proxy_for_env(None, "https://10.0.0.1/", env("10.0.0.1")).is_none());
+    }
+
+    #[test]
+    fn test_kds_timeout() {
+        // A KDS that accepts the connection but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let kds_url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _connections: Vec<_> = listener.incoming().collect();
+        });
+
+        let mut kds = KdsArgs::parse_from(["kds", "--kds-url", &kds_url, "--max-retries", "0"]);
+        kds.timeout = 1;
+        let err = kds.get("/vcek").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("No response from {kds_url}/vcek after 1.")));
+    }
+
+    #[test]
+    fn test_fetch_cached() {
+        let dir = std::env::temp_dir().join(format!("snpguest-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("Milan").join("vcek-test.der");
+        let parse = |der: &[u8]| X509::from_der(der).map(|cert| vec![cert]);
+        let no_crl = |_: &[X509]| panic!("The CRL is only needed to check cached certificates.");
+
+        // Without a cache every fetch goes to the KDS
+        let bytes = fetch_cached(None, parse, no_crl, || Ok(b"first".to_vec())).unwrap();
+        assert_eq!(bytes, b"first");
+
+        // A miss requests the certificate and stores it
+        let bytes = fetch_cached(Some(path.clone()), parse, no_crl, || Ok(b"first".to_vec()));
+        assert_eq!(bytes.unwrap(), b"first");
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        // Cached bytes that aren't a certificate are requested again
+        let bytes = fetch_cached(Some(path.clone()), parse, no_crl, || Ok(b"second".to_vec()));
+        assert_eq!(bytes.unwrap(), b"second");
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        // A cached certificate is requested again when the CRL can't be had
+        let ark = X509::from_pem(sev::certs::snp::builtin::milan::ARK).unwrap();
+        fs::write(&path, ark.to_der().unwrap()).unwrap();
+        let no_kds = |_: &[X509]| Err(anyhow::anyhow!("The KDS is unreachable."));
+        let bytes = fetch_cached(Some(path.clone()), parse, no_kds, || Ok(b"third".to_vec()));
+        assert_eq!(bytes.unwrap(), b"third");
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_kds_url() {
+        assert_eq!(
+            parse_kds_url("https://kds.internal.example/").unwrap(),
+            "https://kds.internal.example"
+        );
+        assert_eq!(
+            parse_kds_url("http://mirror:8080/amd").unwrap(),
+            "http://mirror:8080/amd"
+        );
+        assert!(parse_kds_url("kds.internal.example").is_err());
+        assert!(parse_kds_url("ftp://kds.internal.example").is_err());
+        assert!(parse_kds_url("https://kds.internal.example/?x=1").is_err());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+
+        assert_eq!(retry_delay(0, 500, None), Duration::from_millis(500));
+        assert_eq!(retry_delay(3, 500, None), Duration::from_millis(4000));
+        assert_eq!(retry_delay(3, 500, Some("2")), Duration::from_secs(2));
+        // HTTP dates aren't supported, so back off as usual
+        assert_eq!(
+            retry_delay(1, 500, Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Duration::from_millis(1000)
+        );
+        // A KDS asking to wait a day, or a backoff grown as long, is only waited on for a minute
+        assert_eq!(retry_delay(0, 500, Some("86400")), Duration::from_secs(60));
+        assert_eq!(retry_delay(20, 500, None), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_progress_line() {
+        let url = "https://kdsintf.amd.com/vcek/v1/Milan/cert_chain";
+        assert_eq!(
+            progress_line(url, 1024, Some(4096)),
+            format!("{url}: 1024/4096 bytes")
+        );
+        assert_eq!(progress_line(url, 1024, None), format!("{url}: 1024 bytes"));
     }
 }
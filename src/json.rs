@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file contains the JSON encoder of the structured output printed by the display, verify and capabilities subcommands.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether JSON output is pretty printed, set once from --json-pretty and --json-compact.
+static PRETTY: AtomicBool = AtomicBool::new(true);
+
+pub fn set_pretty(pretty: bool) {
+    PRETTY.store(pretty, Ordering::Relaxed);
+}
+
+// Encode a value as JSON, pretty printed or on a single line as set from the command line.
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    encode(value, PRETTY.load(Ordering::Relaxed))
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_encode() {
+        let value = json!({ "verified": true, "tcb": { "snp": 8 } });
+        assert_eq!(
+            encode(&value, false).unwrap(),
+            r#"{"tcb":{"snp":8},"verified":true}"#
+        );
+        assert_eq!(
+            encode(&value, true).unwrap(),
+            "{\n  \"tcb\": {\n    \"snp\": 8\n  },\n  \"verified\": true\n}"
+        );
+    }
+}
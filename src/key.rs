@@ -2,19 +2,81 @@
 // This file contains code for fetching derived keys from root keys. It also includes functions for requesting and saving derived keys.
 
 use super::*;
-use sev::firmware::guest::{DerivedKey, Firmware, GuestFieldSelect};
-use std::io::Read;
+use sev::firmware::guest::{DerivedKey, GuestFieldSelect};
+use std::io::{Read, Write};
 use std::{fs, path::PathBuf};
 
+// Root keys a derived key can be requested from.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RootKey {
+    Vcek,
+    Vmrk,
+}
+
+impl std::fmt::Display for RootKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RootKey::Vcek => write!(f, "VCEK"),
+            RootKey::Vmrk => write!(f, "VMRK"),
+        }
+    }
+}
+
+// Guest Field Select bits, from the least significant one, along with their names.
+const GUEST_FIELDS: [(&str, &str); 6] = [
+    ("policy", "Guest Policy"),
+    ("image_id", "Image ID"),
+    ("family_id", "Family ID"),
+    ("measurement", "Measurement"),
+    ("svn", "SVN"),
+    ("tcb", "TCB Version"),
+];
+
+// Parse Guest Field Select, either as a 6 digit binary string or as a comma separated list of field names.
+fn parse_guest_field_select(input: &str) -> Result<u64> {
+    if !input.is_empty() && input.chars().all(|c| c == '0' || c == '1') {
+        if input.len() > GUEST_FIELDS.len() {
+            return Err(anyhow::anyhow!("Invalid Guest Field Select option."));
+        }
+        return Ok(u64::from_str_radix(input, 2)?);
+    }
+
+    let mut gfs = 0;
+    for field in input.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let bit = GUEST_FIELDS
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(field))
+            .ok_or_else(|| {
+                let names: Vec<&str> = GUEST_FIELDS.iter().map(|(name, _)| *name).collect();
+                anyhow::anyhow!(
+                    "Unknown guest field {field}. Use a 6 digit binary string or some of {}.",
+                    names.join(", ")
+                )
+            })?;
+        gfs |= 1 << bit;
+    }
+
+    Ok(gfs)
+}
+
 #[derive(Parser)]
 pub struct KeyArgs {
-    /// This is the path where the derived key will be saved.
+    /// This is the path where the derived key will be saved, or - to write it to stdout.
     #[arg(value_name = "key-path", required = true)]
     pub key_path: PathBuf,
 
     /// This is the root key from which to derive the key. Input either VCEK or VMRK.
-    #[arg(value_name = "root-key-select", required = true)]
-    pub root_key_select: String,
+    #[arg(
+        value_name = "root-key-select",
+        ignore_case = true,
+        required_unless_present = "root_key_sel",
+        conflicts_with = "root_key_sel"
+    )]
+    pub root_key_select: Option<RootKey>,
+
+    /// Root key from which to derive the key, instead of the positional root key.
+    #[arg(long = "root-key-sel", value_name = "root-key", ignore_case = true)]
+    pub root_key_sel: Option<RootKey>,
 
     /// Specify an integer VMPL level between 0 and 3 that the Guest is running on.
     #[arg(short, long, value_name = "vmpl", default_value = "1")]
@@ -22,8 +84,16 @@ pub struct KeyArgs {
 
     /// Specify which Guest Field Select bits to enable. It is a 6 digit binary string. For each bit, 0 denotes off and 1 denotes on.
     /// The least significant (rightmost) bit is Guest Policy followed by Image ID, Family ID, Measurement, SVN, TCB Version which is the most significant (leftmost) bit.
-    #[arg(short, long = "guest_file_select", value_name = "######")]
-    pub gfs: Option<String>,
+    /// The fields can also be listed by name: policy, image_id, family_id, measurement, svn and tcb.
+    #[arg(
+        short,
+        long = "guest_field_select",
+        visible_alias = "guest-field-select",
+        alias = "guest_file_select",
+        value_name = "######",
+        value_parser = parse_guest_field_select
+    )]
+    pub gfs: Option<u64>,
 
     /// Specify the guest SVN to mix into the key. Must not exceed the guest SVN provided at launch in the ID block.
     #[arg(short = 's', long = "guest_svn")]
@@ -34,62 +104,110 @@ pub struct KeyArgs {
     pub tcbv: Option<u64>,
 }
 
-pub fn get_derived_key(args: KeyArgs) -> Result<()> {
-    let root_key_select = match args.root_key_select.as_str() {
-        "vcek" => false,
-        "vmrk" => true,
-        _ => return Err(anyhow::anyhow!("Invalid input. Enter either vcek or vmrk")),
-    };
+// Lines describing what a derived key was derived from.
+fn key_summary(root_key: RootKey, vmpl: u32, gfs: u64, gsvn: u32, tcbv: u64) -> Vec<String> {
+    let fields: Vec<&str> = GUEST_FIELDS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| gfs & (1 << bit) != 0)
+        .map(|(_, (_, name))| *name)
+        .collect();
+
+    let mut summary = vec![
+        format!("Root key: {root_key}"),
+        format!("VMPL: {vmpl}"),
+        format!(
+            "Guest fields mixed in: {}",
+            if fields.is_empty() {
+                "none".to_string()
+            } else {
+                fields.join(", ")
+            }
+        ),
+    ];
+    if gfs & (1 << 4) != 0 {
+        summary.push(format!("Guest SVN: {gsvn}"));
+    }
+    if gfs & (1 << 5) != 0 {
+        summary.push(format!("TCB version: {tcbv:#x}"));
+    }
+
+    summary
+}
+
+pub fn get_derived_key(args: KeyArgs, quiet: bool) -> Result<()> {
+    let root_key = args
+        .root_key_sel
+        .or(args.root_key_select)
+        .ok_or_else(|| anyhow::anyhow!("Invalid input. Enter either vcek or vmrk"))?;
 
     let vmpl = match args.vmpl {
         Some(level) => {
             if level <= 3 {
                 level
             } else {
-                return Err(anyhow::anyhow!("Invalid Virtual Machine Privilege Level."));
+                return Err(anyhow::anyhow!(
+                    "VMPL {level} is out of range. Specify a VMPL between 0 and 3."
+                ));
             }
         }
         None => 1,
     };
 
-    let gfs = match args.gfs {
-        Some(gfs) => {
-            let value: u64 = u64::from_str_radix(gfs.as_str(), 2).unwrap();
-            if value <= 63 {
-                value
-            } else {
-                return Err(anyhow::anyhow!("Invalid Guest Field Select option."));
-            }
-        }
-        None => 0,
-    };
+    let gfs = args.gfs.unwrap_or(0);
 
     let gsvn: u32 = args.gsvn.unwrap_or(0);
 
     let tcbv: u64 = args.tcbv.unwrap_or(0);
 
-    let request = DerivedKey::new(root_key_select, GuestFieldSelect(gfs), vmpl, gsvn, tcbv);
-    let mut sev_fw = Firmware::open().context("failed to open SEV firmware device.")?;
+    let request = DerivedKey::new(
+        root_key == RootKey::Vmrk,
+        GuestFieldSelect(gfs),
+        vmpl,
+        gsvn,
+        tcbv,
+    );
+    let mut sev_fw = report::open_guest_firmware()?;
     let derived_key: [u8; 32] = sev_fw
         .get_derived_key(None, request)
         .context("Failed to request derived key")?;
 
     // Create derived key path
     let key_path: PathBuf = args.key_path;
-
-    // Write derived key into desired file
-    let mut key_file = if key_path.exists() {
-        std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(key_path)
-            .context("Unable to overwrite derived key file contents")?
+    let to_stdout = key_path.as_os_str() == "-";
+
+    // Write derived key into desired file, or stdout
+    if to_stdout {
+        let mut stdout = std::io::stdout();
+        stdout
+            .write_all(&derived_key)
+            .and_then(|_| stdout.flush())
+            .context("Could not write derived key to stdout.")?;
     } else {
-        fs::File::create(key_path).context("Unable to create derived key file contents")?
-    };
-
-    bincode::serialize_into(&mut key_file, &derived_key)
-        .context("Could not serialize derived key into file.")?;
+        let mut key_file = if key_path.exists() {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(key_path)
+                .context("Unable to overwrite derived key file contents")?
+        } else {
+            fs::File::create(key_path).context("Unable to create derived key file contents")?
+        };
+
+        bincode::serialize_into(&mut key_file, &derived_key)
+            .context("Could not serialize derived key into file.")?;
+    }
+
+    // Keep stdout to the key alone when it is written there
+    if !quiet {
+        for line in key_summary(root_key, vmpl, gfs, gsvn, tcbv) {
+            if to_stdout {
+                eprintln!("{line}");
+            } else {
+                println!("{line}");
+            }
+        }
+    }
 
     Ok(())
 }
@@ -101,3 +219,35 @@ pub fn read_key(key_path: PathBuf) -> Result<Vec<u8>, anyhow::Error> {
     key_file.read_to_end(&mut key)?;
     Ok(key)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_guest_field_select() {
+        assert_eq!(parse_guest_field_select("100001").unwrap(), 0b100001);
+        assert_eq!(
+            parse_guest_field_select("measurement, policy,TCB").unwrap(),
+            0b101001
+        );
+        assert!(parse_guest_field_select("1000001").is_err());
+        assert!(parse_guest_field_select("chip_id").is_err());
+    }
+
+    #[test]
+    fn test_key_summary() {
+        let summary = key_summary(RootKey::Vmrk, 2, 0b100001, 0, 0x1d);
+        assert_eq!(summary[0], "Root key: VMRK");
+        assert_eq!(summary[1], "VMPL: 2");
+        assert_eq!(
+            summary[2],
+            "Guest fields mixed in: Guest Policy, TCB Version"
+        );
+        assert_eq!(summary[3], "TCB version: 0x1d");
+        assert_eq!(summary.len(), 4);
+
+        let summary = key_summary(RootKey::Vcek, 1, 0, 0, 0);
+        assert_eq!(summary[2], "Guest fields mixed in: none");
+    }
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: Apache-2.0
+// This is the library of the snpguest utility. It holds the subcommands run by the snpguest binary, and lets other crates reuse its SNP OIDs and attestation report parsing.
+
+pub mod capabilities;
+pub mod certs;
+pub mod claims;
+pub mod display;
+pub mod fetch;
+pub mod json;
+pub mod key;
+pub mod oids;
+pub mod ok;
+pub mod policy;
+pub mod report;
+pub mod selftest;
+pub mod verify;
+
+#[cfg(feature = "hyperv")]
+pub mod hyperv;
+
+use certs::CertFormat;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
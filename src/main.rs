@@ -1,26 +1,22 @@
 // SPDX-License-Identifier: Apache-2.0
 // This is the main entry point of the snpguest utility. The CLI includes subcommands for requesting and managing certificates, displaying information, fetching derived keys, and verifying certificates and attestation reports.
 
-mod certs;
-mod display;
-mod fetch;
-mod key;
-mod ok;
-mod report;
-mod verify;
+use snpguest::{capabilities, certs, display, fetch, json, key, ok, report, selftest, verify};
 
 #[cfg(feature = "hyperv")]
-mod hyperv;
+use snpguest::hyperv;
 
-use certs::CertificatesArgs;
+use certs::{CertFormat, CertificatesArgs};
 use display::DisplayCmd;
 use fetch::FetchCmd;
 use key::KeyArgs;
 use report::ReportArgs;
-use verify::VerifyCmd;
+use verify::{ColorMode, VerifyCmd};
 
-use anyhow::{Context, Result};
-use clap::{arg, Parser, Subcommand, ValueEnum};
+use anyhow::Result;
+use clap::{ArgAction, Parser, Subcommand};
+use log::LevelFilter;
+use std::io::IsTerminal;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -31,6 +27,30 @@ struct SnpGuest {
     /// Don't print anything to the console
     #[arg(short, long, default_value_t = false)]
     pub quiet: bool,
+
+    /// Log what snpguest is doing to stderr, -v for debug and -vv for trace messages. Overrides RUST_LOG.
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Encoding of the certificates written to disk [default: pem]
+    #[arg(long, global = true, value_name = "cert-format")]
+    pub cert_format: Option<CertFormat>,
+
+    /// Seconds to wait to connect to and hear back from the network before failing
+    #[arg(long, global = true, value_name = "timeout", default_value_t = fetch::DEFAULT_TIMEOUT)]
+    pub timeout: u64,
+
+    /// When to mark verification checks with colored symbols
+    #[arg(long, global = true, value_enum, value_name = "when", default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+
+    /// Pretty print JSON output, the default when stdout is a terminal
+    #[arg(long, global = true, conflicts_with = "json_compact")]
+    pub json_pretty: bool,
+
+    /// Print JSON output on a single line, the default when stdout is piped
+    #[arg(long, global = true)]
+    pub json_compact: bool,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -60,13 +80,38 @@ enum SnpGuestCmd {
 
     /// Probe system for SEV-SNP support.
     Ok,
+
+    /// Print what this build supports as JSON.
+    Capabilities,
+
+    /// Check this build against known answers, exiting non-zero if any check fails.
+    Selftest,
 }
 
-fn main() -> Result<()> {
-    env_logger::init();
+// Log level selected with -v, or None to leave it to RUST_LOG.
+fn log_level(verbose: u8) -> Option<LevelFilter> {
+    match verbose {
+        0 => None,
+        1 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    }
+}
 
+fn main() -> Result<()> {
     let snpguest = SnpGuest::parse();
 
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = log_level(snpguest.verbose) {
+        logger.filter_module(env!("CARGO_CRATE_NAME"), level);
+    }
+    logger.init();
+
+    json::set_pretty(match (snpguest.json_pretty, snpguest.json_compact) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => std::io::stdout().is_terminal(),
+    });
+
     #[cfg(feature = "hyperv")]
     let hv = hyperv::present();
 
@@ -74,13 +119,22 @@ fn main() -> Result<()> {
     let hv = false;
 
     let status = match snpguest.cmd {
-        SnpGuestCmd::Report(args) => report::get_report(args, hv),
-        SnpGuestCmd::Certificates(args) => certs::get_ext_certs(args),
-        SnpGuestCmd::Fetch(subcmd) => fetch::cmd(subcmd),
-        SnpGuestCmd::Verify(subcmd) => verify::cmd(subcmd, snpguest.quiet),
+        SnpGuestCmd::Report(args) => report::get_report(args, hv, snpguest.cert_format),
+        SnpGuestCmd::Certificates(args) => certs::get_ext_certs(args, snpguest.cert_format),
+        SnpGuestCmd::Fetch(subcmd) => fetch::cmd(
+            subcmd,
+            snpguest.cert_format,
+            snpguest.timeout,
+            snpguest.quiet,
+        ),
+        SnpGuestCmd::Verify(subcmd) => {
+            verify::cmd(subcmd, snpguest.timeout, snpguest.color, snpguest.quiet)
+        }
         SnpGuestCmd::Display(subcmd) => display::cmd(subcmd, snpguest.quiet),
-        SnpGuestCmd::Key(args) => key::get_derived_key(args),
+        SnpGuestCmd::Key(args) => key::get_derived_key(args, snpguest.quiet),
         SnpGuestCmd::Ok => ok::cmd(snpguest.quiet),
+        SnpGuestCmd::Capabilities => capabilities::cmd(snpguest.quiet),
+        SnpGuestCmd::Selftest => selftest::cmd(snpguest.quiet),
     };
 
     if let Err(ref e) = status {
@@ -94,3 +148,328 @@ fn main() -> Result<()> {
 
     status
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        SnpGuest::command().debug_assert();
+    }
+
+    #[test]
+    fn test_cert_format() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args).unwrap();
+
+        let snpguest = parse(&["snpguest", "fetch", "vcek-batch", "reports", "vceks"]);
+        assert_eq!(snpguest.cert_format, None);
+
+        let snpguest = parse(&[
+            "snpguest",
+            "fetch",
+            "vcek",
+            "der",
+            "milan",
+            "--output-dir",
+            "certs",
+            "report.bin",
+            "--cert-format",
+            "der",
+        ]);
+        assert_eq!(snpguest.cert_format, Some(CertFormat::Der));
+
+        // The encoding positional can be left out when --cert-format is given
+        let encoding = |args: &[&str]| {
+            let snpguest = SnpGuest::try_parse_from(args).map_err(|err| err.to_string())?;
+            let cert_format = snpguest.cert_format;
+            match snpguest.cmd {
+                SnpGuestCmd::Fetch(FetchCmd::CA(args)) => {
+                    fetch::require_encoding(args.encoding, cert_format)
+                }
+                SnpGuestCmd::Fetch(FetchCmd::Vcek(args)) => {
+                    args.positionals(cert_format).map(|(encoding, ..)| encoding)
+                }
+                _ => panic!("not a fetch ca or fetch vcek command"),
+            }
+            .map_err(|err| err.to_string())
+        };
+        for args in [
+            &["snpguest", "fetch", "ca", "milan", "--cert-format", "der"][..],
+            &["snpguest", "fetch", "ca", "der", "milan"],
+            &[
+                "snpguest",
+                "--cert-format",
+                "der",
+                "fetch",
+                "vcek",
+                "milan",
+                "report.bin",
+            ],
+            &[
+                "snpguest",
+                "fetch",
+                "vcek",
+                "der",
+                "milan",
+                "--output-dir",
+                "c",
+                "report.bin",
+            ],
+        ] {
+            assert_eq!(encoding(args), Ok(CertFormat::Der), "{args:?}");
+        }
+        assert!(encoding(&["snpguest", "fetch", "ca", "milan"]).is_err());
+        assert!(encoding(&["snpguest", "fetch", "vcek", "milan", "report.bin"]).is_err());
+    }
+
+    #[test]
+    fn test_certificates_convert() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        assert!(parse(&["snpguest", "certificates", "pem", "certs"]).is_ok());
+        assert!(parse(&[
+            "snpguest",
+            "certificates",
+            "convert",
+            "vcek.der",
+            "--to",
+            "pem"
+        ])
+        .is_ok());
+        // The certs-dir falls back to SNPGUEST_CERTS_DIR when it is run
+        assert!(parse(&["snpguest", "certificates", "pem"]).is_ok());
+        assert!(parse(&["snpguest", "certificates", "convert", "vcek.der"]).is_err());
+    }
+
+    #[test]
+    fn test_report_data() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        assert!(parse(&["snpguest", "report", "report.bin", "--data", "data.bin"]).is_ok());
+        assert!(parse(&["snpguest", "report", "report.bin"]).is_err());
+        assert!(parse(&[
+            "snpguest",
+            "report",
+            "report.bin",
+            "request.txt",
+            "--data",
+            "data.bin"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_color() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        let snpguest = parse(&["snpguest", "verify", "certs", "certs"]).unwrap();
+        assert_eq!(snpguest.color, ColorMode::Auto);
+
+        let snpguest =
+            parse(&["snpguest", "verify", "certs", "certs", "--color", "never"]).unwrap();
+        assert_eq!(snpguest.color, ColorMode::Never);
+        assert!(!snpguest.color.enabled());
+        assert!(ColorMode::Always.enabled());
+
+        assert!(parse(&["snpguest", "--color", "rainbow", "verify", "certs", "certs"]).is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args).unwrap();
+
+        let snpguest = parse(&["snpguest", "fetch", "vcek-batch", "reports", "vceks"]);
+        assert_eq!(snpguest.timeout, fetch::DEFAULT_TIMEOUT);
+
+        // Accepted before and after the command
+        let snpguest = parse(&[
+            "snpguest",
+            "--timeout",
+            "5",
+            "verify",
+            "attestation",
+            "--extended",
+            "report.bin",
+        ]);
+        assert_eq!(snpguest.timeout, 5);
+        let snpguest = parse(&[
+            "snpguest",
+            "fetch",
+            "vcek-batch",
+            "reports",
+            "vceks",
+            "--timeout",
+            "5",
+        ]);
+        assert_eq!(snpguest.timeout, 5);
+    }
+
+    #[test]
+    fn test_verbose() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args).unwrap();
+
+        assert_eq!(log_level(parse(&["snpguest", "ok"]).verbose), None);
+        assert_eq!(
+            log_level(parse(&["snpguest", "-v", "ok"]).verbose),
+            Some(LevelFilter::Debug)
+        );
+        assert_eq!(
+            log_level(parse(&["snpguest", "-vvv", "ok"]).verbose),
+            Some(LevelFilter::Trace)
+        );
+
+        // -v of the report command still selects the VMPL
+        let snpguest = parse(&["snpguest", "report", "report.bin", "request.txt", "-v", "0"]);
+        assert_eq!(snpguest.verbose, 0);
+    }
+
+    #[test]
+    fn test_key_root_key() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        assert!(parse(&["snpguest", "key", "key.bin", "VCEK"]).is_ok());
+        assert!(parse(&["snpguest", "key", "-", "--root-key-sel", "vmrk"]).is_ok());
+        assert!(parse(&["snpguest", "key", "key.bin"]).is_err());
+        assert!(parse(&[
+            "snpguest",
+            "key",
+            "key.bin",
+            "vcek",
+            "--root-key-sel",
+            "vmrk"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_insecure_skip_chain() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        assert!(parse(&[
+            "snpguest",
+            "verify",
+            "all",
+            "certs",
+            "report.bin",
+            "--insecure-skip-chain"
+        ])
+        .is_ok());
+        assert!(parse(&[
+            "snpguest",
+            "verify",
+            "all",
+            "certs",
+            "report.bin",
+            "--insecure-skip-chain",
+            "--trusted-ark",
+            "ark.pem"
+        ])
+        .is_err());
+
+        // The paths are resolved once, so the report gets to the VEK lookup
+        let dir = std::env::temp_dir().join(format!("snpguest-skip-chain-{}", std::process::id()));
+        let certs_dir = dir.join("certs");
+        std::fs::create_dir_all(&certs_dir).unwrap();
+        let report = dir.join("report.bin");
+        std::fs::write(&report, [0u8; 1184]).unwrap();
+        let vek = dir.join("vcek.pem");
+        let (certs_dir, report, vek) = (
+            certs_dir.to_str().unwrap(),
+            report.to_str().unwrap(),
+            vek.to_str().unwrap(),
+        );
+        let verify = |args: &[&str]| match parse(args).unwrap().cmd {
+            SnpGuestCmd::Verify(subcmd) => verify::cmd(subcmd, 5, ColorMode::Never, true)
+                .unwrap_err()
+                .to_string(),
+            _ => unreachable!(),
+        };
+
+        let err = verify(&[
+            "snpguest",
+            "verify",
+            "all",
+            "--insecure-skip-chain",
+            certs_dir,
+            report,
+        ]);
+        assert!(
+            err.contains("vcek certificate not found in directory"),
+            "{err}"
+        );
+        let err = verify(&[
+            "snpguest",
+            "verify",
+            "all",
+            "--insecure-skip-chain",
+            "--vek",
+            vek,
+            report,
+        ]);
+        assert!(err.contains("Could not open provided vek file"), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_vek() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        assert!(parse(&[
+            "snpguest",
+            "verify",
+            "attestation",
+            "--vek",
+            "vcek.pem",
+            "report.bin"
+        ])
+        .is_ok());
+        // A directory and a VEK can't both be given
+        let err = parse(&[
+            "snpguest",
+            "verify",
+            "attestation",
+            "certs",
+            "report.bin",
+            "--vek",
+            "vcek.pem",
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_verify_fetch() {
+        let parse = |args: &[&str]| SnpGuest::try_parse_from(args);
+
+        // The certs-dir is optional, as missing certificates are fetched
+        for args in [
+            &["snpguest", "verify", "attestation", "--fetch", "report.bin"][..],
+            &[
+                "snpguest",
+                "verify",
+                "attestation",
+                "certs",
+                "report.bin",
+                "--fetch",
+            ],
+        ] {
+            assert!(parse(args).is_ok());
+        }
+        let err = parse(&[
+            "snpguest",
+            "verify",
+            "attestation",
+            "--fetch",
+            "--vek",
+            "vcek.pem",
+            "report.bin",
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+}
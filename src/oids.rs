@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file contains the OIDs of the SNP-specific X.509 extensions of a VEK, which carry the TCB it was issued for.
+
+use super::*;
+
+use fetch::ProcType;
+
+use asn1_rs::{oid, Oid};
+
+// Signature algorithm AMD signs every certificate of an SNP chain with.
+pub const RSASSA_PSS: Oid<'static> = oid!(1.2.840 .113549 .1 .1 .10);
+
+// Key algorithm and curve of a VEK, which signs reports with ECDSA P-384 and SHA-384.
+pub const EC_PUBLIC_KEY: Oid<'static> = oid!(1.2.840 .10045 .2 .1);
+pub const SECP384R1: Oid<'static> = oid!(1.3.132 .0 .34);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnpOid {
+    BootLoader,
+    Tee,
+    Snp,
+    Ucode,
+    Fmc,
+    HwId,
+}
+
+impl SnpOid {
+    // Every TCB component extension, in the order of the TCB version.
+    pub const TCB: [SnpOid; 5] = [
+        SnpOid::Fmc,
+        SnpOid::BootLoader,
+        SnpOid::Tee,
+        SnpOid::Snp,
+        SnpOid::Ucode,
+    ];
+
+    // Every SNP extension a VEK may carry.
+    pub const ALL: [SnpOid; 6] = [
+        SnpOid::Fmc,
+        SnpOid::BootLoader,
+        SnpOid::Tee,
+        SnpOid::Snp,
+        SnpOid::Ucode,
+        SnpOid::HwId,
+    ];
+
+    // SNP extension with the OID, or None for OIDs of other extensions.
+    pub fn from_oid(oid: &Oid) -> Option<SnpOid> {
+        SnpOid::ALL
+            .into_iter()
+            .find(|snp_oid| snp_oid.oid() == *oid)
+    }
+
+    // OID extensions for the VCEK, will be used to verify attestation report
+    pub fn oid(&self) -> Oid<'static> {
+        match self {
+            SnpOid::BootLoader => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .1),
+            SnpOid::Tee => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .2),
+            SnpOid::Snp => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .3),
+            SnpOid::Ucode => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .8),
+            SnpOid::Fmc => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .9),
+            SnpOid::HwId => oid!(1.3.6 .1 .4 .1 .3704 .1 .4),
+        }
+    }
+
+    // Name of a TCB component in component lists, such as --min-tcb, and in the canonical
+    // form of a TCB.
+    pub fn key(&self) -> &'static str {
+        match self {
+            SnpOid::BootLoader => "bootloader",
+            SnpOid::Tee => "tee",
+            SnpOid::Snp => "snp",
+            SnpOid::Ucode => "microcode",
+            SnpOid::Fmc => "fmc",
+            SnpOid::HwId => "hwid",
+        }
+    }
+
+    // TCB component named in a component list, accepting the short names bl and ucode too.
+    pub fn from_tcb_key(key: &str) -> Option<SnpOid> {
+        match key.trim().to_lowercase().as_str() {
+            "bootloader" | "bl" => Some(SnpOid::BootLoader),
+            "tee" => Some(SnpOid::Tee),
+            "snp" => Some(SnpOid::Snp),
+            "microcode" | "ucode" => Some(SnpOid::Ucode),
+            "fmc" => Some(SnpOid::Fmc),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SnpOid::BootLoader => "Boot Loader",
+            SnpOid::Tee => "TEE",
+            SnpOid::Snp => "SNP",
+            SnpOid::Ucode => "Microcode",
+            SnpOid::Fmc => "FMC",
+            SnpOid::HwId => "Hardware ID",
+        }
+    }
+}
+
+// TCB component extensions a VEK of the processor generation carries, without the FMC
+// before Turin.
+pub fn tcb_oids(proc_model: &ProcType) -> Vec<SnpOid> {
+    SnpOid::TCB
+        .into_iter()
+        .filter(|snp_oid| *snp_oid != SnpOid::Fmc || proc_model.has_fmc())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcb_oids() {
+        assert_eq!(
+            tcb_oids(&ProcType::Milan),
+            [SnpOid::BootLoader, SnpOid::Tee, SnpOid::Snp, SnpOid::Ucode]
+        );
+        assert_eq!(tcb_oids(&ProcType::Turin)[0], SnpOid::Fmc);
+        assert_eq!(SnpOid::Ucode.oid().to_id_string(), "1.3.6.1.4.1.3704.1.3.8");
+        assert_eq!(SnpOid::from_oid(&SnpOid::HwId.oid()), Some(SnpOid::HwId));
+        assert_eq!(SnpOid::from_oid(&RSASSA_PSS), None);
+    }
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file contains helpers for decoding and checking the guest policy of an attestation report.
+
+use super::*;
+use std::{fmt, str::FromStr};
+
+// Guest policy of an attestation report, decoded into its named fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuestPolicy {
+    pub abi_major: u8,
+    pub abi_minor: u8,
+    pub smt_allowed: bool,
+    pub migrate_ma_allowed: bool,
+    pub debug_allowed: bool,
+    pub single_socket_required: bool,
+    pub cxl_allowed: bool,
+    pub mem_aes_256_xts: bool,
+    pub rapl_dis: bool,
+    pub ciphertext_hiding: bool,
+}
+
+pub fn decode_policy(raw: u64) -> GuestPolicy {
+    let bit = |n: u32| raw & (1 << n) != 0;
+
+    GuestPolicy {
+        abi_minor: raw as u8,
+        abi_major: (raw >> 8) as u8,
+        smt_allowed: bit(16),
+        migrate_ma_allowed: bit(18),
+        debug_allowed: bit(19),
+        single_socket_required: bit(20),
+        cxl_allowed: bit(21),
+        mem_aes_256_xts: bit(22),
+        rapl_dis: bit(23),
+        ciphertext_hiding: bit(24),
+    }
+}
+
+impl GuestPolicy {
+    // Minimum firmware ABI version the guest was launched with.
+    pub fn abi(&self) -> AbiVersion {
+        AbiVersion {
+            major: self.abi_major,
+            minor: self.abi_minor,
+        }
+    }
+
+    // Named policy flags, as accepted by --policy-require.
+    pub fn flags(&self) -> [(&'static str, bool); 8] {
+        [
+            ("smt", self.smt_allowed),
+            ("migrate_ma", self.migrate_ma_allowed),
+            ("debug", self.debug_allowed),
+            ("single_socket", self.single_socket_required),
+            ("cxl", self.cxl_allowed),
+            ("mem_aes_256_xts", self.mem_aes_256_xts),
+            ("rapl_dis", self.rapl_dis),
+            ("ciphertext_hiding", self.ciphertext_hiding),
+        ]
+    }
+}
+
+// Firmware ABI version of a guest policy, written as major.minor, e.g. 1.51.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AbiVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl FromStr for AbiVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<AbiVersion, anyhow::Error> {
+        let (major, minor) = input.trim().split_once('.').ok_or_else(|| {
+            anyhow::anyhow!("ABI version {input} is not of the form major.minor.")
+        })?;
+        let parse = |part: &str| {
+            part.parse::<u8>()
+                .with_context(|| format!("Invalid ABI version {input}."))
+        };
+
+        Ok(AbiVersion {
+            major: parse(major)?,
+            minor: parse(minor)?,
+        })
+    }
+}
+
+impl fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+// Required values of guest policy flags, e.g. debug=false,smt=false.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyRequirements(Vec<(&'static str, bool)>);
+
+impl FromStr for PolicyRequirements {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<PolicyRequirements, anyhow::Error> {
+        let known = decode_policy(0).flags();
+        let mut requirements = Vec::new();
+
+        for requirement in input.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let (name, value) = requirement.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("Policy requirement {requirement} is not of the form flag=value.")
+            })?;
+            let name = known
+                .iter()
+                .map(|(known, _)| *known)
+                .find(|known| known.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| anyhow::anyhow!("Unknown guest policy flag {}.", name.trim()))?;
+            let value = value
+                .trim()
+                .parse::<bool>()
+                .with_context(|| format!("Invalid value for guest policy flag {name}."))?;
+            requirements.push((name, value));
+        }
+
+        Ok(PolicyRequirements(requirements))
+    }
+}
+
+impl PolicyRequirements {
+    // Fail if any of the required flags has a different value in the policy.
+    pub fn check(&self, policy: &GuestPolicy) -> Result<()> {
+        let flags = policy.flags();
+
+        for (name, required) in &self.0 {
+            if let Some((_, actual)) = flags.iter().find(|(flag, _)| flag == name) {
+                if actual != required {
+                    return Err(anyhow::anyhow!(
+                        "Guest policy flag {name} is {actual}, but {required} is required."
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_policy() {
+        // ABI 1.51, SMT allowed, reserved bit 17 set, debug allowed
+        let policy = decode_policy(0x000b_0133);
+        assert_eq!(policy.abi_major, 1);
+        assert_eq!(policy.abi_minor, 0x33);
+        assert!(policy.smt_allowed);
+        assert!(policy.debug_allowed);
+        assert!(!policy.migrate_ma_allowed);
+        assert!(!policy.single_socket_required);
+        assert_eq!(policy.abi().to_string(), "1.51");
+    }
+
+    #[test]
+    fn test_abi_version() {
+        let min: AbiVersion = "1.51".parse().unwrap();
+        assert_eq!(
+            min,
+            AbiVersion {
+                major: 1,
+                minor: 51
+            }
+        );
+        assert!(decode_policy(0x0003_0133).abi() >= min);
+        assert!(decode_policy(0x0003_0132).abi() < min);
+        // The major version is compared first
+        assert!(decode_policy(0x0003_0200).abi() > min);
+
+        assert!("1".parse::<AbiVersion>().is_err());
+        assert!("1.256".parse::<AbiVersion>().is_err());
+    }
+
+    #[test]
+    fn test_policy_requirements() {
+        let requirements: PolicyRequirements = "debug=false, smt=true".parse().unwrap();
+        assert!(requirements.check(&decode_policy(0x0003_0000)).is_ok());
+        assert!(requirements.check(&decode_policy(0x000b_0000)).is_err());
+
+        assert!("debug".parse::<PolicyRequirements>().is_err());
+        assert!("debug=no".parse::<PolicyRequirements>().is_err());
+        assert!("sriov=false".parse::<PolicyRequirements>().is_err());
+    }
+}
@@ -6,21 +6,313 @@ use super::*;
 use std::{
     fs::{self, File, OpenOptions},
     io::{Read, Write},
+    ops::Range,
     path::PathBuf,
 };
 
 use anyhow::{anyhow, Result};
+use certs::CertFormat;
+use fetch::{Endorsement, ProcType};
+use openssl::sha::Sha384;
 use rand::{thread_rng, RngCore};
-use sev::firmware::guest::{AttestationReport, Firmware};
+use sev::firmware::{
+    guest::{AttestationReport, Firmware},
+    host::{CertTableEntry, CertType},
+};
+
+// Size of a bin-formatted attestation report.
+pub const REPORT_SIZE: usize = 0x4A0;
+
+// Parse a bin-formatted attestation report from memory. The bytes must hold exactly one report.
+pub fn parse_report(bytes: &[u8]) -> Result<AttestationReport, anyhow::Error> {
+    if bytes.len() != REPORT_SIZE {
+        return Err(anyhow!(
+            "An attestation report is {REPORT_SIZE} bytes long, but {} bytes were given{}.",
+            bytes.len(),
+            if bytes.len() > REPORT_SIZE {
+                " (extended reports are read with --extended)"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    bincode::deserialize(bytes).context("Could not parse attestation report.")
+}
+
+// Decode an attestation report saved as hex or base64 text, leaving binary ones as they are.
+// A file is only taken as text if it isn't report sized and holds nothing but printable ASCII.
+pub fn decode_report_text(bytes: &[u8]) -> Result<Vec<u8>> {
+    let is_text = bytes.len() != REPORT_SIZE
+        && !bytes.is_empty()
+        && bytes
+            .iter()
+            .all(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace());
+    if !is_text {
+        return Ok(bytes.to_vec());
+    }
+
+    let text: String = String::from_utf8_lossy(bytes)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let hex_text = text.strip_prefix("0x").unwrap_or(&text);
+    let (encoding, decoded) = if hex_text.chars().all(|c| c.is_ascii_hexdigit()) {
+        (
+            "hex",
+            hex::decode(hex_text).context("Could not decode hex attestation report.")?,
+        )
+    } else {
+        (
+            "base64",
+            openssl::base64::decode_block(&text)
+                .context("Could not decode base64 attestation report.")?,
+        )
+    };
+    if decoded.len() != REPORT_SIZE {
+        return Err(anyhow!(
+            "The {encoding} encoded attestation report decodes to {} bytes, but an attestation report is {REPORT_SIZE} bytes long.",
+            decoded.len()
+        ));
+    }
 
-// Read a bin-formatted attestation report.
+    Ok(decoded)
+}
+
+// Read an attestation report, either bin-formatted or encoded as hex or base64.
 pub fn read_report(att_report_path: PathBuf) -> Result<AttestationReport, anyhow::Error> {
-    let attestation_file = fs::File::open(att_report_path)?;
+    let bytes = fs::read(att_report_path)?;
+
+    parse_report(&decode_report_text(&bytes)?)
+}
+
+// Read an attestation report from any reader, such as stdin, either bin-formatted or encoded
+// as hex or base64.
+pub fn read_report_from_reader<R: Read>(mut reader: R) -> Result<AttestationReport, anyhow::Error> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .context("Could not read attestation report.")?;
+
+    parse_report(&decode_report_text(&bytes)?)
+}
+
+// Size of a GHCB certificate table entry: a GUID followed by the offset and length of the certificate.
+const CERT_TABLE_ENTRY_SIZE: usize = 24;
+
+// Parse a GHCB certificate table, as the host provides it with an extended report. Entries
+// with GUIDs other than the AMD certificate types are skipped.
+pub fn parse_cert_table(table: &[u8]) -> Result<Vec<CertTableEntry>> {
+    let known = [
+        CertType::ARK,
+        CertType::ASK,
+        CertType::VCEK,
+        CertType::VLEK,
+        CertType::CRL,
+    ];
+    let mut entries = Vec::new();
+
+    for entry in table.chunks(CERT_TABLE_ENTRY_SIZE) {
+        if entry.len() < CERT_TABLE_ENTRY_SIZE {
+            return Err(anyhow!("Certificate table is not terminated."));
+        }
+        if entry.iter().all(|byte| *byte == 0) {
+            return Ok(entries);
+        }
+
+        let guid = hex::encode(&entry[..16]);
+        let guid = format!(
+            "{}-{}-{}-{}-{}",
+            &guid[..8],
+            &guid[8..12],
+            &guid[12..16],
+            &guid[16..20],
+            &guid[20..]
+        );
+        let offset = u32::from_le_bytes(entry[16..20].try_into()?) as usize;
+        let length = u32::from_le_bytes(entry[20..24].try_into()?) as usize;
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| table.get(offset..end))
+            .ok_or_else(|| anyhow!("Certificate {guid} lies outside of the certificate table."))?;
+
+        if let Some(cert_type) = known.iter().find(|cert_type| cert_type.to_string() == guid) {
+            entries.push(CertTableEntry::new(cert_type.clone(), data.to_vec()));
+        }
+    }
+
+    Err(anyhow!("Certificate table is not terminated."))
+}
+
+// Parse an extended report: a bin-formatted attestation report, followed by the certificate
+// table the host provided with it, if any.
+pub fn parse_ext_report(bytes: &[u8]) -> Result<(AttestationReport, Vec<CertTableEntry>)> {
+    let att_report = parse_report(&bytes[..bytes.len().min(REPORT_SIZE)])?;
 
-    let attestation_report = bincode::deserialize_from(attestation_file)
-        .context("Could not parse attestation report.")?;
+    let certs = match &bytes[REPORT_SIZE..] {
+        [] => vec![],
+        table => parse_cert_table(table).context("Could not parse certificate table.")?,
+    };
 
-    Ok(attestation_report)
+    Ok((att_report, certs))
+}
+
+// Attestation report versions whose layout is known.
+pub const REPORT_VERSIONS: [u32; 2] = [2, 3];
+
+// Bytes of a version 2 report its signature covers: everything before the signature at 0x2A0.
+pub const SIGNED_RANGE_V2: Range<usize> = 0x0..0x2A0;
+
+// Version 3 adds the CPUID fields in what was reserved, and signs the same bytes as version 2.
+pub const SIGNED_RANGE_V3: Range<usize> = 0x0..0x2A0;
+
+// Byte range of a report covered by its signature, for the report versions whose layout is
+// known. Unknown versions are an error, rather than a guess at the bytes to hash.
+pub fn signed_range(version: u32) -> Result<Range<usize>> {
+    match version {
+        2 => Ok(SIGNED_RANGE_V2),
+        3 => Ok(SIGNED_RANGE_V3),
+        version => Err(anyhow!("Unsupported attestation report version {version}.")),
+    }
+}
+
+// Get the bytes of the attestation report covered by its signature, according to the
+// report version.
+pub fn signed_report_bytes(att_report: &AttestationReport) -> Result<Vec<u8>> {
+    let signed_range = signed_range(att_report.version)?;
+
+    let report_bytes = bincode::serialize(att_report)
+        .context("Failed to get the signed bytes from the attestation report.")?;
+
+    Ok(report_bytes[signed_range].to_vec())
+}
+
+// SHA-384 digest of the signed part of the attestation report, which its signature covers.
+pub fn report_digest(att_report: &AttestationReport) -> Result<[u8; 48]> {
+    let mut hasher: Sha384 = Sha384::new();
+    hasher.update(&signed_report_bytes(att_report)?);
+
+    Ok(hasher.finish())
+}
+
+// Key information field of a report, which the sev crate keeps private.
+fn key_info(att_report: &AttestationReport) -> Result<u32> {
+    let report_bytes =
+        bincode::serialize(att_report).context("Could not serialize attestation report.")?;
+
+    Ok(u32::from_le_bytes(report_bytes[0x48..0x4C].try_into()?))
+}
+
+// Whether the AUTHOR_KEY_EN bit of the key information field says the ID key was certified
+// by an author key.
+pub fn author_key_enabled(att_report: &AttestationReport) -> Result<bool> {
+    Ok(key_info(att_report)? & 1 != 0)
+}
+
+// Get the key that signed a report from the SIGNING_KEY bits of its key information field,
+// or None if the report is unsigned.
+pub fn get_signing_key(att_report: &AttestationReport) -> Result<Option<Endorsement>> {
+    let key_info = key_info(att_report)?;
+
+    match (key_info >> 2) & 0b111 {
+        0 => Ok(Some(Endorsement::Vcek)),
+        1 => Ok(Some(Endorsement::Vlek)),
+        7 => Ok(None),
+        key => Err(anyhow!("Unknown signing key {key} in attestation report.")),
+    }
+}
+
+// Reserved regions of a report, as offset and length, which firmware leaves zero. Version 3
+// carries the CPUID family, model and stepping in the first bytes of the one at 0x188.
+fn reserved_regions(version: u32) -> [(usize, usize); 6] {
+    let cpuid_len = if version >= 3 { 3 } else { 0 };
+    [
+        (0x4C, 4),
+        (0x188 + cpuid_len, 24 - cpuid_len),
+        (0x1EB, 1),
+        (0x1EF, 1),
+        (0x1F8, 168),
+        // Everything of the signature past its R and S components
+        (0x330, 368),
+    ]
+}
+
+// Describe the reserved parts of a report that aren't zero, as runs of byte offsets.
+pub fn nonzero_reserved(att_report: &AttestationReport) -> Result<Vec<String>> {
+    let report_bytes =
+        bincode::serialize(att_report).context("Could not serialize attestation report.")?;
+    let mut nonzero = Vec::new();
+
+    // Only the AUTHOR_KEY_EN, MASK_CHIP_KEY and SIGNING_KEY bits of the key information are defined
+    if key_info(att_report)? & !0x1F != 0 {
+        nonzero.push("0x48 (key information bits 5-31)".to_string());
+    }
+
+    for (offset, len) in reserved_regions(att_report.version) {
+        let mut run: Option<(usize, usize)> = None;
+        for (index, byte) in report_bytes[offset..offset + len].iter().enumerate() {
+            let at = offset + index;
+            match (run, *byte != 0) {
+                (Some((start, _)), true) => run = Some((start, at)),
+                (None, true) => run = Some((at, at)),
+                (Some(ended), false) => {
+                    nonzero.push(offset_run(ended));
+                    run = None;
+                }
+                (None, false) => (),
+            }
+        }
+        if let Some(ended) = run {
+            nonzero.push(offset_run(ended));
+        }
+    }
+
+    Ok(nonzero)
+}
+
+fn offset_run((start, end): (usize, usize)) -> String {
+    if start == end {
+        format!("{start:#x}")
+    } else {
+        format!("{start:#x}-{end:#x}")
+    }
+}
+
+// Detect the processor generation that produced a report from the CPUID family and model
+// fields, which reports carry starting with version 3.
+pub fn get_processor_model(att_report: &AttestationReport) -> Result<ProcType> {
+    detect_processor_model(att_report).map(|(proc_model, _)| proc_model)
+}
+
+// Detect the processor generation of a report, along with the fields it was derived from.
+pub fn detect_processor_model(att_report: &AttestationReport) -> Result<(ProcType, String)> {
+    if att_report.version < 3 {
+        return Err(anyhow!(
+            "Attestation report version {} does not carry the CPUID information needed to detect the processor model.",
+            att_report.version
+        ));
+    }
+
+    let report_bytes =
+        bincode::serialize(att_report).context("Could not serialize attestation report.")?;
+    let (family, model) = (report_bytes[0x188], report_bytes[0x189]);
+
+    let proc_model = match (family, model) {
+        (0x19, 0x00..=0x0F) => ProcType::Milan,
+        // Bergamo and Siena share the Genoa KDS endpoint and TCB layout.
+        (0x19, 0x10..=0x1F | 0xA0..=0xAF) => ProcType::Genoa,
+        (0x1A, 0x00..=0x11) => ProcType::Turin,
+        _ => {
+            return Err(anyhow!(
+                "Unknown processor CPUID family {family:#x} model {model:#x} in attestation report."
+            ))
+        }
+    };
+
+    Ok((
+        proc_model,
+        format!("CPUID family {family:#x} model {model:#x} at offset 0x188 of the report"),
+    ))
 }
 
 // Create 64 random bytes of data for attestation report request
@@ -50,16 +342,43 @@ pub struct ReportArgs {
     /// Provide file with data for attestation-report request. If provided
     /// with random flag, then the random data will be written in the
     /// provided path.
-    #[arg(value_name = "request-file", required = true)]
-    pub request_file: PathBuf,
+    #[arg(value_name = "request-file", required_unless_present = "data")]
+    pub request_file: Option<PathBuf>,
+
+    /// Report data to request the report with, either 64 hex encoded bytes or a file holding
+    /// the 64 bytes. Used instead of the request file.
+    #[arg(long, value_name = "data", conflicts_with_all = ["request_file", "random", "platform"])]
+    pub data: Option<String>,
+
+    /// Zero-pad report data given with --data that is shorter than 64 bytes.
+    #[arg(long, requires = "data", conflicts_with = "strict")]
+    pub pad: bool,
+
+    /// Reject report data given with --data that is not exactly 64 bytes (the default).
+    #[arg(long, requires = "data")]
+    pub strict: bool,
 
     /// Expect that the 64-byte report data will already be provided by the platform provider.
     #[arg(short, long, conflicts_with = "random")]
     pub platform: bool,
+
+    /// Request an extended report, and write the certificates provided by the host after it.
+    #[arg(long, conflicts_with = "platform")]
+    pub extended: bool,
+
+    /// Directory to also write the certificates of an extended report to, as ark, ask and vcek.
+    #[arg(long, value_name = "certs-out", requires = "extended")]
+    pub certs_out: Option<PathBuf>,
 }
 
 impl ReportArgs {
     pub fn verify(&self, hyperv: bool) -> Result<()> {
+        if let Some(vmpl) = self.vmpl.filter(|vmpl| *vmpl > 3) {
+            return Err(anyhow!(
+                "VMPL {vmpl} is out of range. Specify a VMPL between 0 and 3."
+            ));
+        }
+
         if self.random && self.platform {
             return Err(anyhow!(
                 "--random and --platform both enabled (not allowed). Consult man page."
@@ -72,6 +391,18 @@ impl ReportArgs {
             ));
         }
 
+        if self.data.is_some() && hyperv {
+            return Err(anyhow!(
+                "--data enabled yet Hyper-V guest detected (not allowed). Consult man page."
+            ));
+        }
+
+        if self.extended && hyperv {
+            return Err(anyhow!(
+                "--extended enabled yet Hyper-V guest detected (not allowed). Consult man page."
+            ));
+        }
+
         if self.platform && !hyperv {
             return Err(anyhow!("--platform enabled yet Hyper-V guest not detected (not allowed). Consult man page."));
         }
@@ -88,18 +419,101 @@ fn request_hardware_report(
     hyperv::report::get(vmpl.unwrap_or(0))
 }
 
+// Open the guest firmware device, telling apart a guest without SEV-SNP from other failures.
+pub fn open_guest_firmware() -> Result<Firmware> {
+    const SEV_GUEST: &str = "/dev/sev-guest";
+
+    Firmware::open().map_err(|err| {
+        let err = anyhow::Error::new(err);
+        if std::path::Path::new(SEV_GUEST).exists() {
+            err.context(format!("unable to open {SEV_GUEST}"))
+        } else {
+            err.context(format!(
+                "{SEV_GUEST} does not exist, this does not appear to be an SEV-SNP guest"
+            ))
+        }
+    })
+}
+
 #[cfg(not(feature = "hyperv"))]
 fn request_hardware_report(data: Option<[u8; 64]>, vmpl: Option<u32>) -> Result<AttestationReport> {
-    let mut fw = Firmware::open().context("unable to open /dev/sev-guest")?;
+    let mut fw = open_guest_firmware()?;
     fw.get_report(None, data, vmpl)
         .context("unable to fetch attestation report")
 }
 
+#[cfg(feature = "hyperv")]
+fn request_extended_report(
+    _data: Option<[u8; 64]>,
+    _vmpl: Option<u32>,
+) -> Result<(AttestationReport, Vec<CertTableEntry>)> {
+    Err(anyhow!(
+        "Extended reports are not available on Hyper-V guests."
+    ))
+}
+
+#[cfg(not(feature = "hyperv"))]
+fn request_extended_report(
+    data: Option<[u8; 64]>,
+    vmpl: Option<u32>,
+) -> Result<(AttestationReport, Vec<CertTableEntry>)> {
+    let mut fw = open_guest_firmware()?;
+    let (report, certs) = fw
+        .get_ext_report(None, data, vmpl)
+        .context("unable to fetch extended attestation report")?;
+
+    Ok((report, certs.unwrap_or_default()))
+}
+
+// Report data given with --data, either hex encoded or the path of a file holding the raw bytes.
+// Data shorter than 64 bytes is zero-padded at the end with pad, and rejected otherwise.
+fn parse_report_data(input: &str, pad: bool) -> Result<[u8; 64]> {
+    let hex_data = input.trim().trim_start_matches("0x");
+    let is_hex = !hex_data.is_empty() && hex_data.chars().all(|c| c.is_ascii_hexdigit());
+
+    let mut bytes = if is_hex && !std::path::Path::new(input).exists() {
+        if hex_data.len() % 2 == 1 && !pad {
+            return Err(anyhow!(
+                "Report data has an odd number of hex digits ({}). Use --pad to prefix it with a 0.",
+                hex_data.len()
+            ));
+        }
+        hex::decode(format!("{}{hex_data}", "0".repeat(hex_data.len() % 2)))?
+    } else {
+        fs::read(input).with_context(|| {
+            format!("--data is neither hex encoded bytes nor a readable file: {input}")
+        })?
+    };
+
+    if bytes.len() > 64 {
+        return Err(anyhow!(
+            "Report data must be 64 bytes, but {} were given.",
+            bytes.len()
+        ));
+    }
+    if bytes.len() < 64 {
+        if !pad {
+            return Err(anyhow!(
+                "Report data must be 64 bytes, but {} were given. Use --pad to zero-pad it.",
+                bytes.len()
+            ));
+        }
+        bytes.resize(64, 0);
+    }
+
+    let mut data = [0u8; 64];
+    data.copy_from_slice(&bytes);
+
+    Ok(data)
+}
+
 // Request attestation report and write it into a file
-pub fn get_report(args: ReportArgs, hv: bool) -> Result<()> {
+pub fn get_report(args: ReportArgs, hv: bool, cert_format: Option<CertFormat>) -> Result<()> {
     args.verify(hv)?;
 
-    let data: Option<[u8; 64]> = if args.random {
+    let data: Option<[u8; 64]> = if let Some(data) = &args.data {
+        Some(parse_report_data(data, args.pad)?)
+    } else if args.random {
         Some(create_random_request())
     } else if args.platform {
         None
@@ -107,15 +521,23 @@ pub fn get_report(args: ReportArgs, hv: bool) -> Result<()> {
         /*
          * Read from the request file.
          */
+        let request_file = args
+            .request_file
+            .as_ref()
+            .ok_or_else(|| anyhow!("No request file provided."))?;
         let mut bytes = [0u8; 64];
-        let mut file = File::open(&args.request_file)?;
+        let mut file = File::open(request_file)?;
         file.read_exact(&mut bytes)
             .context("unable to read 64 bytes from REQUEST_FILE")?;
 
         Some(bytes)
     };
 
-    let report = request_hardware_report(data, args.vmpl)?;
+    let (report, certs) = if args.extended {
+        request_extended_report(data, args.vmpl)?
+    } else {
+        (request_hardware_report(data, args.vmpl)?, vec![])
+    };
 
     /*
      * Serialize and write attestation report.
@@ -129,14 +551,35 @@ pub fn get_report(args: ReportArgs, hv: bool) -> Result<()> {
     bincode::serialize_into(&mut file, &report)
         .context("Could not serialize attestation report into file.")?;
 
+    /*
+     * Append the certificate table of an extended report.
+     */
+    if !certs.is_empty() {
+        let table = CertTableEntry::cert_table_to_vec_bytes(&certs)
+            .context("Could not serialize certificate table.")?;
+        file.write_all(&table)
+            .context("Could not write certificate table into file.")?;
+    }
+
+    /*
+     * Split the certificate table into a directory verify can use.
+     */
+    if let Some(certs_out) = &args.certs_out {
+        let encoding = certs::resolve_cert_format(None, cert_format)?;
+        certs::write_cert_table(certs_out, &certs, encoding)
+            .context("The report was written, but its certificates were not")?;
+    }
+
     /*
      * Write reports report data (only for --random or --platform).
      */
-    if args.random {
-        reqdata_write(args.request_file, &report).context("unable to write random request data")?;
-    } else if args.platform {
-        reqdata_write(args.request_file, &report)
-            .context("unable to write platform request data")?;
+    if let Some(request_file) = args.request_file {
+        if args.random {
+            reqdata_write(request_file, &report).context("unable to write random request data")?;
+        } else if args.platform {
+            reqdata_write(request_file, &report)
+                .context("unable to write platform request data")?;
+        }
     }
 
     Ok(())
@@ -167,3 +610,235 @@ pub fn write_hex(file: &mut File, data: &[u8]) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vmpl_range() {
+        let args = |vmpl: &str| {
+            ReportArgs::try_parse_from(["report", "report.bin", "request.txt", "--vmpl", vmpl])
+                .unwrap()
+        };
+
+        assert!(args("0").verify(false).is_ok());
+        assert!(args("3").verify(false).is_ok());
+        let err = args("4").verify(false).unwrap_err();
+        assert!(err.to_string().contains("between 0 and 3"));
+    }
+
+    #[test]
+    fn test_parse_report_data() {
+        let hex_data = "ab".repeat(64);
+        assert_eq!(parse_report_data(&hex_data, false).unwrap(), [0xAB; 64]);
+        assert_eq!(
+            parse_report_data(&format!("0x{hex_data}"), false).unwrap(),
+            [0xAB; 64]
+        );
+
+        let path = std::env::temp_dir().join(format!("snpguest-data-{}", std::process::id()));
+        fs::write(&path, [0xCD; 64]).unwrap();
+        assert_eq!(
+            parse_report_data(path.to_str().unwrap(), false).unwrap(),
+            [0xCD; 64]
+        );
+        fs::write(&path, [0xCD; 32]).unwrap();
+        let err = parse_report_data(path.to_str().unwrap(), false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Report data must be 64 bytes, but 32 were given. Use --pad to zero-pad it."
+        );
+        let data = parse_report_data(path.to_str().unwrap(), true).unwrap();
+        assert_eq!(data[..32], [0xCD; 32]);
+        assert_eq!(data[32..], [0; 32]);
+        fs::write(&path, [0xCD; 65]).unwrap();
+        let err = parse_report_data(path.to_str().unwrap(), true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Report data must be 64 bytes, but 65 were given."
+        );
+        fs::remove_file(&path).unwrap();
+
+        assert!(parse_report_data("abcd", false).is_err());
+        let err = parse_report_data("abc", false).unwrap_err();
+        assert!(err.to_string().contains("odd number of hex digits (3)"));
+        let data = parse_report_data("abc", true).unwrap();
+        assert_eq!(data[..2], [0x0A, 0xBC]);
+        assert_eq!(data[2..], [0; 62]);
+    }
+
+    #[test]
+    fn test_read_report_from_reader() {
+        let mut att_report = AttestationReport::default();
+        att_report.version = 2;
+        att_report.guest_svn = 7;
+        let bytes = bincode::serialize(&att_report).unwrap();
+
+        let read = read_report_from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(read.version, 2);
+        assert_eq!(read.guest_svn, 7);
+
+        assert!(read_report_from_reader(&bytes[..0x100]).is_err());
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let bytes = bincode::serialize(&AttestationReport::default()).unwrap();
+        assert_eq!(bytes.len(), REPORT_SIZE);
+        assert!(parse_report(&bytes).is_ok());
+
+        let err = parse_report(&bytes[..0x100]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "An attestation report is 1184 bytes long, but 256 bytes were given."
+        );
+        let err = parse_report(&[bytes.as_slice(), &[0; 8]].concat()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("1192 bytes were given (extended reports"));
+    }
+
+    #[test]
+    fn test_decode_report_text() {
+        let mut report = AttestationReport::default();
+        report.version = 3;
+        let bytes = bincode::serialize(&report).unwrap();
+        assert_eq!(decode_report_text(&bytes).unwrap(), bytes);
+
+        let hex_text = format!("0x{}\n", hex::encode(&bytes));
+        assert_eq!(decode_report_text(hex_text.as_bytes()).unwrap(), bytes);
+        let base64_text = openssl::base64::encode_block(&bytes);
+        assert_eq!(decode_report_text(base64_text.as_bytes()).unwrap(), bytes);
+        assert_eq!(
+            read_report_from_reader(base64_text.as_bytes())
+                .unwrap()
+                .version,
+            3
+        );
+
+        let err = decode_report_text(hex::encode(&bytes[..0x100]).as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The hex encoded attestation report decodes to 256 bytes, but an attestation report is 1184 bytes long."
+        );
+        assert!(decode_report_text(b"not a report!").is_err());
+    }
+
+    #[test]
+    fn test_detect_processor_model() {
+        let mut bytes = bincode::serialize(&AttestationReport::default()).unwrap();
+        let detect = |bytes: &[u8]| detect_processor_model(&bincode::deserialize(bytes).unwrap());
+
+        // Version 2 reports don't carry the CPUID fields
+        assert!(detect(&bytes).is_err());
+        bytes[0] = 3;
+        bytes[0x188] = 0x19;
+        bytes[0x189] = 0x11;
+        assert_eq!(
+            detect(&bytes).unwrap(),
+            (
+                ProcType::Genoa,
+                "CPUID family 0x19 model 0x11 at offset 0x188 of the report".to_string()
+            )
+        );
+        bytes[0x188] = 0x17;
+        assert!(detect(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_get_signing_key() {
+        let mut bytes = bincode::serialize(&AttestationReport::default()).unwrap();
+        let signing_key = |bytes: &[u8]| get_signing_key(&bincode::deserialize(bytes).unwrap());
+
+        assert_eq!(signing_key(&bytes).unwrap(), Some(Endorsement::Vcek));
+        assert!(!author_key_enabled(&bincode::deserialize(&bytes).unwrap()).unwrap());
+        // Author key enabled, signed with the VLEK
+        bytes[0x48] = 0b101;
+        assert_eq!(signing_key(&bytes).unwrap(), Some(Endorsement::Vlek));
+        assert!(author_key_enabled(&bincode::deserialize(&bytes).unwrap()).unwrap());
+        bytes[0x48] = 0b11100;
+        assert_eq!(signing_key(&bytes).unwrap(), None);
+        bytes[0x48] = 0b01000;
+        assert!(signing_key(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_nonzero_reserved() {
+        let mut att_report = AttestationReport::default();
+        att_report.version = 3;
+        let mut bytes = bincode::serialize(&att_report).unwrap();
+        // The CPUID fields of a version 3 report aren't reserved
+        bytes[0x188] = 0x19;
+        let nonzero = |bytes: &[u8]| nonzero_reserved(&bincode::deserialize(bytes).unwrap());
+        assert!(nonzero(&bytes).unwrap().is_empty());
+
+        bytes[0x4A] = 1;
+        bytes[0x49] = 0x80;
+        bytes[0x1F8..0x1FC].copy_from_slice(&[1, 2, 0, 3]);
+        bytes[0x49F] = 1;
+        assert_eq!(
+            nonzero(&bytes).unwrap(),
+            [
+                "0x48 (key information bits 5-31)",
+                "0x1f8-0x1f9",
+                "0x1fb",
+                "0x49f"
+            ]
+        );
+
+        // The same byte is reserved in version 2
+        let mut bytes = bincode::serialize(&AttestationReport::default()).unwrap();
+        bytes[0x188] = 0x19;
+        assert_eq!(nonzero(&bytes).unwrap(), ["0x188"]);
+    }
+
+    #[test]
+    fn test_signed_report_bytes() {
+        for version in REPORT_VERSIONS {
+            let mut att_report = AttestationReport::default();
+            att_report.version = version;
+            let report_bytes = bincode::serialize(&att_report).unwrap();
+
+            let signed_bytes = signed_report_bytes(&att_report).unwrap();
+            assert_eq!(signed_range(version).unwrap(), 0x0..0x2A0);
+            assert_eq!(signed_bytes.as_slice(), &report_bytes[0x0..0x2A0]);
+
+            let digest = openssl::sha::sha384(&report_bytes[0x0..0x2A0]);
+            assert_eq!(report_digest(&att_report).unwrap(), digest);
+        }
+    }
+
+    #[test]
+    fn test_signed_report_bytes_unknown_version() {
+        let mut att_report = AttestationReport::default();
+        att_report.version = 1;
+        assert!(signed_range(1).is_err());
+        assert!(signed_report_bytes(&att_report).is_err());
+        assert!(report_digest(&att_report).is_err());
+    }
+
+    #[test]
+    fn test_parse_ext_report() {
+        let certs = vec![
+            CertTableEntry::new(CertType::ARK, b"ark".to_vec()),
+            CertTableEntry::new(CertType::VCEK, b"vcek".to_vec()),
+        ];
+        let mut bytes = bincode::serialize(&AttestationReport::default()).unwrap();
+        let report_size = bytes.len();
+
+        let (_, parsed) = parse_ext_report(&bytes).unwrap();
+        assert!(parsed.is_empty());
+
+        bytes.extend(CertTableEntry::cert_table_to_vec_bytes(&certs).unwrap());
+        let (_, parsed) = parse_ext_report(&bytes).unwrap();
+        assert_eq!(parsed, certs);
+
+        // A certificate running past the end of the table
+        bytes.truncate(bytes.len() - 1);
+        assert!(parse_ext_report(&bytes).is_err());
+        // A table without its terminating entry
+        bytes.truncate(report_size + CERT_TABLE_ENTRY_SIZE);
+        assert!(parse_ext_report(&bytes).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file contains the subcommand for checking this build against known answers, to catch a broken environment (such as OpenSSL) before real verifications are trusted.
+
+use super::*;
+
+use colorful::*;
+use openssl::{
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+};
+use sev::certs::snp::{builtin, Certificate, Verifiable};
+
+type SelfTestFn = fn() -> Result<()>;
+
+// Name and check of every self-test, in the order they are run.
+const SELF_TESTS: [(&str, SelfTestFn); 4] = [
+    ("Parse the bundled AMD certificates", parse_builtin_certs),
+    (
+        "Verify the bundled AMD certificate chains",
+        verify_builtin_chains,
+    ),
+    ("Match the TCB of the dummy VCEK", verify::self_test_vcek),
+    ("Sign and verify with ECDSA P-384", ecdsa_round_trip),
+];
+
+// ARK and ASK bundled for each processor model with one.
+fn builtin_cas() -> [(&'static str, &'static [u8], &'static [u8]); 2] {
+    [
+        ("Milan", builtin::milan::ARK, builtin::milan::ASK),
+        ("Genoa", builtin::genoa::ARK, builtin::genoa::ASK),
+    ]
+}
+
+fn parse_builtin_certs() -> Result<()> {
+    for (model, ark, ask) in builtin_cas() {
+        for (cert, expected) in [(ark, "ark"), (ask, "ask")] {
+            let role = verify::cert_role(&certs::cert_from_bytes(cert)?)?;
+            if role != expected {
+                return Err(anyhow::anyhow!(
+                    "The {model} {} was read as an {}.",
+                    expected.to_uppercase(),
+                    role.to_uppercase()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_builtin_chains() -> Result<()> {
+    for (model, ark, ask) in builtin_cas() {
+        let ark = Certificate::from_pem(ark)?;
+        let ask = Certificate::from_pem(ask)?;
+        (&ark, &ark)
+            .verify()
+            .with_context(|| format!("The {model} ARK is not self-signed"))?;
+        (&ark, &ask)
+            .verify()
+            .with_context(|| format!("The {model} ASK was not signed by the ARK"))?;
+        if (&ask, &ark).verify().is_ok() {
+            return Err(anyhow::anyhow!(
+                "The {model} ARK was accepted as signed by the ASK."
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Sign a digest with a fresh key, the way a report is signed, and verify it before and after
+// tampering with the digest.
+fn ecdsa_round_trip() -> Result<()> {
+    let group = EcGroup::from_curve_name(Nid::SECP384R1)?;
+    let key = EcKey::generate(&group)?;
+    let public_key = EcKey::from_public_key(&group, key.public_key())?;
+
+    let mut digest = hash(MessageDigest::sha384(), b"snpguest selftest")?.to_vec();
+    let sig = EcdsaSig::sign(&digest, &key)?;
+    if !sig.verify(&digest, &public_key)? {
+        return Err(anyhow::anyhow!(
+            "A valid ECDSA P-384 signature was rejected."
+        ));
+    }
+    digest[0] ^= 1;
+    if sig.verify(&digest, &public_key)? {
+        return Err(anyhow::anyhow!(
+            "An ECDSA P-384 signature of another digest was accepted."
+        ));
+    }
+
+    Ok(())
+}
+
+// Run every self-test, printing PASS or FAIL for each in console.
+pub fn cmd(quiet: bool) -> Result<()> {
+    let mut failures = 0;
+    for (name, run) in SELF_TESTS {
+        let result = run();
+        if !quiet {
+            match &result {
+                Ok(()) => println!("[ {} ] - {name}", "PASS".green()),
+                Err(err) => println!("[ {} ] - {name}: {err:#}", "FAIL".red()),
+            }
+        }
+        if result.is_err() {
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow::anyhow!(
+            "{failures} of {} self-tests reported a failure",
+            SELF_TESTS.len()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_tests() {
+        for (name, run) in SELF_TESTS {
+            assert!(run().is_ok(), "{name}");
+        }
+        assert!(cmd(true).is_ok());
+    }
+}
@@ -3,15 +3,34 @@
 
 use super::*;
 
-use certs::{convert_path_to_cert, CertPaths};
+use certs::convert_path_to_cert;
+use fetch::{CacheArgs, Endorsement, KdsArgs, ProcType, Tcb, TcbRelation};
+use policy::{decode_policy, AbiVersion, PolicyRequirements};
 
 use std::{
-    io::ErrorKind,
+    fs,
+    io::{ErrorKind, IsTerminal},
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
-use openssl::{ecdsa::EcdsaSig, sha::Sha384};
-use sev::certs::snp::Chain;
+use asn1_rs::FromDer;
+use colorful::{Color, Colorful};
+use log::{debug, trace};
+use openssl::{
+    asn1::Asn1Time,
+    ecdsa::EcdsaSig,
+    nid::Nid,
+    x509::{CrlStatus, X509Crl, X509},
+};
+use sev::{
+    certs::snp::{ca, Certificate, Chain, Verifiable},
+    firmware::host::{CertTableEntry, CertType},
+};
+use x509_parser::{certificate::X509Certificate, x509::X509Name};
+
+pub use attestation::self_test_vcek;
 
 #[derive(Subcommand)]
 pub enum VerifyCmd {
@@ -20,397 +39,3518 @@ pub enum VerifyCmd {
 
     /// Verify the attestation report.
     Attestation(attestation::Args),
+
+    /// Verify the certificate chain and then the attestation report in one pass.
+    All(all::Args),
+
+    /// Verify an extended report offline, against the certificate chain embedded in it.
+    Extended(all::ExtendedArgs),
+
+    /// Verify many attestation reports, with a summary of the results.
+    AttestationBatch(attestation_batch::Args),
+
+    /// Verify a stream of length-prefixed attestation reports read from stdin, printing one
+    /// JSON result per line.
+    AttestationStream(attestation_batch::StreamArgs),
 }
 
-pub fn cmd(cmd: VerifyCmd, quiet: bool) -> Result<()> {
-    match cmd {
+pub fn cmd(cmd: VerifyCmd, timeout: u64, color: ColorMode, quiet: bool) -> Result<()> {
+    STYLED.store(color.enabled(), Ordering::Relaxed);
+    let text = match &cmd {
+        VerifyCmd::Certs(_) => true,
+        VerifyCmd::Attestation(args) => args.output == VerifyOutput::Text,
+        VerifyCmd::All(args) => args.attestation.output == VerifyOutput::Text,
+        VerifyCmd::Extended(args) => args.attestation.output == VerifyOutput::Text,
+        VerifyCmd::AttestationBatch(_) | VerifyCmd::AttestationStream(_) => false,
+    };
+
+    let result = match cmd {
         VerifyCmd::Certs(args) => certificate_chain::validate_cc(args, quiet),
-        VerifyCmd::Attestation(args) => attestation::verify_attestation(args, quiet),
+        VerifyCmd::Attestation(mut args) => {
+            args.kds.timeout = timeout;
+            attestation::verify_attestation(args, quiet)
+        }
+        VerifyCmd::All(mut args) => {
+            args.attestation.kds.timeout = timeout;
+            all::verify_all(args, quiet)
+        }
+        VerifyCmd::Extended(args) => all::verify_extended(args, quiet),
+        VerifyCmd::AttestationBatch(mut args) => {
+            args.kds.timeout = timeout;
+            attestation_batch::verify_batch(args, quiet)
+        }
+        VerifyCmd::AttestationStream(mut args) => {
+            args.kds.timeout = timeout;
+            attestation_batch::verify_stream(args, quiet)
+        }
+    };
+
+    // Close the list of passed checks with the one that failed
+    if let (Err(err), true) = (&result, text && !quiet && styled()) {
+        check_failed(&err.to_string());
     }
+
+    result
 }
 
-// Find a certificate in specified directory according to its extension
-pub fn find_cert_in_dir(dir: &Path, cert: &str) -> Result<PathBuf, anyhow::Error> {
-    if dir.join(format!("{cert}.pem")).exists() {
-        Ok(dir.join(format!("{cert}.pem")))
-    } else if dir.join(format!("{cert}.der")).exists() {
-        Ok(dir.join(format!("{cert}.der")))
-    } else {
-        return Err(anyhow::anyhow!("{cert} certificate not found in directory"));
+// When to print check results with colored symbols.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only when stdout is a terminal.
+    Auto,
+
+    /// Always, even when stdout is piped.
+    Always,
+
+    /// Never, printing plain text.
+    Never,
+}
+
+impl ColorMode {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => std::io::stdout().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
     }
 }
 
-mod certificate_chain {
-    use sev::certs::snp::Verifiable;
+// Whether check results are printed with colored symbols, set once from --color.
+static STYLED: AtomicBool = AtomicBool::new(false);
 
-    use super::*;
+fn styled() -> bool {
+    STYLED.load(Ordering::Relaxed)
+}
 
-    #[derive(Parser)]
-    pub struct Args {
-        /// Path to directory containing certificate chain."
-        #[arg(value_name = "certs-dir", required = true)]
-        pub certs_dir: PathBuf,
+// Print a check that passed, after a green check mark when styled.
+fn check_passed(message: &str) {
+    if styled() {
+        println!("{} {message}", "\u{2713}".color(Color::Green));
+    } else {
+        println!("{message}");
     }
+}
 
-    // Function to validate certificate chain
-    pub fn validate_cc(args: Args, quiet: bool) -> Result<()> {
-        let ark_path = find_cert_in_dir(&args.certs_dir, "ark")?;
-        let (mut vek_type, mut sign_type): (&str, &str) = ("vcek", "ask");
-        let (vek_path, ask_path) = match find_cert_in_dir(&args.certs_dir, "vlek") {
-            Ok(vlek_path) => {
-                (vek_type, sign_type) = ("vlek", "asvk");
-                (vlek_path, find_cert_in_dir(&args.certs_dir, sign_type)?)
-            }
-            Err(_) => (
-                find_cert_in_dir(&args.certs_dir, vek_type)?,
-                find_cert_in_dir(&args.certs_dir, sign_type)?,
-            ),
-        };
+// Print a check that failed, after a red cross when styled.
+fn check_failed(message: &str) {
+    if styled() {
+        println!("{} {message}", "\u{2717}".color(Color::Red));
+    } else {
+        println!("{message}");
+    }
+}
 
-        // Get a cert chain from directory
-        let cert_chain: Chain = CertPaths {
-            ark_path,
-            ask_path,
-            vek_path,
-        }
-        .try_into()?;
+// Format to print the outcome of an attestation report verification in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyOutput {
+    /// A line of text for every check.
+    Text,
 
-        let ark = cert_chain.ca.ark;
-        let ask = cert_chain.ca.ask;
-        let vek = cert_chain.vek;
+    /// The verified claims as a JSON object.
+    Json,
 
-        // Verify each signature and print result in console
-        match (&ark, &ark).verify() {
-            Ok(()) => {
-                if !quiet {
-                    println!("The AMD ARK was self-signed!");
-                }
-            }
-            Err(e) => match e.kind() {
-                ErrorKind::Other => return Err(anyhow::anyhow!("The AMD ARK is not self-signed!")),
-                _ => {
-                    return Err(anyhow::anyhow!(
-                        "Failed to verify the ARK cerfificate: {:?}",
-                        e
-                    ))
-                }
-            },
-        }
+    /// The verified claims as YAML, in the layout of the JSON object.
+    Yaml,
 
-        match (&ark, &ask).verify() {
-            Ok(()) => {
-                if !quiet {
-                    println!(
-                        "The AMD {} was signed by the AMD ARK!",
-                        sign_type.to_uppercase()
-                    );
-                }
-            }
-            Err(e) => match e.kind() {
-                ErrorKind::Other => {
-                    return Err(anyhow::anyhow!(
-                        "The AMD {} was not signed by the AMD ARK!",
-                        sign_type.to_uppercase()
-                    ))
-                }
-                _ => return Err(anyhow::anyhow!("Failed to verify ASK certificate: {:?}", e)),
-            },
+    /// The verified claims as a CBOR map, keyed by EAT claim keys where they apply.
+    Eat,
+}
+
+// Certificate file extensions probed by find_cert_in_dir, in priority order.
+const CERT_EXTENSIONS: [&str; 4] = ["pem", "der", "crt", "cert"];
+
+// Find a certificate in specified directory according to its extension
+pub fn find_cert_in_dir(dir: &Path, cert: &str) -> Result<PathBuf, anyhow::Error> {
+    // Tell a mistyped directory apart from one missing the certificate
+    if !dir.exists() {
+        return Err(anyhow::anyhow!(
+            "Certificate directory {} does not exist",
+            dir.display()
+        ));
+    }
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Certificate directory {} is not a directory",
+            dir.display()
+        ));
+    }
+
+    // Exact lowercase names take priority over anything else in the directory.
+    for ext in CERT_EXTENSIONS {
+        let path = dir.join(format!("{cert}.{ext}"));
+        if path.is_file() {
+            trace!("Found {cert} certificate at {}", path.display());
+            return Ok(path);
         }
+    }
 
-        match (&ask, &vek).verify() {
-            Ok(()) => {
-                if !quiet {
-                    println!(
-                        "The {} was signed by the AMD {}!",
-                        vek_type.to_uppercase(),
-                        sign_type.to_uppercase()
-                    );
-                }
-            }
-            Err(e) => match e.kind() {
-                ErrorKind::Other => {
-                    return Err(anyhow::anyhow!(
-                        "The {} was not signed by the AMD {}!",
-                        vek_type.to_uppercase(),
-                        sign_type.to_uppercase(),
-                    ))
-                }
-                _ => return Err(anyhow::anyhow!("Failed to verify VEK certificate: {:?}", e)),
-            },
+    // Fall back to a case-insensitive scan so names like ARK.PEM or VCEK.crt are found.
+    let mut matches: Vec<(usize, PathBuf)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let stem = path.file_stem()?.to_str()?.to_lowercase();
+                let ext = path.extension()?.to_str()?.to_lowercase();
+                let priority = CERT_EXTENSIONS.iter().position(|e| *e == ext)?;
+                (stem == cert && path.is_file()).then_some((priority, path))
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    // Sort by extension priority, then by file name, so the result is deterministic.
+    matches.sort();
+
+    match matches.into_iter().next() {
+        Some((_, path)) => {
+            trace!("Found {cert} certificate at {}", path.display());
+            Ok(path)
         }
-        Ok(())
+        None => Err(anyhow::anyhow!(
+            "{cert} certificate not found in directory {}",
+            dir.display()
+        )),
     }
 }
 
-mod attestation {
-    use super::*;
+// Format a time as an RFC 3339 UTC timestamp, such as 2024-05-01T12:30:00Z.
+pub fn utc_timestamp(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Civil date of a count of days since 1970-01-01, in the proleptic Gregorian calendar
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+// Names of the ARK CRL in a certificate directory, including those of the KDS cache.
+const CRL_NAMES: [&str; 3] = ["crl", "vcek-crl", "vlek-crl"];
+const CRL_EXTENSIONS: [&str; 3] = ["der", "pem", "crl"];
 
-    use asn1_rs::{oid, FromDer, Oid};
+// Find the CRL issued by the ARK in a certificate directory, if there is one.
+pub fn find_crl_in_dir(dir: &Path) -> Option<PathBuf> {
+    CRL_NAMES
+        .iter()
+        .flat_map(|name| {
+            CRL_EXTENSIONS
+                .iter()
+                .map(move |ext| dir.join(format!("{name}.{ext}")))
+        })
+        .find(|path| path.is_file())
+}
 
-    use x509_parser::{self, certificate::X509Certificate, prelude::X509Extension, x509::X509Name};
+// Read a CRL in either PEM or DER format.
+pub fn read_crl(path: &Path) -> Result<X509Crl> {
+    let bytes = fs::read(path).with_context(|| format!("Could not read CRL {}", path.display()))?;
+    X509Crl::from_der(&bytes)
+        .or_else(|_| X509Crl::from_pem(&bytes))
+        .with_context(|| format!("Could not parse CRL {}", path.display()))
+}
 
-    use sev::{
-        certs::snp::Certificate,
-        firmware::{guest::AttestationReport, host::CertType},
+// Map a certificate subject common name to the type of certificate.
+pub fn parse_common_name(field: &X509Name<'_>) -> Result<CertType> {
+    let Some(val) = field
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+    else {
+        return Err(anyhow::anyhow!(
+            "Certificate Subject Common Name is Unknown!"
+        ));
     };
 
-    enum SnpOid {
-        BootLoader,
-        Tee,
-        Snp,
-        Ucode,
-        HwId,
+    // AMD names its certificates ARK-<model>, SEV-<model> (ASK), SEV-VLEK-<model> (ASVK),
+    // SEV-VCEK and SEV-VLEK.
+    let is_model = |model: &str| model.parse::<ProcType>().is_ok();
+    let lower = val.to_lowercase();
+    match lower.split('-').collect::<Vec<_>>().as_slice() {
+        ["ark", model] if is_model(model) => Ok(CertType::ARK),
+        ["sev", "vcek"] => Ok(CertType::VCEK),
+        ["sev", "vlek"] => Ok(CertType::VLEK),
+        // The ASVK takes the place of the ASK in a VLEK chain.
+        ["sev", "vlek", model] if is_model(model) => Ok(CertType::ASK),
+        ["sev", model] if is_model(model) => Ok(CertType::ASK),
+        _ => Err(anyhow::anyhow!(
+            "Unknown certificate type encountered for common name {val}!"
+        )),
+    }
+}
+
+// Classify a certificate by its subject common name.
+fn cert_type(cert: &Certificate) -> Result<CertType> {
+    let der = cert
+        .to_der()
+        .context("Could not convert certificate to der.")?;
+    let (_, x509) =
+        X509Certificate::from_der(&der).context("Could not create X509Certificate from der")?;
+    parse_common_name(x509.subject())
+}
+
+// Name of the role a certificate plays in an SNP chain (ark, ask, asvk, vcek or vlek),
+// taken from its subject common name.
+pub fn cert_role(cert: &Certificate) -> Result<&'static str> {
+    let der = cert
+        .to_der()
+        .context("Could not convert certificate to der.")?;
+    let (_, x509) =
+        X509Certificate::from_der(&der).context("Could not create X509Certificate from der")?;
+    let is_asvk = x509
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .is_some_and(|cn| cn.to_lowercase().starts_with("sev-vlek-"));
+
+    match parse_common_name(x509.subject())? {
+        CertType::ARK => Ok("ark"),
+        CertType::ASK if is_asvk => Ok("asvk"),
+        CertType::ASK => Ok("ask"),
+        CertType::VCEK => Ok("vcek"),
+        CertType::VLEK => Ok("vlek"),
+        other => Err(anyhow::anyhow!("Unexpected {other:?} certificate.")),
+    }
+}
+
+// Fail if more than one of the inputs is "-", as stdin can only be read once.
+pub fn check_single_stdin(inputs: &[Option<&Path>]) -> Result<()> {
+    let stdin_inputs = inputs
+        .iter()
+        .flatten()
+        .filter(|input| input.as_os_str() == "-")
+        .count();
+    if stdin_inputs > 1 {
+        return Err(anyhow::anyhow!(
+            "Only one input can be read from stdin, but {stdin_inputs} are given as \"-\"."
+        ));
+    }
+
+    Ok(())
+}
+
+// Confirm that a VLEK taken from the directory is signed by the ASVK next to it, as the
+// signature of the report alone doesn't tie the VLEK to AMD.
+pub fn check_vlek_signer(certs_dir: &Path, vlek: &Certificate) -> Result<()> {
+    let asvk_path = find_cert_in_dir(certs_dir, "asvk").map_err(|_| {
+        anyhow::anyhow!(
+            "The VLEK must be signed by an ASVK, but there is no asvk certificate in {}.",
+            certs_dir.display()
+        )
+    })?;
+    let asvk = convert_path_to_cert(&asvk_path, "asvk")?;
+    if cert_role(&asvk)? != "asvk" {
+        return Err(anyhow::anyhow!("{} is not an ASVK.", asvk_path.display()));
+    }
+    if !link_verified((&asvk, vlek).verify(), "VLEK certificate")? {
+        return Err(anyhow::anyhow!(
+            "The VLEK was NOT signed by the ASVK at {}!",
+            asvk_path.display()
+        ));
     }
 
-    // OID extensions for the VCEK, will be used to verify attestation report
-    impl SnpOid {
-        fn oid(&self) -> Oid {
-            match self {
-                SnpOid::BootLoader => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .1),
-                SnpOid::Tee => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .2),
-                SnpOid::Snp => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .3),
-                SnpOid::Ucode => oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .8),
-                SnpOid::HwId => oid!(1.3.6 .1 .4 .1 .3704 .1 .4),
+    Ok(())
+}
+
+// Fail if the directory holds anything but SNP certificates, or more than one certificate of
+// a role. Subdirectories are ignored.
+pub fn check_strict_dir(certs_dir: &Path) -> Result<()> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(certs_dir)
+        .with_context(|| format!("Could not read directory {}", certs_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut roles: Vec<(&'static str, PathBuf)> = Vec::new();
+    for path in paths {
+        // The ARK CRL is the only file allowed next to the certificates
+        if read_crl(&path).is_ok() {
+            if let Some((_, first)) = roles.iter().find(|(found, _)| *found == "crl") {
+                return Err(anyhow::anyhow!(
+                    "--strict-dir: {} and {} are both CRLs.",
+                    first.display(),
+                    path.display()
+                ));
             }
+            roles.push(("crl", path));
+            continue;
+        }
+        let role = fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| certs::cert_from_bytes(&bytes))
+            .and_then(|cert| cert_role(&cert))
+            .with_context(|| {
+                format!(
+                    "--strict-dir: {} is not an expected certificate",
+                    path.display()
+                )
+            })?;
+        if let Some((_, first)) = roles.iter().find(|(found, _)| *found == role) {
+            return Err(anyhow::anyhow!(
+                "--strict-dir: {} and {} are both {} certificates.",
+                first.display(),
+                path.display(),
+                role.to_uppercase()
+            ));
         }
+        roles.push((role, path));
     }
 
-    #[derive(Parser)]
-    pub struct Args {
-        /// Path to directory containing VCEK.
-        #[arg(value_name = "certs-dir", required = true)]
-        pub certs_dir: PathBuf,
+    Ok(())
+}
 
-        /// Path to attestation report to use for validation.
-        #[arg(value_name = "att-report-path", required = true)]
-        pub att_report_path: PathBuf,
+// Assemble a certificate chain from a single PEM file holding the ARK, ASK and VEK in any
+// order. Returns the chain along with the type of its VEK.
+pub fn chain_from_file(chain_file: &Path) -> Result<(Chain, CertType)> {
+    let certs = read_pem_certs(chain_file, "certificate chain")?;
 
-        /// Run the TCB Verification Exclusively.
-        #[arg(short, long, conflicts_with = "signature")]
-        pub tcb: bool,
+    chain_from_certs(certs, "Certificate chain file")
+}
 
-        /// Run the Signature Verification Exclusively.
-        #[arg(short, long, conflicts_with = "tcb")]
-        pub signature: bool,
+// Get a cert chain from the PEM file of the ASK (or ASVK) and ARK served by the KDS cert_chain
+// endpoint, completed with a separate VEK.
+pub fn chain_from_ca_file(ca_chain: &Path, vek: Certificate) -> Result<(Chain, CertType)> {
+    let mut certs = read_pem_certs(ca_chain, "CA chain")?;
+    if certs
+        .iter()
+        .any(|cert| matches!(cert_type(cert), Ok(CertType::VCEK | CertType::VLEK)))
+    {
+        return Err(anyhow::anyhow!(
+            "CA chain file contains a VEK. Verify a file holding the whole chain with --chain-file."
+        ));
     }
+    certs.push(vek);
 
-    fn verify_attestation_signature(
-        vcek: Certificate,
-        att_report: AttestationReport,
-        quiet: bool,
-    ) -> Result<()> {
-        let vek_pubkey = vcek
-            .public_key()
-            .context("Failed to get the public key from the VEK.")?
-            .ec_key()
-            .context("Failed to convert VEK public key into ECkey.")?;
-
-        // Get the attestation report signature
-        let ar_signature = EcdsaSig::try_from(&att_report.signature)
-            .context("Failed to get ECDSA Signature from attestation report.")?;
-        let signed_bytes = &bincode::serialize(&att_report)
-            .context("Failed to get the signed bytes from the attestation report.")?[0x0..0x2A0];
+    chain_from_certs(certs, "CA chain file")
+}
 
-        let mut hasher: Sha384 = Sha384::new();
+// Read every certificate of a PEM file, or of stdin for "-".
+fn read_pem_certs(path: &Path, name: &str) -> Result<Vec<Certificate>> {
+    let bytes = if path.as_os_str() == "-" {
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut bytes)
+            .context(format!("Could not read {name} from stdin"))?;
+        bytes
+    } else {
+        fs::read(path).context(format!("Could not read {name} file"))?
+    };
+    let certs =
+        X509::stack_from_pem(&bytes).context(format!("Could not parse {name} file as PEM"))?;
 
-        hasher.update(signed_bytes);
+    Ok(certs.into_iter().map(Certificate::from).collect())
+}
 
-        let base_message_digest: [u8; 48] = hasher.finish();
+// Sort certificates into a chain by their subject, which must hold exactly one ARK, ASK and VEK.
+fn chain_from_certs(certs: Vec<Certificate>, source: &str) -> Result<(Chain, CertType)> {
+    let (mut ark, mut ask, mut vek): (Option<Certificate>, Option<Certificate>, Option<_>) =
+        (None, None, None);
 
-        // Verify signature
-        if ar_signature
-            .verify(base_message_digest.as_ref(), vek_pubkey.as_ref())
-            .context("Failed to verify attestation report signature with VEK public key.")?
-        {
-            if !quiet {
-                println!("VEK signed the Attestation Report!");
+    for cert in certs {
+        let (slot, role) = match cert_type(&cert)? {
+            CertType::ARK => (&mut ark, "ARK"),
+            CertType::ASK => (&mut ask, "ASK"),
+            vek_type @ (CertType::VCEK | CertType::VLEK) => {
+                if vek.is_some() {
+                    return Err(anyhow::anyhow!("{source} contains more than one VEK."));
+                }
+                vek = Some((cert, vek_type));
+                continue;
             }
-        } else {
-            return Err(anyhow::anyhow!("VEK did NOT sign the Attestation Report!"));
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{source} contains an unexpected certificate."
+                ))
+            }
+        };
+        if slot.is_some() {
+            return Err(anyhow::anyhow!("{source} contains more than one {role}."));
         }
+        *slot = Some(cert);
+    }
 
-        Ok(())
+    let ark = ark.ok_or(anyhow::anyhow!("{source} is missing the ARK."))?;
+    let ask = ask.ok_or(anyhow::anyhow!("{source} is missing the ASK."))?;
+    let (vek, vek_type) = vek.ok_or(anyhow::anyhow!("{source} is missing the VEK."))?;
+
+    Ok((
+        Chain {
+            ca: ca::Chain { ark, ask },
+            vek,
+        },
+        vek_type,
+    ))
+}
+
+// Get a certificate of the given type out of the certificate table of an extended report.
+pub fn embedded_cert(certs: &[CertTableEntry], cert_type: CertType) -> Result<Option<Certificate>> {
+    certs
+        .iter()
+        .find(|cert| cert.cert_type == cert_type)
+        .map(|cert| {
+            debug!("Using the {cert_type:?} certificate embedded in the report");
+            certs::cert_from_bytes(&cert.data).context(format!(
+                "Could not parse embedded {cert_type:?} certificate"
+            ))
+        })
+        .transpose()
+}
+
+// Get the VEK of the signing key out of the certificate table of an extended report,
+// preferring the VLEK for unsigned reports.
+pub fn embedded_vek(
+    certs: &[CertTableEntry],
+    signing_key: Option<&Endorsement>,
+) -> Result<Option<Certificate>> {
+    match signing_key {
+        Some(Endorsement::Vcek) => embedded_cert(certs, CertType::VCEK),
+        Some(Endorsement::Vlek) => embedded_cert(certs, CertType::VLEK),
+        None => match embedded_cert(certs, CertType::VLEK)? {
+            Some(vlek) => Ok(Some(vlek)),
+            None => embedded_cert(certs, CertType::VCEK),
+        },
     }
+}
 
-    // Check the cert extension byte to value
-    fn check_cert_bytes(ext: &X509Extension, val: &[u8]) -> bool {
-        match ext.value[0] {
-            // Integer
-            0x2 => {
-                if ext.value[1] != 0x1 && ext.value[1] != 0x2 {
-                    panic!("Invalid octet length encountered!");
-                } else if let Some(byte_value) = ext.value.last() {
-                    byte_value == &val[0]
-                } else {
-                    false
-                }
-            }
-            // Octet String
-            0x4 => {
-                if ext.value[1] != 0x40 {
-                    panic!("Invalid octet length encountered!");
-                } else if ext.value[2..].len() != 0x40 {
-                    panic!("Invalid size of bytes encountered!");
-                } else if val.len() != 0x40 {
-                    panic!("Invalid certificate harward id length encountered!")
-                }
+// Result of verifying each signature link of an SNP certificate chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainVerification {
+    /// The ARK is self-signed.
+    pub ark_self_signed: bool,
 
-                &ext.value[2..] == val
-            }
-            // Legacy and others.
-            _ => {
-                // Keep around for a bit for old VCEK without x509 DER encoding.
-                if ext.value.len() == 0x40 && val.len() == 0x40 {
-                    ext.value == val
-                } else {
-                    panic!("Invalid type encountered!");
-                }
+    /// The ASK (or ASVK) was signed by the ARK.
+    pub ask_signed_by_ark: bool,
+
+    /// The VEK was signed by the ASK (or ASVK).
+    pub vek_signed_by_ask: bool,
+}
+
+// Map a signature verification outcome into whether the link holds. A bad signature is
+// reported by the sev crate as ErrorKind::Other, anything else is a genuine failure.
+fn link_verified(result: std::io::Result<()>, link: &str) -> Result<bool> {
+    match result {
+        Ok(()) => {
+            debug!("Signature of the {link} is valid");
+            Ok(true)
+        }
+        Err(e) => match e.kind() {
+            ErrorKind::Other => {
+                debug!("Signature of the {link} is invalid: {e}");
+                Ok(false)
             }
+            _ => Err(anyhow::anyhow!("Failed to verify the {link}: {:?}", e)),
+        },
+    }
+}
+
+/// Check that an ARK is the pinned root, either byte for byte or by being
+/// signed with the pinned root's key.
+pub fn ark_matches_trusted(ark: &Certificate, trusted_ark: &Certificate) -> Result<bool> {
+    if ark.to_der().context("Could not convert ARK to der.")?
+        == trusted_ark
+            .to_der()
+            .context("Could not convert trusted ARK to der.")?
+    {
+        return Ok(true);
+    }
+
+    link_verified((trusted_ark, ark).verify(), "ARK against the trusted ARK")
+}
+
+/// Processor model of the ARK bundled with snpguest that an ARK matches, if any.
+pub fn bundled_ark_model(ark: &Certificate) -> Result<Option<&'static str>> {
+    // Anything the ARK signed, such as the ASK, verifies against it, so only a self-signed
+    // certificate can be a reissued ARK
+    if (ark, ark).verify().is_err() {
+        return Ok(None);
+    }
+    for (model, bundled) in [
+        ("Milan", sev::certs::snp::builtin::milan::ARK),
+        ("Genoa", sev::certs::snp::builtin::genoa::ARK),
+    ] {
+        if ark_matches_trusted(ark, &Certificate::from_pem(bundled)?)? {
+            return Ok(Some(model));
         }
     }
 
-    fn parse_common_name(field: &X509Name<'_>) -> Result<CertType> {
-        if let Some(val) = field
-            .iter_common_name()
-            .next()
-            .and_then(|cn| cn.as_str().ok())
-        {
-            match val.to_lowercase() {
-                x if x.contains("ark") => Ok(CertType::ARK),
-                x if x.contains("ask") => Ok(CertType::ASK),
-                x if x.contains("vcek") => Ok(CertType::VCEK),
-                x if x.contains("vlek") => Ok(CertType::VLEK),
-                x if x.contains("crl") => Ok(CertType::CRL),
-                _ => Err(anyhow::anyhow!("Unknown certificate type encountered!")),
-            }
-        } else {
-            Err(anyhow::anyhow!(
-                "Certificate Subject Common Name is Unknown!"
-            ))
+    Ok(None)
+}
+
+/// Check that a certificate of the chain is a CA allowed to have at least
+/// `intermediates` CA certificates below it.
+pub fn check_ca_constraints(cert: &Certificate, name: &str, intermediates: u32) -> Result<()> {
+    let der = cert
+        .to_der()
+        .with_context(|| format!("Could not convert {name} to der."))?;
+    let (_, x509) =
+        X509Certificate::from_der(&der).context("Could not create X509Certificate from der")?;
+
+    let constraints = x509
+        .basic_constraints()
+        .with_context(|| format!("Failed to parse the {name} basic constraints."))?
+        .ok_or_else(|| anyhow::anyhow!("The {name} has no basic constraints."))?;
+    if !constraints.value.ca {
+        return Err(anyhow::anyhow!(
+            "The {name} is not a CA certificate (CA:FALSE)."
+        ));
+    }
+    if let Some(pathlen) = constraints.value.path_len_constraint {
+        if pathlen < intermediates {
+            return Err(anyhow::anyhow!(
+                "The {name} path length constraint of {pathlen} doesn't allow {intermediates} intermediate CA."
+            ));
         }
     }
+    debug!("Basic constraints of the {name} allow it to issue the chain");
 
-    fn verify_attestation_tcb(
-        vcek: Certificate,
-        att_report: AttestationReport,
-        quiet: bool,
-    ) -> Result<()> {
-        let vek_der = vcek.to_der().context("Could not convert VEK to der.")?;
-        let (_, vek_x509) = X509Certificate::from_der(&vek_der)
-            .context("Could not create X509Certificate from der")?;
+    Ok(())
+}
 
-        // Collect extensions from VEK
-        let extensions: std::collections::HashMap<Oid, &X509Extension> = vek_x509
-            .extensions_map()
-            .context("Failed getting VEK oids.")?;
+/// Check that a certificate of the chain is signed with RSASSA-PSS, as AMD signs every
+/// certificate, and for the VEK that its key is the P-384 key reports are signed with.
+pub fn check_algorithms(cert: &Certificate, name: &str, vek: bool) -> Result<()> {
+    let der = cert
+        .to_der()
+        .with_context(|| format!("Could not convert {name} to der."))?;
+    let (_, x509) =
+        X509Certificate::from_der(&der).context("Could not create X509Certificate from der")?;
 
-        let common_name: CertType = parse_common_name(vek_x509.subject())?;
+    let algorithm = &x509.signature_algorithm.algorithm;
+    if *algorithm != oids::RSASSA_PSS {
+        return Err(anyhow::anyhow!(
+            "The {name} is signed with {algorithm}, but AMD signs it with RSASSA-PSS ({}).",
+            oids::RSASSA_PSS
+        ));
+    }
 
-        // Compare bootloaders
-        if let Some(cert_bl) = extensions.get(&SnpOid::BootLoader.oid()) {
-            if !check_cert_bytes(cert_bl, &att_report.reported_tcb.bootloader.to_le_bytes()) {
-                return Err(anyhow::anyhow!(
-                    "Report TCB Boot Loader and Certificate Boot Loader mismatch encountered."
-                ));
-            }
-            if !quiet {
-                println!(
-                    "Reported TCB Boot Loader from certificate matches the attestation report."
-                );
-            }
+    if vek {
+        let key = &x509.public_key().algorithm;
+        let curve = key
+            .parameters
+            .as_ref()
+            .and_then(|parameters| parameters.as_oid().ok());
+        if key.algorithm != oids::EC_PUBLIC_KEY || curve != Some(oids::SECP384R1) {
+            return Err(anyhow::anyhow!(
+                "The {name} doesn't hold an ECDSA P-384 key ({} on {}).",
+                oids::EC_PUBLIC_KEY,
+                oids::SECP384R1
+            ));
         }
+    }
+    debug!("Algorithms of the {name} are the ones AMD uses");
 
-        // Compare TEE information
-        if let Some(cert_tee) = extensions.get(&SnpOid::Tee.oid()) {
-            if !check_cert_bytes(cert_tee, &att_report.reported_tcb.tee.to_le_bytes()) {
-                return Err(anyhow::anyhow!(
-                    "Report TCB TEE and Certificate TEE mismatch encountered."
-                ));
-            }
-            if !quiet {
-                println!("Reported TCB TEE from certificate matches the attestation report.");
-            }
+    Ok(())
+}
+
+/// Verify the signatures of an SNP certificate chain without printing anything.
+pub fn verify_cert_chain(chain: &Chain) -> Result<ChainVerification> {
+    let ark = &chain.ca.ark;
+    let ask = &chain.ca.ask;
+    let vek = &chain.vek;
+
+    Ok(ChainVerification {
+        ark_self_signed: link_verified((ark, ark).verify(), "ARK certificate")?,
+        ask_signed_by_ark: link_verified((ark, ask).verify(), "ASK certificate")?,
+        vek_signed_by_ask: link_verified((ask, vek).verify(), "VEK certificate")?,
+    })
+}
+
+mod certificate_chain {
+    use super::*;
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Path to directory containing certificate chain, defaulting to $SNPGUEST_CERTS_DIR.
+        #[arg(value_name = "certs-dir", conflicts_with_all = ["chain_file", "ca_chain"])]
+        pub certs_dir: Option<PathBuf>,
+
+        /// Path to a single PEM file containing the ARK, ASK and VEK, or "-" to read it from stdin.
+        #[arg(long, value_name = "chain-file", conflicts_with = "ca_chain")]
+        pub chain_file: Option<PathBuf>,
+
+        /// Path to the PEM file holding the ASK (or ASVK) and ARK, as served by the KDS
+        /// cert_chain endpoint, or "-" to read it from stdin. Used along with --vek.
+        #[arg(long, value_name = "ca-chain", requires = "vek")]
+        pub ca_chain: Option<PathBuf>,
+
+        /// Path to the VCEK or VLEK to verify against the --ca-chain, or "-" to read it from stdin.
+        #[arg(long, value_name = "vek", requires = "ca_chain")]
+        pub vek: Option<PathBuf>,
+
+        /// Path to a pinned AMD ARK, or "-" to read it from stdin. The ARK in the directory must
+        /// match it.
+        #[arg(long, value_name = "trusted-ark")]
+        pub trusted_ark: Option<PathBuf>,
+
+        /// Verify a legacy SEV chain (ARK, ASK, CEK, OCA, PEK and PDH) instead of an SNP chain.
+        #[arg(long, conflicts_with_all = ["chain_file", "ca_chain", "trusted_ark"])]
+        pub legacy_sev: bool,
+
+        /// Fail if the directory holds anything but SNP certificates, or two of the same role.
+        #[arg(long, conflicts_with_all = ["chain_file", "ca_chain", "legacy_sev"])]
+        pub strict_dir: bool,
+    }
+
+    // Read a certificate in the AMD SEV format from the directory, refusing X.509 (SNP) ones.
+    fn read_legacy_cert<T>(certs_dir: &Path, cert: &str) -> Result<T>
+    where
+        T: codicon::Decoder<(), Error = std::io::Error>,
+    {
+        let path = find_cert_in_dir(certs_dir, cert)?;
+        let bytes = fs::read(&path).context(format!("Could not read {cert} certificate"))?;
+        if X509::from_pem(&bytes).is_ok() || X509::from_der(&bytes).is_ok() {
+            return Err(anyhow::anyhow!(
+                "The {} is an X.509 certificate from an SNP chain. Verify it without --legacy-sev.",
+                cert.to_uppercase()
+            ));
         }
 
-        // Compare SNP information
-        if let Some(cert_snp) = extensions.get(&SnpOid::Snp.oid()) {
-            if !check_cert_bytes(cert_snp, &att_report.reported_tcb.snp.to_le_bytes()) {
-                return Err(anyhow::anyhow!(
-                    "Report TCB SNP and Certificate SNP mismatch encountered."
-                ));
+        T::decode(&mut bytes.as_slice(), ()).context(format!(
+            "Could not parse {} as a legacy SEV certificate",
+            cert.to_uppercase()
+        ))
+    }
+
+    // Verify a legacy SEV certificate chain, printing the result of each link in console
+    fn validate_legacy_cc(certs_dir: &Path, quiet: bool) -> Result<()> {
+        use sev::certs::sev::{ca as sev_ca, sev as sev_sev, Verifiable as _};
+
+        let ark: sev_ca::Certificate = read_legacy_cert(certs_dir, "ark")?;
+        let ask: sev_ca::Certificate = read_legacy_cert(certs_dir, "ask")?;
+        let cek: sev_sev::Certificate = read_legacy_cert(certs_dir, "cek")?;
+        let oca: sev_sev::Certificate = read_legacy_cert(certs_dir, "oca")?;
+        let pek: sev_sev::Certificate = read_legacy_cert(certs_dir, "pek")?;
+        let pdh: sev_sev::Certificate = read_legacy_cert(certs_dir, "pdh")?;
+
+        let links = [
+            ("The AMD ARK was self-signed!", (&ark, &ark).verify()),
+            (
+                "The AMD ASK was signed by the AMD ARK!",
+                (&ark, &ask).verify(),
+            ),
+            ("The CEK was signed by the AMD ASK!", (&ask, &cek).verify()),
+            ("The OCA was self-signed!", (&oca, &oca).verify()),
+            ("The PEK was signed by the OCA!", (&oca, &pek).verify()),
+            ("The PEK was signed by the CEK!", (&cek, &pek).verify()),
+            ("The PDH was signed by the PEK!", (&pek, &pdh).verify()),
+        ];
+
+        for (link, result) in links {
+            debug!("{link} {result:?}");
+            if result.is_err() {
+                return Err(anyhow::anyhow!("{}", link.replace(" was ", " was NOT ")));
             }
             if !quiet {
-                println!("Reported TCB SNP from certificate matches the attestation report.");
+                check_passed(link);
             }
         }
 
-        // Compare Microcode information
-        if let Some(cert_ucode) = extensions.get(&SnpOid::Ucode.oid()) {
-            if !check_cert_bytes(cert_ucode, &att_report.reported_tcb.microcode.to_le_bytes()) {
-                return Err(anyhow::anyhow!(
-                    "Report TCB Microcode and Certificate Microcode mismatch encountered."
-                ));
-            }
-            if !quiet {
-                println!("Reported TCB Microcode from certificate matches the attestation report.");
-            }
+        Ok(())
+    }
+
+    // Get a cert chain from directory, along with the VEK and signer types found. The roles
+    // of the certificates are taken from their subjects rather than their file names. The
+    // VLEK chain is preferred unless the VEK is known to be a VCEK.
+    fn chain_from_dir(
+        certs_dir: &Path,
+        vek: Option<&Endorsement>,
+    ) -> Result<(Chain, &'static str, &'static str)> {
+        let ark_path = find_cert_in_dir(certs_dir, "ark")?;
+        let ark = convert_path_to_cert(&ark_path, "ark")?;
+        if cert_role(&ark)? != "ark" {
+            return Err(anyhow::anyhow!("{} is not an ARK.", ark_path.display()));
         }
 
-        // Compare HWID information only on VCEK
-        if common_name == CertType::VCEK {
-            if let Some(cert_hwid) = extensions.get(&SnpOid::HwId.oid()) {
-                if !check_cert_bytes(cert_hwid, &att_report.chip_id) {
+        // Load whichever VEKs and signers are present, keyed by the role in their subject
+        let mut veks = Vec::new();
+        let mut signers = Vec::new();
+        for name in ["vcek", "vlek", "ask", "asvk"] {
+            let Ok(path) = find_cert_in_dir(certs_dir, name) else {
+                continue;
+            };
+            let cert = convert_path_to_cert(&path, name)?;
+            match cert_role(&cert)? {
+                role @ ("vcek" | "vlek") => veks.push((role, cert)),
+                role @ ("ask" | "asvk") => signers.push((role, cert)),
+                role => {
                     return Err(anyhow::anyhow!(
-                        "Report TCB ID and Certificate ID mismatch encountered."
-                    ));
-                }
-                if !quiet {
-                    println!("Chip ID from certificate matches the attestation report.");
+                        "{} holds an {}, not a VEK or its signer.",
+                        path.display(),
+                        role.to_uppercase()
+                    ))
                 }
             }
         }
 
-        Ok(())
-    }
+        let vek_type = match vek {
+            Some(Endorsement::Vcek) => "vcek",
+            Some(Endorsement::Vlek) => "vlek",
+            None if veks.iter().any(|(role, _)| *role == "vlek") => "vlek",
+            None => "vcek",
+        };
+        let sign_type = if vek_type == "vlek" { "asvk" } else { "ask" };
 
-    pub fn verify_attestation(args: Args, quiet: bool) -> Result<()> {
-        // Get attestation report
-        let att_report = if !args.att_report_path.exists() {
-            return Err(anyhow::anyhow!("No attestation report was found. Provide an attestation report to request VEK from the KDS."));
-        } else {
-            report::read_report(args.att_report_path)
-                .context("Could not open attestation report")?
+        let take = |certs: &mut Vec<(&'static str, Certificate)>, role: &str| {
+            certs
+                .iter()
+                .position(|(found, _)| *found == role)
+                .map(|index| certs.swap_remove(index).1)
+        };
+        let Some(vek_cert) = take(&mut veks, vek_type) else {
+            return Err(match veks.first() {
+                Some((other, _)) => anyhow::anyhow!(
+                    "No {} found in directory, only a {}.",
+                    vek_type.to_uppercase(),
+                    other.to_uppercase()
+                ),
+                None => anyhow::anyhow!("{vek_type} certificate not found in directory"),
+            });
+        };
+        let Some(ask) = take(&mut signers, sign_type) else {
+            return Err(match signers.first() {
+                Some((other, _)) => anyhow::anyhow!(
+                    "The {} is signed by an {}, but the directory only holds an {}.",
+                    vek_type.to_uppercase(),
+                    sign_type.to_uppercase(),
+                    other.to_uppercase()
+                ),
+                None => anyhow::anyhow!("{sign_type} certificate not found in directory"),
+            });
         };
 
-        // Get VEK and its public key.
-        let (vek_path, vek_type) = match find_cert_in_dir(&args.certs_dir, "vlek") {
-            Ok(vlek_path) => (vlek_path, "vlek"),
-            Err(_) => (find_cert_in_dir(&args.certs_dir, "vcek")?, "vcek"),
+        let cert_chain = Chain {
+            ca: ca::Chain { ark, ask },
+            vek: vek_cert,
         };
 
-        // Get VEK and grab its public key
-        let vek = convert_path_to_cert(&vek_path, vek_type)?;
+        Ok((cert_chain, vek_type, sign_type))
+    }
 
-        if args.tcb || args.signature {
-            if args.tcb {
-                verify_attestation_tcb(vek.clone(), att_report, quiet)?;
-            }
-            if args.signature {
-                verify_attestation_signature(vek, att_report, quiet)?;
-            }
-        } else {
-            verify_attestation_tcb(vek.clone(), att_report, quiet)?;
-            verify_attestation_signature(vek, att_report, quiet)?;
+    // Get a cert chain from a chain file, a CA chain file and a VEK, or a directory, along
+    // with the VEK and signer types.
+    pub fn load_chain(
+        certs_dir: Option<&Path>,
+        chain_file: Option<&Path>,
+        ca_chain: Option<(&Path, &Path)>,
+        vek: Option<&Endorsement>,
+    ) -> Result<(Chain, &'static str, &'static str)> {
+        let file_chain = match (chain_file, ca_chain) {
+            (Some(chain_file), _) => Some(chain_from_file(chain_file)?),
+            (None, Some((ca_chain, vek_path))) => Some(chain_from_ca_file(
+                ca_chain,
+                convert_path_to_cert(&vek_path.to_path_buf(), "vek")?,
+            )?),
+            (None, None) => None,
+        };
+        match (file_chain, certs_dir) {
+            (Some((cert_chain, CertType::VLEK)), _) => Ok((cert_chain, "vlek", "asvk")),
+            (Some((cert_chain, _)), _) => Ok((cert_chain, "vcek", "ask")),
+            (None, Some(certs_dir)) => chain_from_dir(certs_dir, vek),
+            (None, None) => Err(anyhow::anyhow!("No certificate chain provided.")),
         }
-
-        Ok(())
     }
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-        use x509_parser::{self, certificate::X509Certificate};
-
+    // Function to validate certificate chain
+    pub fn validate_cc(mut args: Args, quiet: bool) -> Result<()> {
+        check_single_stdin(&[
+            args.chain_file.as_deref(),
+            args.ca_chain.as_deref(),
+            args.vek.as_deref(),
+            args.trusted_ark.as_deref(),
+        ])?;
+        if args.chain_file.is_none() && args.ca_chain.is_none() {
+            args.certs_dir = Some(certs::require_certs_dir(args.certs_dir)?);
+        }
+
+        if args.legacy_sev {
+            let certs_dir = args
+                .certs_dir
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("No certificate directory provided."))?;
+            return validate_legacy_cc(certs_dir, quiet);
+        }
+        if let (true, Some(certs_dir)) = (args.strict_dir, &args.certs_dir) {
+            check_strict_dir(certs_dir)?;
+        }
+
+        let (cert_chain, vek_type, sign_type) = load_chain(
+            args.certs_dir.as_deref(),
+            args.chain_file.as_deref(),
+            args.ca_chain.as_deref().zip(args.vek.as_deref()),
+            None,
+        )
+        .map_err(|err| match args.certs_dir.as_deref() {
+            // A PDH only exists in legacy SEV chains
+            Some(certs_dir) if find_cert_in_dir(certs_dir, "pdh").is_ok() => {
+                err.context("The directory holds a legacy SEV chain. Verify it with --legacy-sev.")
+            }
+            _ => err,
+        })?;
+
+        check_chain(
+            &cert_chain,
+            vek_type,
+            sign_type,
+            args.trusted_ark.as_deref(),
+            quiet,
+        )?;
+
+        match args.certs_dir.as_deref().and_then(find_crl_in_dir) {
+            Some(crl_path) => check_crl(&crl_path, &cert_chain, sign_type, quiet),
+            None => Ok(()),
+        }
+    }
+
+    // Verify the CRL found next to the chain against the ARK, printing when it was issued and
+    // warning if it is past its next update. The ASK must not be revoked by it.
+    pub fn check_crl(
+        crl_path: &Path,
+        cert_chain: &Chain,
+        sign_type: &str,
+        quiet: bool,
+    ) -> Result<()> {
+        let crl = read_crl(crl_path)?;
+        let ark = X509::from_der(&cert_chain.ca.ark.to_der()?)?;
+        let ask = X509::from_der(&cert_chain.ca.ask.to_der()?)?;
+
+        if crl.issuer_name().try_cmp(ark.subject_name())? != std::cmp::Ordering::Equal {
+            return Err(anyhow::anyhow!(
+                "The CRL {} was not issued by the AMD ARK of the chain!",
+                crl_path.display()
+            ));
+        }
+        if !crl.verify(ark.public_key()?.as_ref())? {
+            return Err(anyhow::anyhow!(
+                "The CRL {} was not signed by the AMD ARK!",
+                crl_path.display()
+            ));
+        }
+        if !quiet {
+            check_passed("The CRL was signed by the AMD ARK!");
+        }
+
+        if !matches!(crl.get_by_cert(&ask), CrlStatus::NotRevoked) {
+            return Err(anyhow::anyhow!(
+                "The AMD {} was revoked by the CRL!",
+                sign_type.to_uppercase()
+            ));
+        }
+
+        let next_update = crl.next_update();
+        if !quiet {
+            println!("CRL last update: {}", crl.last_update());
+            match next_update {
+                Some(next_update) => println!("CRL next update: {next_update}"),
+                None => println!("CRL next update: none"),
+            }
+        }
+        if !quiet && crl_stale(&crl)? {
+            eprintln!(
+                "WARNING: the CRL {} is stale, fetch a new one from the KDS.",
+                crl_path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    // Whether a CRL is past its next update.
+    pub fn crl_stale(crl: &X509Crl) -> Result<bool> {
+        let now = Asn1Time::days_from_now(0)?;
+        Ok(crl.next_update().is_some_and(|next| next < now))
+    }
+
+    // Verify each link of the chain, printing the result of each in console.
+    pub fn check_chain(
+        cert_chain: &Chain,
+        vek_type: &str,
+        sign_type: &str,
+        trusted_ark: Option<&Path>,
+        quiet: bool,
+    ) -> Result<()> {
+        let result = verify_cert_chain(cert_chain)?;
+
+        // Print the result of each link in console
+        if !result.ark_self_signed {
+            return Err(anyhow::anyhow!("The AMD ARK is not self-signed!"));
+        }
+        if !quiet {
+            check_passed("The AMD ARK was self-signed!");
+        }
+
+        if let Some(trusted_ark_path) = trusted_ark {
+            let trusted_ark = convert_path_to_cert(&trusted_ark_path.to_path_buf(), "trusted ark")?;
+            if !ark_matches_trusted(&cert_chain.ca.ark, &trusted_ark)? {
+                return Err(anyhow::anyhow!(
+                    "The AMD ARK does not match the trusted ARK!"
+                ));
+            }
+            if !quiet {
+                check_passed("The AMD ARK matches the trusted ARK!");
+            }
+        }
+
+        // The ARK issues the ASK, which only issues the VEK
+        check_ca_constraints(&cert_chain.ca.ark, "AMD ARK", 1)?;
+        check_ca_constraints(
+            &cert_chain.ca.ask,
+            &format!("AMD {}", sign_type.to_uppercase()),
+            0,
+        )?;
+        check_algorithms(&cert_chain.ca.ark, "AMD ARK", false)?;
+        check_algorithms(
+            &cert_chain.ca.ask,
+            &format!("AMD {}", sign_type.to_uppercase()),
+            false,
+        )?;
+        check_algorithms(&cert_chain.vek, &vek_type.to_uppercase(), true)?;
+
+        if !result.ask_signed_by_ark {
+            return Err(anyhow::anyhow!(
+                "The AMD {} was not signed by the AMD ARK!",
+                sign_type.to_uppercase()
+            ));
+        }
+        if !quiet {
+            check_passed(&format!(
+                "The AMD {} was signed by the AMD ARK!",
+                sign_type.to_uppercase()
+            ));
+        }
+
+        if !result.vek_signed_by_ask {
+            return Err(anyhow::anyhow!(
+                "The {} was not signed by the AMD {}!",
+                vek_type.to_uppercase(),
+                sign_type.to_uppercase(),
+            ));
+        }
+        if !quiet {
+            check_passed(&format!(
+                "The {} was signed by the AMD {}!",
+                vek_type.to_uppercase(),
+                sign_type.to_uppercase()
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use sev::certs::sev::builtin::rome;
+
+        #[test]
+        fn test_validate_legacy_cc() {
+            let dir = std::env::temp_dir().join(format!("snpguest-legacy-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            fs::write(dir.join("ark.cert"), rome::ARK).unwrap();
+            fs::write(dir.join("ask.cert"), rome::ASK).unwrap();
+            let ark: sev::certs::sev::ca::Certificate = read_legacy_cert(&dir, "ark").unwrap();
+            let ask: sev::certs::sev::ca::Certificate = read_legacy_cert(&dir, "ask").unwrap();
+            {
+                use sev::certs::sev::Verifiable as _;
+                assert!((&ark, &ask).verify().is_ok());
+            }
+            // The platform part of the chain is missing
+            assert!(validate_legacy_cc(&dir, true).is_err());
+
+            // SNP certificates are refused
+            fs::remove_file(dir.join("ark.cert")).unwrap();
+            fs::write(dir.join("ark.pem"), sev::certs::snp::builtin::milan::ARK).unwrap();
+            let err = validate_legacy_cc(&dir, true).unwrap_err();
+            assert!(err.to_string().contains("Verify it without --legacy-sev"));
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
+
+mod attestation {
+    use super::*;
+
+    use openssl::error::ErrorStack;
+    use std::collections::HashMap;
+
+    use asn1_rs::Oid;
+    use oids::{tcb_oids, SnpOid};
+
+    use x509_parser::{self, prelude::X509Extension};
+
+    use sev::{certs::snp::Certificate, firmware::guest::AttestationReport};
+
+    // OIDs a VEK is expected to carry for the processor generation and certificate type.
+    fn expected_oids(proc_model: &ProcType, common_name: &CertType) -> Vec<SnpOid> {
+        let mut expected = tcb_oids(proc_model);
+        if *common_name == CertType::VCEK {
+            expected.push(SnpOid::HwId);
+        }
+        expected
+    }
+
+    // Warn about expected OIDs missing from the VEK, or fail in strict mode.
+    fn check_missing_oids(missing: &[SnpOid], strict: bool, quiet: bool) -> Result<()> {
+        let missing: Vec<String> = missing
+            .iter()
+            .map(|snp_oid| format!("{} ({})", snp_oid.name(), snp_oid.oid()))
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else if strict {
+            Err(anyhow::anyhow!(
+                "The VEK is missing the expected OIDs: {}",
+                missing.join(", ")
+            ))
+        } else {
+            if !quiet {
+                eprintln!(
+                    "WARNING: The VEK is missing the expected OIDs, skipping: {}",
+                    missing.join(", ")
+                );
+            }
+            Ok(())
+        }
+    }
+
+    // Minimum acceptable value of each TCB component. Absent components are unconstrained.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct MinTcb {
+        pub fmc: Option<u8>,
+        pub bootloader: Option<u8>,
+        pub tee: Option<u8>,
+        pub snp: Option<u8>,
+        pub microcode: Option<u8>,
+    }
+
+    impl FromStr for MinTcb {
+        type Err = anyhow::Error;
+
+        // Parse a list like "bootloader=3,tee=0,snp=8,microcode=115".
+        fn from_str(input: &str) -> Result<MinTcb, anyhow::Error> {
+            let mut min_tcb = MinTcb::default();
+
+            for component in input.split(',').map(str::trim) {
+                let (name, value) = component.split_once('=').ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid minimum TCB component \"{component}\", expected name=value."
+                    )
+                })?;
+                let value: u8 = value.trim().parse().with_context(|| {
+                    format!("Invalid value for minimum TCB component \"{name}\".")
+                })?;
+
+                let field = match SnpOid::from_tcb_key(name) {
+                    Some(SnpOid::Fmc) => &mut min_tcb.fmc,
+                    Some(SnpOid::BootLoader) => &mut min_tcb.bootloader,
+                    Some(SnpOid::Tee) => &mut min_tcb.tee,
+                    Some(SnpOid::Snp) => &mut min_tcb.snp,
+                    Some(SnpOid::Ucode) => &mut min_tcb.microcode,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Unknown minimum TCB component \"{name}\", expected one of fmc, bootloader, tee, snp, microcode."
+                        ))
+                    }
+                };
+                *field = Some(value);
+            }
+
+            Ok(min_tcb)
+        }
+    }
+
+    impl MinTcb {
+        // Minimum of a TCB component, if one was given.
+        fn get(&self, component: SnpOid) -> Option<u8> {
+            match component {
+                SnpOid::Fmc => self.fmc,
+                SnpOid::BootLoader => self.bootloader,
+                SnpOid::Tee => self.tee,
+                SnpOid::Snp => self.snp,
+                SnpOid::Ucode => self.microcode,
+                SnpOid::HwId => None,
+            }
+        }
+
+        // Fail if any component of the reported TCB is below its minimum.
+        fn check(&self, tcb: &Tcb) -> Result<()> {
+            for component in SnpOid::TCB {
+                let name = component.name();
+                match (self.get(component), tcb.get(component)) {
+                    (Some(minimum), Some(reported)) if reported < minimum => {
+                        return Err(anyhow::anyhow!(
+                            "Report TCB {name} {reported} is below the minimum of {minimum}."
+                        ))
+                    }
+                    (Some(_), None) => {
+                        return Err(anyhow::anyhow!(
+                            "A minimum TCB {name} was given but the report has no {name} component."
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    // TCB components whose mismatch with the VEK only warns instead of failing.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct AllowedTcbMismatches {
+        pub fmc: bool,
+        pub bootloader: bool,
+        pub tee: bool,
+        pub snp: bool,
+        pub microcode: bool,
+    }
+
+    impl FromStr for AllowedTcbMismatches {
+        type Err = anyhow::Error;
+
+        // Parse a list like "microcode,snp".
+        fn from_str(input: &str) -> Result<AllowedTcbMismatches, anyhow::Error> {
+            let mut allowed = AllowedTcbMismatches::default();
+
+            for name in input.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                let field = match SnpOid::from_tcb_key(name) {
+                    Some(SnpOid::Fmc) => &mut allowed.fmc,
+                    Some(SnpOid::BootLoader) => &mut allowed.bootloader,
+                    Some(SnpOid::Tee) => &mut allowed.tee,
+                    Some(SnpOid::Snp) => &mut allowed.snp,
+                    Some(SnpOid::Ucode) => &mut allowed.microcode,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Unknown TCB component \"{name}\", expected one of fmc, bootloader, tee, snp, microcode."
+                        ))
+                    }
+                };
+                *field = true;
+            }
+
+            Ok(allowed)
+        }
+    }
+
+    impl AllowedTcbMismatches {
+        // Whether a mismatch of the TCB component only warns.
+        fn allows(&self, component: SnpOid) -> bool {
+            match component {
+                SnpOid::Fmc => self.fmc,
+                SnpOid::BootLoader => self.bootloader,
+                SnpOid::Tee => self.tee,
+                SnpOid::Snp => self.snp,
+                SnpOid::Ucode => self.microcode,
+                SnpOid::HwId => false,
+            }
+        }
+    }
+
+    // VEK extensions snpguest doesn't know, each along with the report field it must match,
+    // named as in display report --output json with dots for nested fields.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ExtraOids(pub Vec<(Oid<'static>, String)>);
+
+    impl ExtraOids {
+        // Read a JSON object mapping dotted OIDs to report fields.
+        pub fn read(path: &Path) -> Result<ExtraOids> {
+            let bytes = fs::read(path)
+                .with_context(|| format!("Could not read extra OIDs {}", path.display()))?;
+            let map: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(&bytes)
+                .with_context(|| format!("{} is not a JSON object", path.display()))?;
+
+            map.into_iter()
+                .map(|(oid, field)| {
+                    let parsed = Oid::from_str(&oid).map_err(|_| {
+                        anyhow::anyhow!("Invalid OID \"{oid}\" in {}.", path.display())
+                    })?;
+                    let field = match field {
+                        serde_json::Value::String(field) => field,
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "The report field of OID {oid} in {} is not a string.",
+                                path.display()
+                            ))
+                        }
+                    };
+                    Ok((parsed.to_owned(), field))
+                })
+                .collect::<Result<_>>()
+                .map(ExtraOids)
+        }
+    }
+
+    // Whether a VEK extension holds the value of a report field: an integer for numbers,
+    // booleans and 0x prefixed hex, and the bytes of other hex strings.
+    fn extension_matches(ext: &X509Extension, value: &serde_json::Value) -> Result<bool> {
+        let integer = match value {
+            serde_json::Value::Number(number) => number.as_u64(),
+            serde_json::Value::Bool(value) => Some(u64::from(*value)),
+            serde_json::Value::String(value) => match value.strip_prefix("0x") {
+                Some(hex) => Some(u64::from_str_radix(hex, 16)?),
+                None => None,
+            },
+            _ => return Err(anyhow::anyhow!("Report field {value} can't be compared.")),
+        };
+        if let Some(integer) = integer {
+            return cert_int(ext)
+                .map(|cert_value| cert_value == integer)
+                .ok_or_else(|| anyhow::anyhow!("The VEK extension is not an integer."));
+        }
+
+        let bytes = hex::decode(value.as_str().unwrap_or_default())
+            .context("Report field is not hex encoded")?;
+        Ok(match ext.value {
+            [0x4, len, octets @ ..] if octets.len() == *len as usize => octets == bytes.as_slice(),
+            raw => raw == bytes.as_slice(),
+        })
+    }
+
+    // Check each extra VEK extension against its report field. An extension missing from the
+    // VEK only warns, unless strict_tcb is set.
+    fn check_extra_oids(
+        extra_oids: &ExtraOids,
+        extensions: &HashMap<Oid, &X509Extension>,
+        att_report: &AttestationReport,
+        strict_tcb: bool,
+        checks: &mut Checks,
+        quiet: bool,
+    ) -> Result<()> {
+        let report = display::report_json(att_report);
+
+        for (oid, field) in &extra_oids.0 {
+            let value = report
+                .pointer(&format!("/{}", field.replace('.', "/")))
+                .ok_or_else(|| anyhow::anyhow!("--extra-oids: the report has no field {field}."))?;
+            let name = format!("extra_{field}");
+
+            let Some(ext) = extensions.get(oid) else {
+                let missing =
+                    anyhow::anyhow!("The VEK has no extension {oid} for the report {field}.");
+                if strict_tcb {
+                    checks.run(&name, Err::<(), _>(missing))?;
+                } else if !quiet {
+                    eprintln!("WARNING: {missing}");
+                }
+                continue;
+            };
+
+            let result = extension_matches(ext, value)
+                .with_context(|| {
+                    format!("Could not compare VEK extension {oid} with the report {field}")
+                })
+                .and_then(|matches| match matches {
+                    true => Ok(()),
+                    false => Err(anyhow::anyhow!(
+                        "Report {field} and Certificate extension {oid} mismatch encountered."
+                    )),
+                });
+            if checks.run(&name, result)?.is_some() && !quiet {
+                check_passed(&format!(
+                    "Report {field} from certificate extension {oid} matches the attestation report."
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Fail on a mismatch between the reported and the VEK TCB component, unless it is allowed.
+    fn tcb_mismatch(name: &str, allowed: bool, quiet: bool) -> Result<()> {
+        if !allowed {
+            return Err(anyhow::anyhow!(
+                "Report TCB {name} and Certificate {name} mismatch encountered."
+            ));
+        }
+        if !quiet {
+            eprintln!("WARNING: Report TCB {name} and Certificate {name} mismatch allowed by --allow-tcb-mismatch.");
+        }
+
+        Ok(())
+    }
+
+    // Check that no TCB component the VEK encodes is newer than the reported one, which is a
+    // sign of a VCEK issued for a later TCB being paired with an older report.
+    fn check_tcb_ordering(fields: &[TcbField], quiet: bool) -> Result<()> {
+        let ahead: Vec<String> = fields
+            .iter()
+            .filter(|field| field.vek_newer)
+            .map(|field| {
+                format!(
+                    "{} {} > {}",
+                    field.component.name(),
+                    field.expected,
+                    field.actual
+                )
+            })
+            .collect();
+        if !ahead.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The VEK encodes a newer TCB than the attestation report: {}.",
+                ahead.join(", ")
+            ));
+        }
+        if !quiet {
+            check_passed("The TCB encoded in the VEK is not newer than the reported TCB.");
+        }
+
+        Ok(())
+    }
+
+    // Checks run on a report. The first failure is returned in fail-fast mode, while
+    // --report-all records them all and returns them together once every check ran.
+    pub struct Checks {
+        report_all: bool,
+        failures: Vec<anyhow::Error>,
+        // Name of every check run, along with the error of the ones that failed.
+        results: Vec<(String, Option<String>)>,
+        // Set once a failure is returned, so it isn't recorded again on its way up.
+        returned: bool,
+    }
+
+    impl Checks {
+        pub fn new(report_all: bool) -> Checks {
+            Checks {
+                report_all,
+                failures: Vec::new(),
+                results: Vec::new(),
+                returned: false,
+            }
+        }
+
+        // The value of a check that passed, or None for a failure recorded by --report-all.
+        fn run<T>(&mut self, name: &str, result: Result<T>) -> Result<Option<T>> {
+            if self.returned {
+                return result.map(Some);
+            }
+            self.results.push((
+                name.to_string(),
+                result.as_ref().err().map(|err| format!("{err:#}")),
+            ));
+            match result {
+                Ok(value) => Ok(Some(value)),
+                Err(err) if self.report_all => {
+                    self.failures.push(err);
+                    Ok(None)
+                }
+                Err(err) => {
+                    self.returned = true;
+                    Err(err)
+                }
+            }
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            match self.failures.len() {
+                0 => Ok(()),
+                1 => Err(self.failures.remove(0)),
+                count => {
+                    let failures: Vec<String> = self
+                        .failures
+                        .drain(..)
+                        .map(|err| format!("  - {err:#}"))
+                        .collect();
+                    Err(anyhow::anyhow!(
+                        "{count} checks failed:\n{}",
+                        failures.join("\n")
+                    ))
+                }
+            }
+        }
+
+        // Result of every check run, as a JSON array.
+        pub fn to_json(&self) -> serde_json::Value {
+            self.results
+                .iter()
+                .map(|(name, error)| match error {
+                    None => serde_json::json!({ "check": name, "passed": true }),
+                    Some(error) => {
+                        serde_json::json!({ "check": name, "passed": false, "error": error })
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[derive(Parser)]
+    #[command(allow_missing_positional = true)]
+    pub struct Args {
+        /// Path to directory containing VCEK, defaulting to $SNPGUEST_CERTS_DIR.
+        #[arg(value_name = "certs-dir", conflicts_with_all = ["chain_file", "vek"])]
+        pub certs_dir: Option<PathBuf>,
+
+        /// Path to attestation report to use for validation, or "-" to read it from stdin.
+        #[arg(value_name = "att-report-path", required_unless_present = "report")]
+        pub positional_report: Option<PathBuf>,
+
+        /// Path to directory containing VCEK, instead of the certs-dir positional.
+        #[arg(long = "certs-dir", value_name = "certs-dir", conflicts_with_all = ["chain_file", "vek"])]
+        pub certs_dir_flag: Option<PathBuf>,
+
+        /// Path to attestation report, instead of the att-report-path positional.
+        #[arg(long, value_name = "report")]
+        pub report: Option<PathBuf>,
+
+        // Report to verify, from --report or the positionals once resolved.
+        #[arg(skip)]
+        pub att_report_path: PathBuf,
+
+        /// Path to a single PEM file containing the ARK, ASK and VEK, or "-" to read it from stdin.
+        #[arg(long, value_name = "chain-file", conflicts_with = "vek")]
+        pub chain_file: Option<PathBuf>,
+
+        /// Path to the VCEK or VLEK to verify the report with, or "-" to read it from stdin, used
+        /// instead of a certs-dir.
+        #[arg(long, value_name = "vek")]
+        pub vek: Option<PathBuf>,
+
+        /// Path to the PEM file holding the ASK (or ASVK) and ARK, as served by the KDS
+        /// cert_chain endpoint. The --vek is verified against it before the report.
+        #[arg(long, value_name = "ca-chain", requires = "vek")]
+        pub ca_chain: Option<PathBuf>,
+
+        /// Format to print the outcome of the verification in.
+        #[arg(short, long, value_enum, default_value_t = VerifyOutput::Text)]
+        pub output: VerifyOutput,
+
+        /// Processor model that produced the report. Detected from the report when not provided.
+        #[arg(short, long, value_name = "processor-model")]
+        pub processor_model: Option<ProcType>,
+
+        /// Take the processor model from the common names of the VEK when the report does not
+        /// identify it, instead of verifying as Milan.
+        #[arg(long, conflicts_with = "processor_model")]
+        pub assume_model_from_cert: bool,
+
+        /// Run the TCB Verification Exclusively.
+        #[arg(short, long, conflicts_with = "signature")]
+        pub tcb: bool,
+
+        /// Fail if the certs-dir holds anything but SNP certificates, or two of the same role.
+        #[arg(long, conflicts_with_all = ["chain_file", "vek"])]
+        pub strict_dir: bool,
+
+        /// Fail TCB verification if the VEK is missing any of the expected OIDs.
+        #[arg(long, conflicts_with = "signature")]
+        pub strict_tcb: bool,
+
+        /// Reject VCEKs with a hardware ID in the deprecated raw format, instead of warning.
+        #[arg(long, conflicts_with = "signature")]
+        pub no_legacy_certs: bool,
+
+        /// Skip only the comparison of the VCEK hardware ID with the chip ID, such as for
+        /// reports taken after a live migration. The TCB components are still compared.
+        #[arg(long, conflicts_with_all = ["no_legacy_certs", "signature"])]
+        pub skip_hwid: bool,
+
+        /// Minimum reported TCB to accept, e.g. bootloader=3,tee=0,snp=8,microcode=115. Checked
+        /// even with --signature.
+        #[arg(long, value_name = "min-tcb")]
+        pub min_tcb: Option<MinTcb>,
+
+        /// TCB components allowed to differ from the VEK with a warning, e.g. microcode,snp.
+        #[arg(long, value_name = "field", conflicts_with = "signature")]
+        pub allow_tcb_mismatch: Option<AllowedTcbMismatches>,
+
+        /// Fail if any TCB component encoded in the VEK is newer than the reported TCB.
+        #[arg(long, conflicts_with = "signature")]
+        pub check_tcb_ordering: bool,
+
+        /// JSON file mapping further VEK extension OIDs to the report fields they must match,
+        /// named as in display report --output json, e.g. {"1.3.6.1.4.1.3704.1.3.10": "reported_tcb.fmc"}.
+        #[arg(long, value_name = "extra-oids", conflicts_with = "signature")]
+        pub extra_oids: Option<PathBuf>,
+
+        /// Fail if the reported TCB is older than the current TCB of the platform. Checked even
+        /// with --signature.
+        #[arg(long)]
+        pub no_downgrade: bool,
+
+        /// Fail if any component of the current TCB of the platform differs from the reported TCB.
+        /// Checked even with --signature.
+        #[arg(long)]
+        pub require_current_equals_reported: bool,
+
+        /// Required guest policy flags, e.g. debug=false,smt=false.
+        #[arg(long, value_name = "policy-require")]
+        pub policy_require: Option<PolicyRequirements>,
+
+        /// Fail if the guest policy allows debugging the guest.
+        #[arg(long)]
+        pub require_no_debug: bool,
+
+        /// Minimum firmware ABI version (major.minor) the guest policy must require, e.g. 1.51.
+        #[arg(long, value_name = "min-abi")]
+        pub min_abi: Option<AbiVersion>,
+
+        /// Expected report data (64 bytes, hex encoded), such as a nonce sent with the request.
+        #[arg(long, value_name = "report-data")]
+        pub report_data: Option<String>,
+
+        /// VMPL the report must have been requested at, e.g. 0 for the most privileged level.
+        #[arg(long, value_name = "require-vmpl")]
+        pub require_vmpl: Option<u32>,
+
+        /// Key the report must have been signed with, to only accept hardware rooted (vcek) or
+        /// vendor rooted (vlek) reports.
+        #[arg(long, value_enum, value_name = "signer")]
+        pub require_signed_by: Option<Endorsement>,
+
+        /// Expected SHA-384 digest of the ID key that signed the ID block (48 bytes, hex encoded).
+        #[arg(long, value_name = "id-key-digest")]
+        pub id_key_digest: Option<String>,
+
+        /// Expected SHA-384 digest of the author key that certified the ID key (48 bytes, hex encoded).
+        #[arg(long, value_name = "author-key-digest")]
+        pub author_key_digest: Option<String>,
+
+        /// Expected family ID of the guest (16 bytes), hex encoded or as a file holding it.
+        #[arg(long, value_name = "expected-family-id")]
+        pub expected_family_id: Option<String>,
+
+        /// Expected image ID of the guest (16 bytes), hex encoded or as a file holding it.
+        #[arg(long, value_name = "expected-image-id")]
+        pub expected_image_id: Option<String>,
+
+        /// Fail if any reserved field of the report isn't zero, listing the offsets that aren't.
+        #[arg(long)]
+        pub strict_reserved: bool,
+
+        /// Print the subject, serial number, validity and TCB of the VEK the report was verified
+        /// with, as vek_info in the claims of the json and yaml outputs.
+        #[arg(long, conflicts_with = "signature")]
+        pub print_vek_info: bool,
+
+        /// Run every check even after one fails, and report all the failures at once.
+        #[arg(long)]
+        pub report_all: bool,
+
+        /// Print the KDS URLs of the VCEK and CA chain of the report when verification fails.
+        #[arg(long)]
+        pub print_urls: bool,
+
+        /// Print the claims of the report as JSON once it passed verification, each marked by
+        /// whether the checks that were run verified it, instead of the verification steps.
+        #[arg(long, conflicts_with = "output")]
+        pub print_claims: bool,
+
+        /// Also write a record of the verification to this file, as JSON or, for a .yaml or
+        /// .yml path, YAML.
+        #[arg(long, value_name = "report-out")]
+        pub report_out: Option<PathBuf>,
+
+        /// Run the Signature Verification Exclusively.
+        #[arg(short, long, conflicts_with = "tcb")]
+        pub signature: bool,
+
+        /// The report is an extended report. Without a certs-dir or chain file, verify it against
+        /// the chain embedded in it, whose ARK must be one bundled with snpguest, falling back to
+        /// the KDS when the chain is incomplete.
+        #[arg(long)]
+        pub extended: bool,
+
+        /// Fetch the VCEK, ASK and ARK missing from the certs-dir from the KDS, verify the chain
+        /// and then the report. Fetched certificates are saved in the certs-dir, if given.
+        #[arg(long, conflicts_with_all = ["chain_file", "vek", "extended"])]
+        pub fetch: bool,
+
+        #[command(flatten)]
+        pub kds: KdsArgs,
+
+        #[command(flatten)]
+        pub cache: CacheArgs,
+    }
+
+    // List the TCB components where the reported TCB is behind the current TCB, the ones
+    // compared as a downgrade.
+    fn tcb_downgrades(reported_tcb: &Tcb, current_tcb: &Tcb) -> Vec<String> {
+        reported_tcb
+            .compare(current_tcb)
+            .into_iter()
+            .filter(|(_, _, _, relation)| *relation == TcbRelation::Downgrade)
+            .map(|(name, reported, current, _)| format!("{name} {reported} < {current}"))
+            .collect()
+    }
+
+    // Compare the reported TCB against the current TCB, printing both side by side and
+    // failing on a downgrade or any difference if requested.
+    fn verify_tcb_downgrade(
+        att_report: &AttestationReport,
+        proc_model: &ProcType,
+        no_downgrade: bool,
+        require_equal: bool,
+        quiet: bool,
+    ) -> Result<()> {
+        let reported_tcb = proc_model.decode_tcb(&att_report.reported_tcb)?;
+        let current_tcb = proc_model.decode_tcb(&att_report.current_tcb)?;
+        let comparison = reported_tcb.compare(&current_tcb);
+
+        if !quiet {
+            println!("Reported TCB vs current TCB:");
+            for (name, reported, current, relation) in &comparison {
+                println!("  {name:<12}{reported:>4}{current:>4}  ({relation})");
+            }
+        }
+
+        let differences: Vec<String> = comparison
+            .iter()
+            .filter(|(_, _, _, relation)| *relation != TcbRelation::Equal)
+            .map(|(name, reported, current, relation)| {
+                format!("{name} {reported} reported, {current} current ({relation})")
+            })
+            .collect();
+        if require_equal && !differences.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Current TCB differs from the reported TCB: {}",
+                differences.join(", ")
+            ));
+        }
+
+        let downgrades = tcb_downgrades(&reported_tcb, &current_tcb);
+
+        if downgrades.is_empty() {
+            if !quiet {
+                check_passed("Reported TCB is not behind the current TCB.");
+            }
+        } else if no_downgrade {
+            return Err(anyhow::anyhow!(
+                "Reported TCB is older than the current TCB: {}",
+                downgrades.join(", ")
+            ));
+        } else if !quiet {
+            eprintln!(
+                "WARNING: Reported TCB is older than the current TCB: {}",
+                downgrades.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    // Check the report data against the value the report was requested with, to reject replays.
+    fn verify_report_data(
+        att_report: &AttestationReport,
+        expected: &str,
+        quiet: bool,
+    ) -> Result<()> {
+        let expected = hex::decode(expected.trim().trim_start_matches("0x"))
+            .context("Expected report data is not valid hex.")?;
+        if expected.len() != att_report.report_data.len() {
+            return Err(anyhow::anyhow!(
+                "Expected report data must be {} bytes, but {} were given.",
+                att_report.report_data.len(),
+                expected.len()
+            ));
+        }
+        if expected != att_report.report_data {
+            return Err(anyhow::anyhow!(
+                "Report data does not match the expected report data."
+            ));
+        }
+        if !quiet {
+            check_passed("Report data matches the expected report data.");
+        }
+
+        Ok(())
+    }
+
+    // Expected value of a report field, given hex encoded or as the path of a file holding it
+    // raw or hex encoded.
+    fn expected_bytes(name: &str, input: &str, len: usize) -> Result<Vec<u8>> {
+        let decode = |text: &str| hex::decode(text.trim().trim_start_matches("0x")).ok();
+
+        let bytes = match decode(input) {
+            Some(bytes) if !Path::new(input).exists() => bytes,
+            _ => {
+                let contents = fs::read(input).with_context(|| {
+                    format!(
+                        "Expected {name} is neither hex encoded bytes nor a readable file: {input}"
+                    )
+                })?;
+                match std::str::from_utf8(&contents).ok().and_then(decode) {
+                    Some(bytes) if bytes.len() == len => bytes,
+                    _ => contents,
+                }
+            }
+        };
+        if bytes.len() != len {
+            return Err(anyhow::anyhow!(
+                "Expected {name} must be {len} bytes, but {} were given.",
+                bytes.len()
+            ));
+        }
+
+        Ok(bytes)
+    }
+
+    // Check the family or image ID the guest was launched with against the expected one.
+    fn verify_launch_id(name: &str, actual: &[u8; 16], expected: &str, quiet: bool) -> Result<()> {
+        let expected = expected_bytes(name, expected, actual.len())?;
+        if expected != actual {
+            return Err(anyhow::anyhow!(
+                "Report {name} {} does not match the expected {name} {}.",
+                hex::encode(actual),
+                hex::encode(&expected)
+            ));
+        }
+        if !quiet {
+            check_passed(&format!("Report {name} matches the expected {name}."));
+        }
+
+        Ok(())
+    }
+
+    // Check that the reserved fields of the report are zero, as firmware leaves them.
+    fn verify_reserved(att_report: &AttestationReport, quiet: bool) -> Result<()> {
+        let nonzero = report::nonzero_reserved(att_report)?;
+        if !nonzero.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Reserved fields of the report are not zero at offsets {}.",
+                nonzero.join(", ")
+            ));
+        }
+        if !quiet {
+            check_passed("Reserved fields of the report are zero.");
+        }
+
+        Ok(())
+    }
+
+    // Check that the guest policy doesn't allow the host to debug the guest.
+    fn verify_no_debug(att_report: &AttestationReport, quiet: bool) -> Result<()> {
+        if decode_policy(att_report.policy.0).debug_allowed {
+            return Err(anyhow::anyhow!(
+                "The guest policy allows debugging (bit 19 is set), so the host can read and modify guest memory."
+            ));
+        }
+        if !quiet {
+            check_passed("Guest policy doesn't allow debugging.");
+        }
+
+        Ok(())
+    }
+
+    // Check that the guest was launched requiring at least the given firmware ABI version.
+    fn verify_min_abi(
+        att_report: &AttestationReport,
+        min_abi: AbiVersion,
+        quiet: bool,
+    ) -> Result<()> {
+        let abi = decode_policy(att_report.policy.0).abi();
+        if abi < min_abi {
+            return Err(anyhow::anyhow!(
+                "The guest policy requires firmware ABI {abi}, but at least ABI {min_abi} is required."
+            ));
+        }
+        if !quiet {
+            check_passed(&format!(
+                "Guest policy ABI {abi} meets the minimum ABI {min_abi}."
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Check the VMPL the report was requested at against the required one.
+    fn verify_vmpl(att_report: &AttestationReport, required: u32, quiet: bool) -> Result<()> {
+        if att_report.vmpl != required {
+            return Err(anyhow::anyhow!(
+                "The report was requested at VMPL {}, but VMPL {required} is required.",
+                att_report.vmpl
+            ));
+        }
+        if !quiet {
+            check_passed("Report VMPL matches the required VMPL.");
+        }
+
+        Ok(())
+    }
+
+    // Check the key that signed the report against the one the policy requires.
+    fn verify_signer(signer: &Endorsement, required: &Endorsement, quiet: bool) -> Result<()> {
+        if signer != required {
+            return Err(anyhow::anyhow!(
+                "The report was signed by the {signer}, but the {required} is required."
+            ));
+        }
+        if !quiet {
+            check_passed(&format!("Report was signed by the required {required}."));
+        }
+
+        Ok(())
+    }
+
+    // Check a key digest of the ID block against the expected one. A digest the report doesn't
+    // carry is an error rather than a mismatch.
+    fn verify_key_digest(
+        name: &str,
+        actual: &[u8; 48],
+        present: bool,
+        expected: &str,
+        quiet: bool,
+    ) -> Result<()> {
+        let expected = hex::decode(expected.trim().trim_start_matches("0x"))
+            .with_context(|| format!("Expected {name} digest is not valid hex."))?;
+        if expected.len() != actual.len() {
+            return Err(anyhow::anyhow!(
+                "Expected {name} digest must be {} bytes, but {} were given.",
+                actual.len(),
+                expected.len()
+            ));
+        }
+        if !present {
+            return Err(anyhow::anyhow!(
+                "An {name} digest was expected, but the report has none."
+            ));
+        }
+        if expected != actual {
+            return Err(anyhow::anyhow!(
+                "Report {name} digest does not match the expected {name} digest."
+            ));
+        }
+        if !quiet {
+            check_passed(&format!(
+                "Report {name} digest matches the expected {name} digest."
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Add the reasons of an OpenSSL error stack to the error chain. The sev crate wraps the
+    // stack in an io::Error, so look inside one as well.
+    fn openssl_context<T, E>(result: std::result::Result<T, E>, msg: &'static str) -> Result<T>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        result.map_err(|err| {
+            let source: &(dyn std::error::Error + 'static) = &err;
+            let stack = source.downcast_ref::<ErrorStack>().or_else(|| {
+                source
+                    .downcast_ref::<std::io::Error>()
+                    .and_then(|err| err.get_ref())
+                    .and_then(|err| err.downcast_ref::<ErrorStack>())
+            });
+            let reasons: Vec<String> = stack
+                .map(|stack| {
+                    stack
+                        .errors()
+                        .iter()
+                        .map(|err| match (err.library(), err.reason()) {
+                            (Some(library), Some(reason)) => format!("{reason} ({library})"),
+                            (_, reason) => reason.unwrap_or("unknown reason").to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let err = anyhow::Error::new(err);
+            if reasons.is_empty() {
+                err.context(msg)
+            } else {
+                err.context(format!("OpenSSL: {}", reasons.join(", ")))
+                    .context(msg)
+            }
+        })
+    }
+
+    fn verify_attestation_signature(
+        vcek: Certificate,
+        att_report: AttestationReport,
+        quiet: bool,
+    ) -> Result<()> {
+        let vek_pubkey = openssl_context(
+            vcek.public_key(),
+            "Failed to get the public key from the VEK.",
+        )?;
+        let vek_pubkey = openssl_context(
+            vek_pubkey.ec_key(),
+            "Failed to convert VEK public key into ECkey.",
+        )?;
+
+        // AMD endorsement keys are always ECDSA P-384 keys.
+        let curve = vek_pubkey.group().curve_name();
+        if curve != Some(Nid::SECP384R1) {
+            return Err(anyhow::anyhow!(
+                "The VEK public key is not on the P-384 curve (found {:?}).",
+                curve
+            ));
+        }
+
+        check_vek_key_usage(&vcek)?;
+
+        // Get the attestation report signature
+        let ar_signature = openssl_context(
+            EcdsaSig::try_from(&att_report.signature),
+            "Failed to get ECDSA Signature from attestation report.",
+        )?;
+        let base_message_digest: [u8; 48] = report::report_digest(&att_report)?;
+        debug!("Verifying the report signature with the VEK public key");
+        trace!("Report digest {}", hex::encode(base_message_digest));
+
+        // Verify signature
+        if openssl_context(
+            ar_signature.verify(base_message_digest.as_ref(), vek_pubkey.as_ref()),
+            "Failed to verify attestation report signature with VEK public key.",
+        )? {
+            if !quiet {
+                check_passed("VEK signed the Attestation Report!");
+            }
+        } else {
+            return Err(anyhow::anyhow!("VEK did NOT sign the Attestation Report!"));
+        }
+
+        Ok(())
+    }
+
+    // Make sure the VEK's key usage extensions (where present) allow it to sign reports.
+    fn check_vek_key_usage(vek: &Certificate) -> Result<()> {
+        let vek_der = vek.to_der().context("Could not convert VEK to der.")?;
+        let (_, vek_x509) = X509Certificate::from_der(&vek_der)
+            .context("Could not create X509Certificate from der")?;
+
+        if let Some(key_usage) = vek_x509
+            .key_usage()
+            .context("Failed to parse VEK key usage.")?
+        {
+            if !key_usage.value.digital_signature() {
+                return Err(anyhow::anyhow!(
+                    "The VEK key usage does not allow digital signatures."
+                ));
+            }
+        }
+
+        if let Some(ext_key_usage) = vek_x509
+            .extended_key_usage()
+            .context("Failed to parse VEK extended key usage.")?
+        {
+            // Only the well-known web/email purposes are set, none of which cover attestation.
+            if !ext_key_usage.value.any && ext_key_usage.value.other.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "The VEK extended key usage does not allow signing attestation reports."
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Check the cert extension byte to value, which must be expected_len bytes long. A hardware
+    // ID is as long as the chip ID of the processor model (64 bytes, or 8 on Turin), and a TCB
+    // component one byte. A malformed extension is an error rather than a mismatch.
+    fn check_cert_bytes(ext: &X509Extension, val: &[u8], expected_len: usize) -> Result<bool> {
+        trace!(
+            "Comparing VEK extension {} with reported {}",
+            hex::encode(ext.value),
+            hex::encode(val)
+        );
+        match ext.value {
+            // Integer
+            [0x2, len @ (0x1 | 0x2), bytes @ ..] if bytes.len() == usize::from(*len) => {
+                Ok(val.len() == expected_len && bytes.last() == val.first())
+            }
+            [0x2, ..] => Err(anyhow::anyhow!(
+                "The VEK extension {} holds a malformed integer.",
+                ext.oid
+            )),
+            // Octet String
+            [0x4, len, bytes @ ..] if bytes.len() == usize::from(*len) => {
+                Ok(bytes.len() == expected_len && bytes == val)
+            }
+            [0x4, ..] => Err(anyhow::anyhow!(
+                "The VEK extension {} holds a malformed octet string.",
+                ext.oid
+            )),
+            // Keep around for a bit for old VCEK without x509 DER encoding.
+            raw if raw.len() == 0x40 => Ok(expected_len == 0x40 && raw == val),
+            _ => Err(anyhow::anyhow!(
+                "The VEK extension {} holds a value of unknown encoding.",
+                ext.oid
+            )),
+        }
+    }
+
+    // Whether a VEK extension holds the raw 64 bytes of a hardware ID, as VCEKs issued before
+    // the extensions were DER encoded do.
+    fn is_legacy_hwid(ext: &X509Extension) -> bool {
+        !matches!(ext.value.first(), Some(0x2) | Some(0x4)) && ext.value.len() == 0x40
+    }
+
+    // Reject a VCEK with a legacy hardware ID, or warn about it once per run.
+    fn check_legacy_hwid(legacy_hwid: bool, no_legacy_certs: bool, quiet: bool) -> Result<()> {
+        static WARNED: std::sync::Once = std::sync::Once::new();
+
+        if !legacy_hwid {
+            return Ok(());
+        }
+        if no_legacy_certs {
+            return Err(anyhow::anyhow!(
+                "The VCEK encodes its hardware ID as raw bytes, a legacy format rejected by --no-legacy-certs."
+            ));
+        }
+        if !quiet {
+            WARNED.call_once(|| {
+                eprintln!(
+                    "WARNING: The VCEK encodes its hardware ID as raw bytes, a deprecated format. Fetch a current VCEK from the KDS."
+                )
+            });
+        }
+
+        Ok(())
+    }
+
+    // Check that the reported TCB and the VEK agree with the generation on carrying an FMC,
+    // returning the reported FMC when there is one.
+    fn check_fmc_layout(
+        proc_model: &ProcType,
+        reported_tcb: &Tcb,
+        vek_fmc: bool,
+    ) -> Result<Option<u8>> {
+        match (proc_model.has_fmc(), reported_tcb.fmc) {
+            (true, None) => Err(anyhow::anyhow!(
+                "{proc_model} attestation reports carry an FMC in their reported TCB, but the report does not."
+            )),
+            (false, _) if vek_fmc => Err(anyhow::anyhow!(
+                "The VEK carries an FMC, which {proc_model} attestation reports don't. Check the processor model."
+            )),
+            (_, fmc) => Ok(fmc),
+        }
+    }
+
+    // Compare the TCB of a report with the one its VEK was issued for, field by field, along
+    // with the hardware ID of a VCEK. Nothing is printed or enforced here, the caller decides
+    // which differences fail the verification.
+    fn verify_attestation_tcb(
+        vek: &Certificate,
+        att_report: &AttestationReport,
+        proc_model: &ProcType,
+    ) -> Result<TcbVerification> {
+        if att_report.version < proc_model.min_report_version() {
+            return Err(anyhow::anyhow!(
+                "{proc_model} attestation reports are at least version {}, but the report is version {}.",
+                proc_model.min_report_version(),
+                att_report.version
+            ));
+        }
+
+        let reported_tcb = proc_model.decode_tcb(&att_report.reported_tcb)?;
+        debug!("Reported TCB: {reported_tcb}");
+
+        let vek_der = vek.to_der().context("Could not convert VEK to der.")?;
+        let (_, vek_x509) = X509Certificate::from_der(&vek_der)
+            .context("Could not create X509Certificate from der")?;
+
+        // Collect extensions from VEK
+        let extensions: HashMap<Oid, &X509Extension> = vek_x509
+            .extensions_map()
+            .context("Failed getting VEK oids.")?;
+
+        let vek_type: CertType = parse_common_name(vek_x509.subject())?;
+        let missing_oids = expected_oids(proc_model, &vek_type)
+            .into_iter()
+            .filter(|snp_oid| !extensions.contains_key(&snp_oid.oid()))
+            .collect();
+
+        let mut fields = compare_tcb(&reported_tcb, &extensions)?;
+        for field in &fields {
+            debug!(
+                "VEK {} is {}, the report {}",
+                field.component.name(),
+                field.expected,
+                field.actual
+            );
+        }
+
+        // Compare HWID information only on VCEK
+        let (mut legacy_hwid, mut hwid_error) = (false, None);
+        if let (CertType::VCEK, Some(hwid_ext)) = (&vek_type, extensions.get(&SnpOid::HwId.oid())) {
+            legacy_hwid = is_legacy_hwid(hwid_ext);
+            let chip_id = match report_chip_id(att_report, proc_model) {
+                Ok(chip_id) if cert_hwid(hwid_ext).len() != chip_id.len() => {
+                    hwid_error = Some(format!(
+                        "The VCEK hardware ID is {} bytes long, but the {proc_model} chip ID is {} bytes long.",
+                        cert_hwid(hwid_ext).len(),
+                        chip_id.len()
+                    ));
+                    chip_id
+                }
+                Ok(chip_id) => chip_id,
+                Err(err) => {
+                    hwid_error = Some(format!("{err:#}"));
+                    &att_report.chip_id[..]
+                }
+            };
+            fields.push(compare_hwid(hwid_ext, chip_id, proc_model)?);
+        }
+
+        Ok(TcbVerification {
+            reported_tcb,
+            vek_type,
+            missing_oids,
+            vek_fmc: extensions.contains_key(&SnpOid::Fmc.oid()),
+            legacy_hwid,
+            hwid_error,
+            fields,
+            vek_info: VekInfo::new(&vek_x509, &extensions),
+        })
+    }
+
+    // Outcome of comparing a report with its VEK: one field for every TCB component the VEK
+    // encodes and, unless skipped, the hardware ID of a VCEK, along with the VEK metadata.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TcbVerification {
+        pub reported_tcb: Tcb,
+        pub vek_type: CertType,
+        // OIDs expected of the VEK for the processor model, that it doesn't carry.
+        pub missing_oids: Vec<SnpOid>,
+        // The VEK carries an FMC.
+        pub vek_fmc: bool,
+        // The VCEK hardware ID is in the deprecated raw format.
+        pub legacy_hwid: bool,
+        // Why the hardware ID can't match the chip ID at all, such as a masked chip ID.
+        pub hwid_error: Option<String>,
+        pub fields: Vec<TcbField>,
+        pub vek_info: VekInfo,
+    }
+
+    // Value a VEK was issued for and the one in the report, hex encoded for the hardware ID.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TcbField {
+        pub component: SnpOid,
+        pub expected: String,
+        pub actual: String,
+        pub matched: bool,
+        // The VEK encodes a newer value than the report.
+        pub vek_newer: bool,
+    }
+
+    // Compare each component of the reported TCB with the VEK extension carrying it, if any.
+    fn compare_tcb(
+        reported_tcb: &Tcb,
+        extensions: &HashMap<Oid, &X509Extension>,
+    ) -> Result<Vec<TcbField>> {
+        reported_tcb
+            .values()
+            .into_iter()
+            .filter_map(|(component, value)| {
+                Some((component, value, extensions.get(&component.oid())?))
+            })
+            .map(|(component, value, ext)| {
+                Ok(TcbField {
+                    component,
+                    expected: cert_int(ext).map_or_else(
+                        || hex::encode(ext.value),
+                        |cert_value| cert_value.to_string(),
+                    ),
+                    actual: value.to_string(),
+                    matched: check_cert_bytes(ext, &value.to_le_bytes(), 1)?,
+                    vek_newer: cert_int(ext)
+                        .is_some_and(|cert_value| cert_value > u64::from(value)),
+                })
+            })
+            .collect()
+    }
+
+    // Compare the chip ID of a report with the hardware ID of its VCEK, which must be as long
+    // as the chip IDs of the processor model.
+    fn compare_hwid(
+        ext: &X509Extension,
+        chip_id: &[u8],
+        proc_model: &ProcType,
+    ) -> Result<TcbField> {
+        Ok(TcbField {
+            component: SnpOid::HwId,
+            expected: hex::encode(cert_hwid(ext)),
+            actual: hex::encode(chip_id),
+            matched: check_cert_bytes(ext, chip_id, proc_model.chip_id_len())?,
+            vek_newer: false,
+        })
+    }
+
+    // Metadata of the VEK a report was verified with, for the record of auditors.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct VekInfo {
+        pub common_name: String,
+        pub serial: String,
+        pub not_before: String,
+        pub not_after: String,
+        pub tcb: Vec<(&'static str, u64)>,
+    }
+
+    impl VekInfo {
+        fn new(vek_x509: &X509Certificate, extensions: &HashMap<Oid, &X509Extension>) -> VekInfo {
+            let common_name = vek_x509
+                .subject()
+                .iter_common_name()
+                .next()
+                .and_then(|cn| cn.as_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let validity = vek_x509.validity();
+
+            VekInfo {
+                common_name,
+                serial: hex::encode(vek_x509.raw_serial()),
+                not_before: validity.not_before.to_string(),
+                not_after: validity.not_after.to_string(),
+                tcb: SnpOid::TCB
+                    .iter()
+                    .filter_map(|snp_oid| {
+                        let value = cert_int(extensions.get(&snp_oid.oid())?)?;
+                        Some((snp_oid.name(), value))
+                    })
+                    .collect(),
+            }
+        }
+
+        fn print(&self) {
+            let tcb: Vec<String> = self
+                .tcb
+                .iter()
+                .map(|(name, value)| format!("{name} {value}"))
+                .collect();
+
+            println!("VEK info:");
+            println!("  Subject CN:  {}", self.common_name);
+            println!("  Serial:      {}", self.serial);
+            println!("  Not before:  {}", self.not_before);
+            println!("  Not after:   {}", self.not_after);
+            println!("  TCB:         {}", tcb.join(", "));
+        }
+
+        fn claim(&self) -> claims::Claim {
+            let text = |value: &str| claims::ClaimValue::Text(value.to_string());
+            let tcb = self
+                .tcb
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_lowercase().replace(' ', "_"),
+                        claims::ClaimValue::Uint(*value),
+                    )
+                })
+                .collect();
+
+            claims::Claim {
+                name: "vek_info",
+                eat_key: None,
+                value: claims::ClaimValue::Map(vec![
+                    ("common_name".to_string(), text(&self.common_name)),
+                    ("serial".to_string(), text(&self.serial)),
+                    ("not_before".to_string(), text(&self.not_before)),
+                    ("not_after".to_string(), text(&self.not_after)),
+                    ("tcb".to_string(), claims::ClaimValue::Map(tcb)),
+                ]),
+            }
+        }
+    }
+
+    // Value of a TCB extension of the VEK, a DER integer.
+    fn cert_int(ext: &X509Extension) -> Option<u64> {
+        match ext.value {
+            [0x2, len, bytes @ ..] if bytes.len() == *len as usize && bytes.len() <= 8 => Some(
+                bytes
+                    .iter()
+                    .fold(0, |value, byte| value << 8 | u64::from(*byte)),
+            ),
+            _ => None,
+        }
+    }
+
+    // Get the chip ID of a report, cut to the length the generation uses. Fails when the
+    // bytes set in the chip ID field don't add up to that length.
+    fn report_chip_id<'a>(
+        att_report: &'a AttestationReport,
+        proc_model: &ProcType,
+    ) -> Result<&'a [u8]> {
+        let chip_id = &att_report.chip_id;
+        if chip_id.iter().all(|byte| *byte == 0) {
+            return Err(anyhow::anyhow!(
+                "The report chip ID is masked, so it can't be compared with the VCEK hardware ID."
+            ));
+        }
+
+        let expected = proc_model.chip_id_len();
+        let actual = if chip_id[8..].iter().all(|byte| *byte == 0) {
+            8
+        } else {
+            chip_id.len()
+        };
+        if actual != expected {
+            return Err(anyhow::anyhow!(
+                "The report chip ID is {actual} bytes long, but {proc_model} chip IDs are {expected} bytes long."
+            ));
+        }
+
+        Ok(&chip_id[..expected])
+    }
+
+    // Hardware ID in a VCEK extension, either DER encoded or raw (legacy).
+    fn cert_hwid<'a>(ext: &X509Extension<'a>) -> &'a [u8] {
+        match ext.value.first() {
+            Some(0x4) => ext.value.get(2..).unwrap_or_default(),
+            _ => ext.value,
+        }
+    }
+
+    // Read the attestation report, from stdin when the path is "-"
+    pub fn read_att_report(att_report_path: &Path) -> Result<AttestationReport> {
+        if att_report_path.as_os_str() == "-" {
+            report::read_report_from_reader(std::io::stdin().lock())
+                .context("Could not read attestation report from stdin")
+        } else if !att_report_path.exists() {
+            Err(anyhow::anyhow!("No attestation report was found. Provide an attestation report to request VEK from the KDS."))
+        } else {
+            report::read_report(att_report_path.to_path_buf())
+                .context("Could not open attestation report")
+        }
+    }
+
+    // Names a VCEK of the chip of the report can be stored under, as fetch vcek-batch writes
+    // them and by chip ID alone. None when the chip ID is masked.
+    fn chip_vcek_names(att_report: &AttestationReport) -> Result<Vec<String>> {
+        if att_report.chip_id.iter().all(|byte| *byte == 0) {
+            return Ok(vec![]);
+        }
+
+        let chip_id = hex::encode(att_report.chip_id);
+        let reported_tcb = bincode::serialize(&att_report.reported_tcb)
+            .context("Could not serialize TCB version.")?;
+
+        Ok(vec![
+            format!("{chip_id}-{}", hex::encode(reported_tcb)),
+            chip_id,
+        ])
+    }
+
+    // Find the VEK of the signing key in the directory, preferring the VLEK for unsigned reports.
+    // A VCEK named by the chip ID of the report takes priority over the plain vcek, and a VLEK
+    // is only used once the ASVK in the directory is confirmed to have signed it.
+    pub fn find_vek_in_dir(
+        certs_dir: &Path,
+        signing_key: Option<&Endorsement>,
+        att_report: &AttestationReport,
+    ) -> Result<Certificate> {
+        let vek = find_dir_vek(certs_dir, signing_key, att_report)?;
+        if cert_role(&vek)? == "vlek" {
+            check_vlek_signer(certs_dir, &vek)?;
+        }
+
+        Ok(vek)
+    }
+
+    fn find_dir_vek(
+        certs_dir: &Path,
+        signing_key: Option<&Endorsement>,
+        att_report: &AttestationReport,
+    ) -> Result<Certificate> {
+        let (wanted, other) = match signing_key {
+            Some(Endorsement::Vcek) => (Endorsement::Vcek, Endorsement::Vlek),
+            Some(Endorsement::Vlek) => (Endorsement::Vlek, Endorsement::Vcek),
+            None => match find_cert_in_dir(certs_dir, "vlek") {
+                Ok(vlek_path) => return convert_path_to_cert(&vlek_path, "vlek"),
+                Err(_) => (Endorsement::Vcek, Endorsement::Vlek),
+            },
+        };
+
+        if wanted == Endorsement::Vcek {
+            for name in chip_vcek_names(att_report)? {
+                if let Ok(vek_path) = find_cert_in_dir(certs_dir, &name) {
+                    debug!("Using the VCEK of the chip at {}", vek_path.display());
+                    return convert_path_to_cert(&vek_path, "vcek");
+                }
+            }
+        }
+
+        let wanted_name = wanted.to_string().to_lowercase();
+        match find_cert_in_dir(certs_dir, &wanted_name) {
+            Ok(vek_path) => convert_path_to_cert(&vek_path, &wanted_name),
+            Err(_)
+                if signing_key.is_some()
+                    && find_cert_in_dir(certs_dir, &other.to_string().to_lowercase()).is_ok() =>
+            {
+                Err(anyhow::anyhow!(
+                    "The report was {wanted}-signed but only a {other} is present."
+                ))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Read an extended report along with its embedded certificates, from stdin when the
+    // path is "-"
+    pub fn read_ext_att_report(
+        att_report_path: &Path,
+    ) -> Result<(AttestationReport, Vec<CertTableEntry>)> {
+        let bytes = if att_report_path.as_os_str() == "-" {
+            let mut bytes = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut bytes)
+                .context("Could not read extended report from stdin")?;
+            bytes
+        } else {
+            fs::read(att_report_path).context("Could not open extended report")?
+        };
+
+        report::parse_ext_report(&bytes)
+    }
+
+    // Read the report, with the certificates embedded in it when it is an extended report.
+    pub fn read_args_report(args: &Args) -> Result<(AttestationReport, Vec<CertTableEntry>)> {
+        if args.extended {
+            read_ext_att_report(&args.att_report_path)
+        } else {
+            Ok((read_att_report(&args.att_report_path)?, vec![]))
+        }
+    }
+
+    // Request the VCEK of an extended report from the KDS, when it doesn't embed its full chain.
+    pub fn fetch_kds_vek(
+        args: &Args,
+        att_report: &AttestationReport,
+        signing_key: Option<&Endorsement>,
+    ) -> Result<Certificate> {
+        if signing_key == Some(&Endorsement::Vlek) {
+            return Err(anyhow::anyhow!(
+                "The report was VLEK-signed but doesn't embed the full VLEK chain, and a VLEK can't be fetched from the KDS."
+            ));
+        }
+        let proc_model = match &args.processor_model {
+            Some(proc_model) => proc_model.clone(),
+            None => report::get_processor_model(att_report)
+                .context("Provide the processor model to fetch the VCEK from the KDS.")?,
+        };
+
+        let vcek = fetch::fetch_report_vcek(&args.kds, &args.cache, &proc_model, att_report)?;
+        Certificate::from_der(&vcek).context("Could not parse VCEK from the KDS")
+    }
+
+    // Whether the directory holds a VEK of the signing key, under any name find_vek_in_dir
+    // looks it up by. A missing directory holds none, as fetched certificates create it.
+    fn vek_in_dir(
+        certs_dir: &Path,
+        signing_key: Option<&Endorsement>,
+        att_report: &AttestationReport,
+    ) -> Result<bool> {
+        if !certs_dir.exists() {
+            return Ok(false);
+        }
+        if !certs_dir.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Certificate directory {} is not a directory",
+                certs_dir.display()
+            ));
+        }
+
+        let mut names = Vec::new();
+        if signing_key != Some(&Endorsement::Vlek) {
+            names.extend(chip_vcek_names(att_report)?);
+            names.push("vcek".to_string());
+        }
+        if signing_key != Some(&Endorsement::Vcek) {
+            names.push("vlek".to_string());
+        }
+
+        Ok(names
+            .iter()
+            .any(|name| find_cert_in_dir(certs_dir, name).is_ok()))
+    }
+
+    // Save a fetched certificate in the certs-dir, warning if it can't be.
+    fn save_fetched(
+        certs_dir: &Path,
+        cert_type: &CertType,
+        cert: &Certificate,
+        endorser: &Endorsement,
+        quiet: bool,
+    ) {
+        let result = std::fs::create_dir_all(certs_dir)
+            .context("Could not create the certs-dir")
+            .and_then(|_| {
+                certs::write_cert(
+                    certs_dir,
+                    cert_type,
+                    &cert.to_pem()?,
+                    certs::CertFormat::Pem,
+                    endorser,
+                    false,
+                )
+            });
+        if let (Err(err), false) = (result, quiet) {
+            eprintln!(
+                "WARNING: Could not save the fetched {cert_type:?} in the certs-dir: {err:#}"
+            );
+        }
+    }
+
+    // Verify the chain and then the report, fetching the VCEK, ASK (or ASVK) and ARK missing
+    // from the certs-dir from the KDS. A VLEK can't be fetched, so it must be in the certs-dir.
+    fn verify_fetched(
+        args: &Args,
+        att_report: AttestationReport,
+        signing_key: Option<&Endorsement>,
+        quiet: bool,
+    ) -> Result<()> {
+        let certs_dir = args.certs_dir.as_deref();
+        let local = |name: &str| -> Result<Option<Certificate>> {
+            match certs_dir.map(|dir| find_cert_in_dir(dir, name)) {
+                Some(Ok(path)) => convert_path_to_cert(&path, name).map(Some),
+                _ => Ok(None),
+            }
+        };
+
+        let (endorser, vek_type, sign_type) = match signing_key {
+            Some(Endorsement::Vlek) => (Endorsement::Vlek, "vlek", "asvk"),
+            _ => (Endorsement::Vcek, "vcek", "ask"),
+        };
+
+        // Only a VEK absent from the certs-dir is fetched, one that fails to load is an error
+        let vek = match certs_dir {
+            Some(dir) if vek_in_dir(dir, signing_key, &att_report)? => {
+                find_vek_in_dir(dir, signing_key, &att_report)?
+            }
+            _ if endorser == Endorsement::Vlek => {
+                return Err(anyhow::anyhow!(
+                    "The report was VLEK-signed but there is no VLEK in the certs-dir, and a VLEK can't be fetched from the KDS."
+                ));
+            }
+            _ => {
+                let vek = fetch_kds_vek(args, &att_report, signing_key)
+                    .context("Fetching the VCEK from the KDS failed")?;
+                if let Some(dir) = certs_dir {
+                    save_fetched(dir, &CertType::VCEK, &vek, &endorser, quiet);
+                }
+                vek
+            }
+        };
+
+        let (ark, ask) = match (local("ark")?, local(sign_type)?) {
+            (Some(ark), Some(ask)) => (ark, ask),
+            _ => {
+                let proc_model = match &args.processor_model {
+                    Some(proc_model) => proc_model.clone(),
+                    None => report::get_processor_model(&att_report).context(
+                        "Provide the processor model to fetch the CA chain from the KDS.",
+                    )?,
+                };
+                let (ark, ask) =
+                    fetch::fetch_ca_chain(&args.kds, &args.cache, &proc_model, &endorser)
+                        .context("Fetching the CA chain from the KDS failed")?;
+                let (ark, ask) = (Certificate::from(ark), Certificate::from(ask));
+                if let Some(dir) = certs_dir {
+                    save_fetched(dir, &CertType::ARK, &ark, &endorser, quiet);
+                    save_fetched(dir, &CertType::ASK, &ask, &endorser, quiet);
+                }
+                (ark, ask)
+            }
+        };
+
+        let cert_chain = Chain {
+            ca: ca::Chain { ark, ask },
+            vek,
+        };
+        certificate_chain::check_chain(
+            &cert_chain,
+            vek_type,
+            sign_type,
+            None,
+            quiet || args.output != VerifyOutput::Text,
+        )
+        .context("Certificate chain verification failed")?;
+
+        verify_report(args, att_report, cert_chain.vek, quiet)
+            .context("Attestation report verification failed")
+    }
+
+    // Verify the --vek against the CA chain file, and then the report against it.
+    fn verify_ca_chain(
+        args: &Args,
+        att_report: AttestationReport,
+        ca_chain: &Path,
+        quiet: bool,
+    ) -> Result<()> {
+        let vek_path = args
+            .vek
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("--ca-chain requires the VEK given with --vek."))?;
+        let (cert_chain, vek_type, sign_type) =
+            certificate_chain::load_chain(None, None, Some((ca_chain, vek_path)), None)?;
+        certificate_chain::check_chain(
+            &cert_chain,
+            vek_type,
+            sign_type,
+            None,
+            quiet || args.output != VerifyOutput::Text,
+        )
+        .context("Certificate chain verification failed")?;
+
+        verify_report(args, att_report, cert_chain.vek, quiet)
+            .context("Attestation report verification failed")
+    }
+
+    // Take the certs-dir and report from --certs-dir and --report, or from the positionals in
+    // their usual order. A lone positional is the report, unless --report is given.
+    pub fn resolve_args_paths(args: &mut Args) -> Result<()> {
+        let mut positionals: Vec<PathBuf> = [args.certs_dir.take(), args.positional_report.take()]
+            .into_iter()
+            .flatten()
+            .collect();
+        let report = match args.report.take() {
+            Some(report) => report,
+            None => positionals
+                .pop()
+                .ok_or_else(|| anyhow::anyhow!("No attestation report provided."))?,
+        };
+        let certs_dir = args.certs_dir_flag.take().or_else(|| positionals.pop());
+        if !positionals.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The certs-dir and report were given both as positionals and with --certs-dir or --report."
+            ));
+        }
+        if certs_dir.is_some() && (args.chain_file.is_some() || args.vek.is_some()) {
+            return Err(anyhow::anyhow!(
+                "A certs-dir can't be given along with --chain-file or --vek."
+            ));
+        }
+
+        args.certs_dir = certs_dir;
+        args.att_report_path = report;
+        Ok(())
+    }
+
+    // Fall back to SNPGUEST_CERTS_DIR for the certs-dir, unless the VEK is given another way.
+    pub fn resolve_args_certs_dir(args: &mut Args) -> Result<()> {
+        if args.chain_file.is_some() || args.vek.is_some() {
+            return Ok(());
+        }
+
+        args.certs_dir = if args.extended || args.fetch {
+            certs::resolve_certs_dir(args.certs_dir.take())
+        } else {
+            Some(certs::require_certs_dir(args.certs_dir.take())?)
+        };
+        if args.strict_dir && args.certs_dir.is_none() {
+            return Err(anyhow::anyhow!(
+                "--strict-dir requires a certificate directory."
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn verify_attestation(mut args: Args, quiet: bool) -> Result<()> {
+        resolve_args_paths(&mut args)?;
+        check_single_stdin(&[
+            Some(args.att_report_path.as_path()),
+            args.chain_file.as_deref(),
+            args.vek.as_deref(),
+            args.ca_chain.as_deref(),
+        ])?;
+        resolve_args_certs_dir(&mut args)?;
+
+        verify_resolved(&args, quiet)
+    }
+
+    // Verify the report of arguments whose paths are already resolved, as resolving them
+    // again would take the certs-dir for the report.
+    pub fn verify_resolved(args: &Args, quiet: bool) -> Result<()> {
+        if let (true, Some(certs_dir)) = (args.strict_dir, &args.certs_dir) {
+            check_strict_dir(certs_dir)?;
+        }
+
+        // Get attestation report
+        let (att_report, embedded) = read_args_report(args)?;
+
+        let result = verify_read_report(args, att_report, &embedded, quiet);
+        if let (Err(_), true) = (&result, args.print_urls) {
+            print_report_urls(args, &att_report);
+        }
+
+        result
+    }
+
+    // Print where the certificates of a report can be fetched from, to follow up on a failed
+    // verification. Printed even with --quiet, as --print-urls asks for it.
+    pub fn print_report_urls(args: &Args, att_report: &AttestationReport) {
+        let urls = match &args.processor_model {
+            Some(proc_model) => Ok(proc_model.clone()),
+            None => report::get_processor_model(att_report)
+                .context("Provide the processor model to print the KDS URLs of the report"),
+        }
+        .and_then(|proc_model| fetch::report_urls(&args.kds, &proc_model, att_report));
+
+        match urls {
+            Ok(urls) => {
+                eprintln!("The certificates of the report can be fetched from the KDS:");
+                for (name, url) in urls {
+                    eprintln!("  {name}: {url}");
+                }
+            }
+            Err(err) => eprintln!("WARNING: Could not build the KDS URLs: {err:#}"),
+        }
+    }
+
+    // Verify a report read from the arguments against the VEK they point at.
+    fn verify_read_report(
+        args: &Args,
+        att_report: AttestationReport,
+        embedded: &[CertTableEntry],
+        quiet: bool,
+    ) -> Result<()> {
+        // Get the VEK the report was signed with and grab its public key
+        let signing_key = report::get_signing_key(&att_report)?;
+        if args.fetch {
+            return verify_fetched(args, att_report, signing_key.as_ref(), quiet);
+        }
+        if let Some(ca_chain) = &args.ca_chain {
+            return verify_ca_chain(args, att_report, ca_chain, quiet);
+        }
+        if let Some(vek_path) = &args.vek {
+            let vek = convert_path_to_cert(vek_path, "vek")?;
+            return verify_report(args, att_report, vek, quiet);
+        }
+        let vek = match (&args.chain_file, &args.certs_dir) {
+            (Some(chain_file), _) => chain_from_file(chain_file)?.0.vek,
+            (None, Some(certs_dir)) => {
+                find_vek_in_dir(certs_dir, signing_key.as_ref(), &att_report)?
+            }
+            (None, None) if args.extended => {
+                return verify_embedded(args, att_report, embedded, signing_key.as_ref(), quiet)
+            }
+            (None, None) => return Err(anyhow::anyhow!("No VEK provided.")),
+        };
+
+        verify_report(args, att_report, vek, quiet)
+    }
+
+    // Verify an extended report against its embedded chain, once it is verified, or else as
+    // --fetch does, against a VCEK verified with the CA chain the KDS provides. An embedded VEK
+    // is never trusted alone, as the host writes the certificate table.
+    fn verify_embedded(
+        args: &Args,
+        att_report: AttestationReport,
+        embedded: &[CertTableEntry],
+        signing_key: Option<&Endorsement>,
+        quiet: bool,
+    ) -> Result<()> {
+        let text_quiet = quiet || args.output != VerifyOutput::Text;
+        let Some((cert_chain, vek_type, sign_type)) =
+            all::pinned_embedded_chain(embedded, signing_key, None, text_quiet)?
+        else {
+            return verify_fetched(args, att_report, signing_key, quiet);
+        };
+
+        certificate_chain::check_chain(&cert_chain, vek_type, sign_type, None, text_quiet)
+            .context("Certificate chain verification failed")?;
+        verify_report(args, att_report, cert_chain.vek, quiet)
+            .context("Attestation report verification failed")
+    }
+
+    // Processor model to verify a report as, printing where it was detected from. A given
+    // model is used as is, with a warning when the report was produced by another generation.
+    fn verified_processor_model(
+        given: Option<&ProcType>,
+        att_report: &AttestationReport,
+        vek: Option<&Certificate>,
+        print: bool,
+        quiet: bool,
+    ) -> Result<ProcType> {
+        let detected = report::detect_processor_model(att_report);
+        match (given, detected) {
+            (Some(given), Ok((detected, reason))) => {
+                if given.kds_path() != detected.kds_path() && !quiet {
+                    eprintln!("WARNING: The processor model is set to {given}, but the report was detected as {detected} from its {reason}.");
+                }
+                Ok(given.clone())
+            }
+            (Some(given), Err(_)) => Ok(given.clone()),
+            (None, Ok((detected, reason))) => {
+                if print {
+                    println!("Processor model {detected} detected from the {reason}.");
+                }
+                Ok(detected)
+            }
+            (None, Err(err)) => match vek {
+                Some(vek) => {
+                    let (model, reason) = model_from_vek(vek).map_err(|cert_err| {
+                        anyhow::anyhow!("Could not detect the processor model from the report ({err}) nor from the VEK ({cert_err}).")
+                    })?;
+                    if print {
+                        println!("Processor model {model} taken from the {reason}, as the report does not identify it.");
+                    }
+                    Ok(model)
+                }
+                // Reports before version 3 don't identify their generation, and every
+                // generation before Turin shares the Milan TCB layout.
+                None => {
+                    if print {
+                        println!("Processor model not detected, verifying as Milan: {err}");
+                    }
+                    Ok(ProcType::Milan)
+                }
+            },
+        }
+    }
+
+    // Processor model named in the common name of a VEK, or of its issuer. The KDS names the
+    // generation in the issuer (SEV-Milan) of a VCEK and the subject (SEV-VLEK-Milan) of an ASVK.
+    fn model_from_vek(vek: &Certificate) -> Result<(ProcType, String)> {
+        let der = vek
+            .to_der()
+            .context("Could not convert certificate to der.")?;
+        let (_, x509) =
+            X509Certificate::from_der(&der).context("Could not create X509Certificate from der")?;
+
+        for (name, role) in [(x509.subject(), "subject"), (x509.issuer(), "issuer")] {
+            for cn in name.iter_common_name().filter_map(|cn| cn.as_str().ok()) {
+                if let Some(model) = cn.split('-').find_map(|part| part.parse::<ProcType>().ok()) {
+                    return Ok((model, format!("common name {cn} of the VEK {role}")));
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "no processor model in the common names of the VEK subject or issuer"
+        ))
+    }
+
+    // Warn that half of the verification was skipped. Printed even with --quiet, so a report
+    // verified with --tcb or --signature alone is never taken as fully verified.
+    pub fn warn_skipped(tcb: bool, signature: bool) {
+        if tcb {
+            eprintln!(
+                "WARNING: signature verification SKIPPED, only the TCB was verified (--tcb)."
+            );
+        }
+        if signature {
+            eprintln!(
+                "WARNING: TCB verification SKIPPED, the reported TCB was not compared with the VEK (--signature)."
+            );
+        }
+    }
+
+    // Run the verifications selected in the arguments on a report against its VEK.
+    pub fn verify_report(
+        args: &Args,
+        att_report: AttestationReport,
+        vek: Certificate,
+        quiet: bool,
+    ) -> Result<()> {
+        warn_skipped(args.tcb, args.signature);
+        let text = args.output == VerifyOutput::Text && !args.print_claims;
+        let proc_model = verified_processor_model(
+            args.processor_model.as_ref(),
+            &att_report,
+            args.assume_model_from_cert.then_some(&vek),
+            text && !quiet,
+            quiet,
+        )?;
+
+        let mut checks = Checks::new(args.report_all);
+        if args.output == VerifyOutput::Text {
+            let result = check_report(
+                args,
+                att_report,
+                vek.clone(),
+                &proc_model,
+                &mut checks,
+                quiet || !text,
+            );
+            let written = match &args.report_out {
+                Some(report_out) => {
+                    let claims = claims_of(args, &att_report, &vek, &proc_model, &result);
+                    write_record(report_out, args, &att_report, &vek, &checks, &claims)
+                }
+                None => Ok(()),
+            };
+            with_record(result, written)?;
+            if args.print_claims && !quiet {
+                print_verified_claims(args, &att_report, &vek, &proc_model)?;
+            }
+            return Ok(());
+        }
+
+        // Print the claims instead, whatever the outcome
+        let result = check_report(
+            args,
+            att_report,
+            vek.clone(),
+            &proc_model,
+            &mut checks,
+            true,
+        );
+        let claims = claims_of(args, &att_report, &vek, &proc_model, &result);
+        if !quiet {
+            match args.output {
+                VerifyOutput::Json => {
+                    println!("{}", json::to_json(&claims.to_json())?)
+                }
+                VerifyOutput::Yaml => print!("{}", serde_yaml::to_string(&claims.to_json())?),
+                VerifyOutput::Eat => {
+                    std::io::Write::write_all(&mut std::io::stdout(), &claims.to_eat()?)
+                        .context("Could not write claims to stdout")?
+                }
+                VerifyOutput::Text => (),
+            }
+        }
+        let written = match &args.report_out {
+            Some(report_out) => write_record(report_out, args, &att_report, &vek, &checks, &claims),
+            None => Ok(()),
+        };
+
+        with_record(result, written).map(|_| ())
+    }
+
+    // The outcome of a verification along with the writing of its record. A failed
+    // verification is what is returned even if its record couldn't be written, which is
+    // warned about instead.
+    fn with_record<T>(result: Result<T>, written: Result<()>) -> Result<T> {
+        match (result, written) {
+            (Err(err), Err(write_err)) => {
+                eprintln!("WARNING: {write_err:#}");
+                Err(err)
+            }
+            (Ok(_), Err(write_err)) => Err(write_err),
+            (result, Ok(())) => result,
+        }
+    }
+
+    // Print the claims a policy engine can rely on, from a report that passed verification.
+    fn print_verified_claims(
+        args: &Args,
+        att_report: &AttestationReport,
+        vek: &Certificate,
+        proc_model: &ProcType,
+    ) -> Result<()> {
+        let vek_type = vek_type_name(vek);
+        let reported_tcb = proc_model.decode_tcb(&att_report.reported_tcb).ok();
+        let claims = claims::verified_claims(
+            att_report,
+            reported_tcb.as_ref(),
+            vek_type,
+            !args.tcb,
+            !args.signature,
+        );
+        println!("{}", json::to_json(&claims)?);
+
+        Ok(())
+    }
+
+    // Run the verifications selected in the arguments silently, along with the claims about
+    // the report and their outcome.
+    pub fn report_claims(
+        args: &Args,
+        att_report: AttestationReport,
+        vek: Certificate,
+        proc_model: &ProcType,
+    ) -> (claims::Claims, Result<()>) {
+        let mut checks = Checks::new(args.report_all);
+        let result = check_report(args, att_report, vek.clone(), proc_model, &mut checks, true);
+
+        (
+            claims_of(args, &att_report, &vek, proc_model, &result),
+            result.map(|_| ()),
+        )
+    }
+
+    // Name of the type of a VEK, for the claims.
+    fn vek_type_name(vek: &Certificate) -> Option<&'static str> {
+        match cert_type(vek) {
+            Ok(CertType::VCEK) => Some("VCEK"),
+            Ok(CertType::VLEK) => Some("VLEK"),
+            _ => None,
+        }
+    }
+
+    // Claims about a report and the outcome of its verification.
+    fn claims_of(
+        args: &Args,
+        att_report: &AttestationReport,
+        vek: &Certificate,
+        proc_model: &ProcType,
+        result: &Result<Option<VekInfo>>,
+    ) -> claims::Claims {
+        let vek_type = vek_type_name(vek);
+        let reported_tcb = proc_model.decode_tcb(&att_report.reported_tcb).ok();
+        let current_tcb = proc_model.decode_tcb(&att_report.current_tcb).ok();
+        let mut claims = claims::Claims::new(
+            att_report,
+            reported_tcb.as_ref(),
+            current_tcb.as_ref(),
+            vek_type,
+            result.as_ref().map(|_| ()),
+        );
+        if let (true, Ok(Some(vek_info))) = (args.print_vek_info, result) {
+            claims.0.push(vek_info.claim());
+        }
+
+        claims
+    }
+
+    // Write the record of a verification for audit trails: when it ran, the report and VEK it
+    // covered, and the result of every check along with the claims. The record is YAML for
+    // a .yaml or .yml path, and JSON otherwise.
+    fn write_record(
+        path: &Path,
+        args: &Args,
+        att_report: &AttestationReport,
+        vek: &Certificate,
+        checks: &Checks,
+        claims: &claims::Claims,
+    ) -> Result<()> {
+        let report_bytes =
+            bincode::serialize(att_report).context("Could not serialize attestation report.")?;
+        let vek_der = vek.to_der().context("Could not convert VEK to der.")?;
+        let (_, vek_x509) = X509Certificate::from_der(&vek_der)
+            .context("Could not create X509Certificate from der")?;
+
+        let record = serde_json::json!({
+            "timestamp": utc_timestamp(std::time::SystemTime::now()),
+            "report": {
+                "path": args.att_report_path.display().to_string(),
+                "sha384": hex::encode(openssl::sha::sha384(&report_bytes)),
+            },
+            "vek_subject": vek_x509.subject().to_string(),
+            "checks": checks.to_json(),
+            "claims": claims.to_json(),
+        });
+
+        let yaml = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "yaml" | "yml"));
+        let contents = if yaml {
+            serde_yaml::to_string(&record)?
+        } else {
+            serde_json::to_string_pretty(&record)? + "\n"
+        };
+        fs::write(path, contents)
+            .with_context(|| format!("Could not write verification record to {}", path.display()))
+    }
+
+    // Run the verifications selected in the arguments, printing the result of each in console.
+    fn check_report(
+        args: &Args,
+        att_report: AttestationReport,
+        vek: Certificate,
+        proc_model: &ProcType,
+        checks: &mut Checks,
+        quiet: bool,
+    ) -> Result<Option<VekInfo>> {
+        // Run both verifications unless one of them was requested exclusively
+        let (run_tcb, run_signature) = if args.tcb || args.signature {
+            (args.tcb, args.signature)
+        } else {
+            (true, true)
+        };
+
+        // The VEK must be the key the report says it was signed with
+        let vek_type = match cert_type(&vek)? {
+            CertType::VCEK => Endorsement::Vcek,
+            CertType::VLEK => Endorsement::Vlek,
+            other => {
+                return Err(anyhow::anyhow!(
+                    "The VEK is a {other:?} certificate, not a VCEK or VLEK."
+                ))
+            }
+        };
+        match report::get_signing_key(&att_report)? {
+            Some(signing_key) if signing_key != vek_type => {
+                return Err(anyhow::anyhow!(
+                    "The report was {signing_key}-signed but the VEK is a {vek_type}."
+                ));
+            }
+            None if run_signature => {
+                return Err(anyhow::anyhow!("The report is not signed."));
+            }
+            _ => (),
+        }
+
+        if let Some(required) = &args.require_signed_by {
+            checks.run("signer", verify_signer(&vek_type, required, quiet))?;
+        }
+        let extra_oids = args
+            .extra_oids
+            .as_deref()
+            .map(ExtraOids::read)
+            .transpose()?;
+        // Refuse reports below the security baseline regardless of the VEK. The reported TCB is
+        // part of the report, so this holds with --signature too.
+        if let Some(min_tcb) = &args.min_tcb {
+            let min_tcb = proc_model
+                .decode_tcb(&att_report.reported_tcb)
+                .and_then(|reported_tcb| min_tcb.check(&reported_tcb));
+            if checks.run("min_tcb", min_tcb)?.is_some() && !quiet {
+                check_passed("Reported TCB meets the minimum TCB.");
+            }
+        }
+
+        let tcb = if run_tcb {
+            let tcb = verify_attestation_tcb(&vek, &att_report, proc_model).and_then(|tcb| {
+                check_fmc_layout(proc_model, &tcb.reported_tcb, tcb.vek_fmc)?;
+                Ok(tcb)
+            });
+            checks.run("tcb_layout", tcb)?
+        } else {
+            None
+        };
+        if let Some(tcb) = &tcb {
+            checks.run(
+                "vek_extensions",
+                check_missing_oids(&tcb.missing_oids, args.strict_tcb, quiet),
+            )?;
+
+            let allowed_mismatches = args.allow_tcb_mismatch.clone().unwrap_or_default();
+            for field in tcb
+                .fields
+                .iter()
+                .filter(|field| field.component != SnpOid::HwId)
+            {
+                let name = field.component.name();
+                if !field.matched {
+                    checks.run(
+                        &format!("tcb_{}", field.component.key()),
+                        tcb_mismatch(name, allowed_mismatches.allows(field.component), quiet),
+                    )?;
+                } else if !quiet {
+                    check_passed(&format!(
+                        "Reported TCB {name} from certificate matches the attestation report."
+                    ));
+                }
+            }
+            if args.check_tcb_ordering {
+                checks.run("tcb_ordering", check_tcb_ordering(&tcb.fields, quiet))?;
+            }
+
+            if let Some(extra_oids) = &extra_oids {
+                let vek_der = vek.to_der().context("Could not convert VEK to der.")?;
+                let (_, vek_x509) = X509Certificate::from_der(&vek_der)
+                    .context("Could not create X509Certificate from der")?;
+                let extensions = vek_x509
+                    .extensions_map()
+                    .context("Failed getting VEK oids.")?;
+                check_extra_oids(
+                    extra_oids,
+                    &extensions,
+                    &att_report,
+                    args.strict_tcb,
+                    checks,
+                    quiet,
+                )?;
+            }
+
+            let hwid = tcb
+                .fields
+                .iter()
+                .find(|field| field.component == SnpOid::HwId);
+            if tcb.vek_type == CertType::VCEK && args.skip_hwid {
+                if !quiet {
+                    eprintln!("WARNING: --skip-hwid is set, the VCEK hardware ID is not compared with the chip ID of the report.");
+                }
+            } else if let Some(hwid) = hwid {
+                checks.run(
+                    "legacy_hwid",
+                    check_legacy_hwid(tcb.legacy_hwid, args.no_legacy_certs, quiet),
+                )?;
+                let hwid_match = match (&tcb.hwid_error, hwid.matched) {
+                    (Some(err), _) => Err(anyhow::anyhow!("{err}")),
+                    (None, false) => Err(anyhow::anyhow!(
+                        "Report TCB ID and Certificate ID mismatch encountered."
+                    )),
+                    (None, true) => Ok(()),
+                };
+                if checks.run("chip_id", hwid_match)?.is_some() && !quiet {
+                    check_passed("Chip ID from certificate matches the attestation report.");
+                }
+            }
+        }
+        let vek_info = tcb.map(|tcb| tcb.vek_info);
+        if run_signature {
+            checks.run(
+                "signature",
+                verify_attestation_signature(vek, att_report, quiet),
+            )?;
+        }
+        if run_tcb || args.no_downgrade || args.require_current_equals_reported {
+            checks.run(
+                "tcb_downgrade",
+                verify_tcb_downgrade(
+                    &att_report,
+                    proc_model,
+                    args.no_downgrade,
+                    args.require_current_equals_reported,
+                    quiet,
+                ),
+            )?;
+        }
+        if let Some(policy_require) = &args.policy_require {
+            let policy = policy_require.check(&decode_policy(att_report.policy.0));
+            if checks.run("policy", policy)?.is_some() && !quiet {
+                check_passed("Guest policy meets the policy requirements.");
+            }
+        }
+        if args.require_no_debug {
+            checks.run("no_debug", verify_no_debug(&att_report, quiet))?;
+        }
+        if let Some(min_abi) = args.min_abi {
+            checks.run("min_abi", verify_min_abi(&att_report, min_abi, quiet))?;
+        }
+        if let Some(report_data) = &args.report_data {
+            checks.run(
+                "report_data",
+                verify_report_data(&att_report, report_data, quiet),
+            )?;
+        }
+        if let Some(require_vmpl) = args.require_vmpl {
+            checks.run("vmpl", verify_vmpl(&att_report, require_vmpl, quiet))?;
+        }
+        if let Some(expected) = &args.expected_family_id {
+            let family_id = verify_launch_id("family ID", &att_report.family_id, expected, quiet);
+            checks.run("family_id", family_id)?;
+        }
+        if let Some(expected) = &args.expected_image_id {
+            let image_id = verify_launch_id("image ID", &att_report.image_id, expected, quiet);
+            checks.run("image_id", image_id)?;
+        }
+        if args.strict_reserved {
+            checks.run("reserved", verify_reserved(&att_report, quiet))?;
+        }
+        // The ID key digest is zero without an ID block, and the author key digest only
+        // counts when AUTHOR_KEY_EN is set
+        if let Some(id_key_digest) = &args.id_key_digest {
+            checks.run(
+                "id_key_digest",
+                verify_key_digest(
+                    "ID key",
+                    &att_report.id_key_digest,
+                    att_report.id_key_digest != [0; 48],
+                    id_key_digest,
+                    quiet,
+                ),
+            )?;
+        }
+        if let Some(author_key_digest) = &args.author_key_digest {
+            checks.run(
+                "author_key_digest",
+                verify_key_digest(
+                    "author key",
+                    &att_report.author_key_digest,
+                    report::author_key_enabled(&att_report)?,
+                    author_key_digest,
+                    quiet,
+                ),
+            )?;
+        }
+        checks.finish()?;
+
+        if !quiet {
+            println!("Verified using the {vek_type}.");
+            if let (true, Some(vek_info)) = (args.print_vek_info, &vek_info) {
+                vek_info.print();
+            }
+        }
+
+        Ok(vek_info)
+    }
+
+    // Known answer test of the TCB checks against the dummy VCEK, whose hardware ID is known.
+    pub fn self_test_vcek() -> Result<()> {
+        let (cert_bytes, hw_id) = cert_and_hw_id();
+        let (_, vcek) = X509Certificate::from_der(&cert_bytes)
+            .context("Could not create X509Certificate from der")?;
+        let extensions = vcek.extensions_map().context("Failed getting VEK oids.")?;
+        if parse_common_name(vcek.subject())? != CertType::VCEK {
+            return Err(anyhow::anyhow!(
+                "The dummy VCEK was not recognized as a VCEK."
+            ));
+        }
+
+        let cert_hwid = extensions
+            .get(&SnpOid::HwId.oid())
+            .context("The dummy VCEK has no hardware ID.")?;
+        if !check_cert_bytes(cert_hwid, &hw_id, hw_id.len())? || is_legacy_hwid(cert_hwid) {
+            return Err(anyhow::anyhow!(
+                "The hardware ID of the dummy VCEK does not match."
+            ));
+        }
+        let mut other_hw_id = hw_id;
+        other_hw_id[0] ^= 1;
+        if check_cert_bytes(cert_hwid, &other_hw_id, hw_id.len())? {
+            return Err(anyhow::anyhow!(
+                "A different hardware ID matched the dummy VCEK."
+            ));
+        }
+
+        let cert_ucode = extensions
+            .get(&SnpOid::Ucode.oid())
+            .context("The dummy VCEK has no microcode TCB.")?;
+        if !check_cert_bytes(cert_ucode, &[0x1E], 1)? {
+            return Err(anyhow::anyhow!(
+                "The microcode TCB of the dummy VCEK does not match."
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Dummy VCEK along with its hardware ID, for the tests and the selftest command.
+    // Important note that this is NOT a valid certificate, and the signature will NOT
+    // match at all.
+    pub fn cert_and_hw_id() -> ([u8; 1362], [u8; 64]) {
+        (
+            [
+                0x30, 0x82, 0x05, 0x4e, 0x30, 0x82, 0x02, 0xfd, 0xa0, 0x03, 0x02, 0x01, 0x02, 0x02,
+                0x01, 0x00, 0x30, 0x46, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01,
+                0x0a, 0x30, 0x39, 0xa0, 0x0f, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65,
+                0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0xa1, 0x1c, 0x30, 0x1a, 0x06, 0x09, 0x2a, 0x86,
+                0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x08, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48,
+                0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0xa2, 0x03, 0x02, 0x01, 0x30, 0xa3,
+                0x03, 0x02, 0x01, 0x01, 0x30, 0x7b, 0x31, 0x14, 0x30, 0x12, 0x06, 0x03, 0x55, 0x04,
+                0x0b, 0x0c, 0x0b, 0x45, 0x6e, 0x67, 0x69, 0x6e, 0x65, 0x65, 0x72, 0x69, 0x6e, 0x67,
+                0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x06, 0x13, 0x02, 0x55, 0x53, 0x31,
+                0x14, 0x30, 0x12, 0x06, 0x03, 0x55, 0x04, 0x07, 0x0c, 0x0b, 0x53, 0x61, 0x6e, 0x74,
+                0x61, 0x20, 0x43, 0x6c, 0x61, 0x72, 0x61, 0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55,
+                0x04, 0x08, 0x0c, 0x02, 0x43, 0x41, 0x31, 0x1f, 0x30, 0x1d, 0x06, 0x03, 0x55, 0x04,
+                0x0a, 0x0c, 0x16, 0x41, 0x64, 0x76, 0x61, 0x6e, 0x63, 0x65, 0x64, 0x20, 0x4d, 0x69,
+                0x63, 0x72, 0x6f, 0x20, 0x44, 0x65, 0x76, 0x69, 0x63, 0x65, 0x73, 0x31, 0x12, 0x30,
+                0x10, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x09, 0x53, 0x45, 0x56, 0x2d, 0x4d, 0x69,
+                0x6c, 0x61, 0x6e, 0x30, 0x1e, 0x17, 0x0d, 0x32, 0x33, 0x30, 0x38, 0x31, 0x37, 0x31,
+                0x34, 0x32, 0x37, 0x30, 0x39, 0x5a, 0x17, 0x0d, 0x33, 0x30, 0x30, 0x38, 0x31, 0x37,
+                0x31, 0x34, 0x32, 0x37, 0x30, 0x39, 0x5a, 0x30, 0x7a, 0x31, 0x14, 0x30, 0x12, 0x06,
+                0x03, 0x55, 0x04, 0x0b, 0x0c, 0x0b, 0x45, 0x6e, 0x67, 0x69, 0x6e, 0x65, 0x65, 0x72,
+                0x69, 0x6e, 0x67, 0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x06, 0x13, 0x02,
+                0x55, 0x53, 0x31, 0x14, 0x30, 0x12, 0x06, 0x03, 0x55, 0x04, 0x07, 0x0c, 0x0b, 0x53,
+                0x61, 0x6e, 0x74, 0x61, 0x20, 0x43, 0x6c, 0x61, 0x72, 0x61, 0x31, 0x0b, 0x30, 0x09,
+                0x06, 0x03, 0x55, 0x04, 0x08, 0x0c, 0x02, 0x43, 0x41, 0x31, 0x1f, 0x30, 0x1d, 0x06,
+                0x03, 0x55, 0x04, 0x0a, 0x0c, 0x16, 0x41, 0x64, 0x76, 0x61, 0x6e, 0x63, 0x65, 0x64,
+                0x20, 0x4d, 0x69, 0x63, 0x72, 0x6f, 0x20, 0x44, 0x65, 0x76, 0x69, 0x63, 0x65, 0x73,
+                0x31, 0x11, 0x30, 0x0f, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x08, 0x53, 0x45, 0x56,
+                0x2d, 0x56, 0x43, 0x45, 0x4b, 0x30, 0x76, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48,
+                0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22, 0x03, 0x62, 0x00,
+                0x04, 0x07, 0x79, 0x5c, 0xaa, 0x60, 0x2f, 0x16, 0x5e, 0x8d, 0x37, 0x46, 0x93, 0x87,
+                0xc5, 0x06, 0x4a, 0x52, 0x46, 0xc9, 0x72, 0x0b, 0xdb, 0x7a, 0xd2, 0x15, 0xb2, 0xc6,
+                0x61, 0x3c, 0x6f, 0x9b, 0x1e, 0xd4, 0x61, 0x48, 0xee, 0xbd, 0xdd, 0xef, 0x56, 0xc3,
+                0xb6, 0x40, 0xdf, 0xd0, 0x5e, 0xbb, 0x3c, 0x0c, 0x77, 0x2e, 0xea, 0x5a, 0xb0, 0xa9,
+                0x4b, 0x2e, 0x9a, 0x85, 0x92, 0x08, 0x55, 0x7c, 0x23, 0xc3, 0x2a, 0xe1, 0xac, 0xb0,
+                0x2f, 0x3d, 0x59, 0x15, 0xe9, 0xbd, 0x2e, 0x64, 0xb4, 0x37, 0x73, 0xb8, 0x04, 0xd5,
+                0xd5, 0x1b, 0x11, 0x5e, 0x60, 0x1a, 0xc1, 0xf3, 0x86, 0x9d, 0x3e, 0x32, 0xe2, 0xa3,
+                0x82, 0x01, 0x18, 0x30, 0x82, 0x01, 0x14, 0x30, 0x10, 0x06, 0x09, 0x2b, 0x06, 0x01,
+                0x04, 0x01, 0x9c, 0x78, 0x01, 0x01, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x17, 0x06,
+                0x09, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x02, 0x04, 0x0a, 0x16, 0x08,
+                0x4d, 0x69, 0x6c, 0x61, 0x6e, 0x2d, 0x42, 0x30, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06,
+                0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x01, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30,
+                0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x02, 0x04,
+                0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c,
+                0x78, 0x01, 0x03, 0x04, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b,
+                0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x05, 0x04, 0x03, 0x02, 0x01, 0x00,
+                0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x06,
+                0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01,
+                0x9c, 0x78, 0x01, 0x03, 0x07, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a,
+                0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x03, 0x04, 0x03, 0x02, 0x01,
+                0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03,
+                0x08, 0x04, 0x03, 0x02, 0x01, 0x1e, 0x30, 0x4f, 0x06, 0x09, 0x2b, 0x06, 0x01, 0x04,
+                0x01, 0x9c, 0x78, 0x01, 0x04, 0x04, 0x42, 0x04, 0x40, 0x00, 0x01, 0x02, 0x03, 0x04,
+                0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12,
+                0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20,
+                0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e,
+                0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c,
+                0x3d, 0x3e, 0x3f, 0x30, 0x46, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01,
+                0x01, 0x0a, 0x30, 0x39, 0xa0, 0x0f, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01,
+                0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0xa1, 0x1c, 0x30, 0x1a, 0x06, 0x09, 0x2a,
+                0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x08, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86,
+                0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0xa2, 0x03, 0x02, 0x01, 0x30,
+                0xa3, 0x03, 0x02, 0x01, 0x01, 0x03, 0x82, 0x02, 0x01, 0x00, 0x12, 0x41, 0x24, 0x4a,
+                0xf3, 0xf8, 0xfb, 0x0f, 0x70, 0x33, 0x9a, 0x0e, 0x36, 0x9e, 0xf5, 0x89, 0xad, 0x85,
+                0x6b, 0xed, 0xd1, 0x25, 0x2d, 0x23, 0x89, 0x16, 0x80, 0xcb, 0xee, 0xbd, 0x70, 0x97,
+                0x92, 0x24, 0x76, 0x0b, 0xf9, 0x15, 0x9e, 0x8e, 0x4c, 0xb4, 0x9d, 0x61, 0x9d, 0x3d,
+                0xfe, 0x3a, 0xf3, 0x36, 0xb4, 0xc8, 0xb7, 0x56, 0xad, 0x1a, 0x1f, 0x35, 0xf5, 0x36,
+                0xf9, 0xb5, 0xed, 0x8f, 0x95, 0x0d, 0x37, 0x0f, 0xa8, 0x89, 0xba, 0x1c, 0x96, 0x91,
+                0x97, 0x62, 0x4f, 0xc7, 0x93, 0x87, 0x6d, 0x23, 0xdc, 0xc0, 0xbb, 0xcd, 0x17, 0x38,
+                0xae, 0xbd, 0x0d, 0xc4, 0xcc, 0xa4, 0x3f, 0xc8, 0x7d, 0x0d, 0x0b, 0x5c, 0xf1, 0xba,
+                0x9b, 0x20, 0x29, 0x95, 0xb0, 0x96, 0x02, 0x4d, 0x9d, 0xcd, 0x82, 0x0a, 0x60, 0x92,
+                0x51, 0xa1, 0x3c, 0x69, 0xec, 0x27, 0x81, 0x8e, 0x28, 0xc7, 0x4e, 0x34, 0xbb, 0x9f,
+                0xb0, 0x49, 0xc7, 0x6e, 0xe6, 0xb7, 0x6b, 0x1f, 0x91, 0x20, 0x0a, 0x80, 0xd2, 0x9f,
+                0x67, 0x24, 0xe0, 0x75, 0x40, 0x9b, 0x4a, 0xdd, 0xeb, 0xab, 0x34, 0x5f, 0x59, 0x3d,
+                0x3b, 0x06, 0xf0, 0x4d, 0x7d, 0xf9, 0x26, 0xeb, 0x35, 0xcb, 0x08, 0x35, 0x7b, 0xbf,
+                0x02, 0x4e, 0xa5, 0x50, 0xf8, 0x91, 0xf3, 0x60, 0xed, 0x80, 0x0d, 0xe1, 0x7e, 0x2b,
+                0x86, 0x75, 0x3d, 0x0c, 0x83, 0xea, 0x64, 0x50, 0x6c, 0xbd, 0xe2, 0x17, 0x6e, 0x45,
+                0xaa, 0x10, 0xe8, 0x84, 0xcc, 0xa1, 0x06, 0xb6, 0x8b, 0xa5, 0x96, 0xb0, 0x83, 0xba,
+                0x61, 0xe6, 0xa4, 0x14, 0xd3, 0x26, 0xf3, 0x19, 0x31, 0xbe, 0x40, 0x2a, 0x18, 0x53,
+                0x58, 0x75, 0x1d, 0x46, 0xe2, 0xfe, 0x47, 0xa3, 0xa9, 0x39, 0x68, 0xee, 0x37, 0x8f,
+                0x57, 0xe6, 0x12, 0x92, 0x34, 0xa6, 0x0a, 0x51, 0xcb, 0x4c, 0xce, 0x54, 0xe2, 0xbe,
+                0x8b, 0x8c, 0x02, 0xe5, 0x3c, 0x3a, 0x7b, 0x7f, 0x7b, 0x3b, 0x80, 0x44, 0x98, 0x9c,
+                0x52, 0x1d, 0x29, 0x42, 0xce, 0x9f, 0x95, 0xc5, 0x79, 0xbe, 0xd8, 0x06, 0x71, 0xff,
+                0xa2, 0x0a, 0xe2, 0x21, 0xa9, 0x59, 0xda, 0xac, 0x05, 0xe8, 0x2e, 0xa5, 0x1f, 0x01,
+                0xaf, 0xae, 0xc6, 0x90, 0xbb, 0x5d, 0x7b, 0xa9, 0x84, 0xff, 0x1c, 0x11, 0x78, 0x07,
+                0x89, 0x0a, 0x09, 0x4f, 0xc8, 0x4c, 0xb1, 0x7e, 0x68, 0x12, 0xa6, 0x3d, 0xae, 0x6b,
+                0x69, 0x8d, 0xc9, 0x03, 0x5f, 0x4d, 0x45, 0x47, 0xde, 0xf0, 0xa5, 0x1a, 0x19, 0x97,
+                0x37, 0x0e, 0xe8, 0x8a, 0xd2, 0x30, 0x07, 0xbf, 0xb4, 0x09, 0x80, 0x93, 0xa4, 0x91,
+                0x28, 0x40, 0xe3, 0x2c, 0xf3, 0x46, 0xf0, 0x22, 0xb3, 0xb7, 0xc5, 0x92, 0x69, 0x7a,
+                0x4d, 0xdb, 0xf7, 0x67, 0x97, 0x6f, 0x83, 0xcf, 0x5d, 0x29, 0x8b, 0x55, 0x72, 0xd3,
+                0xa2, 0xcb, 0x65, 0x21, 0x76, 0x84, 0xed, 0x75, 0xd5, 0xf3, 0x74, 0xff, 0xc1, 0x1a,
+                0x8d, 0x65, 0xac, 0x4f, 0xb0, 0x8c, 0x87, 0xae, 0x6a, 0xf0, 0xf9, 0x56, 0x23, 0xfc,
+                0x29, 0x5a, 0x1c, 0xd4, 0x12, 0xf9, 0x79, 0x66, 0x97, 0xad, 0x95, 0xc1, 0xa9, 0x0e,
+                0xf3, 0x2b, 0x94, 0x17, 0xc3, 0xfd, 0x51, 0x1f, 0x94, 0x35, 0xad, 0xa7, 0xf9, 0x61,
+                0x57, 0xf3, 0x67, 0x53, 0x17, 0xc7, 0xee, 0x1f, 0x54, 0x11, 0x1a, 0xd4, 0xc9, 0x33,
+                0x4b, 0x3a, 0x71, 0x27, 0xd7, 0xbb, 0x9f, 0x96, 0xba, 0xfa, 0x8a, 0x9c, 0x1e, 0x80,
+                0x6e, 0xfa, 0xa5, 0xd6, 0xba, 0xd7, 0x92, 0x71, 0xe9, 0x4e, 0x82, 0xa9, 0x02, 0x2a,
+                0x3b, 0xb8, 0x4e, 0x01, 0x53, 0x34, 0xa6, 0x70, 0x61, 0x56, 0x95, 0x1b, 0x59, 0xfe,
+                0x46, 0x94, 0x84, 0x8c, 0xa2, 0x2a, 0x16, 0x0c, 0xc2, 0x59, 0x9e, 0xac, 0xca, 0xa9,
+                0x93, 0xe6, 0x84, 0xf4,
+            ],
+            [
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+                0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
+                0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29,
+                0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37,
+                0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
+            ],
+        )
+    }
+
+    // Report matching the dummy VCEK, so that it passes the TCB checks against it.
+    #[cfg(test)]
+    pub fn dummy_vcek_report() -> AttestationReport {
+        let (cert_bytes, hw_id) = cert_and_hw_id();
+        let (_, vek) = X509Certificate::from_der(&cert_bytes).unwrap();
+        let extensions = vek.extensions_map().unwrap();
+        let cert_value = |snp_oid: SnpOid| cert_int(extensions[&snp_oid.oid()]).unwrap() as u8;
+        let mut att_report = AttestationReport::default();
+        att_report.version = 2;
+        att_report.reported_tcb = sev::firmware::host::TcbVersion::new(
+            cert_value(SnpOid::BootLoader),
+            cert_value(SnpOid::Tee),
+            cert_value(SnpOid::Snp),
+            cert_value(SnpOid::Ucode),
+        );
+        att_report.chip_id = hw_id;
+        att_report
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use x509_parser::{self, certificate::X509Certificate};
+
         /// Important note that this is NOT a valid certificate,
         /// and the signature will NOT match at all.
         fn cert_and_hw_id_legacy() -> ([u8; 1361], [u8; 64]) {
@@ -523,169 +3663,2451 @@ mod attestation {
                     0x7f, 0x57, 0x6d, 0x3a, 0xc8, 0x14, 0x4e, 0x88, 0x8d,
                 ],
                 [
-                    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
-                    0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19,
-                    0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26,
-                    0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33,
-                    0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
-                ],
-            )
+                    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
+                    0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19,
+                    0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26,
+                    0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33,
+                    0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
+                ],
+            )
+        }
+
+        #[test]
+        fn test_check_cert_bytes_legacy() {
+            let (legacy_cert_bytes, val) = cert_and_hw_id_legacy();
+
+            let dummy_x509: X509Certificate =
+                X509Certificate::from_der(&legacy_cert_bytes).unwrap().1;
+            let extensions = dummy_x509.extensions_map().unwrap();
+
+            let ext = extensions.get(&SnpOid::HwId.oid()).unwrap();
+
+            assert!(check_cert_bytes(ext, &val, val.len()).unwrap());
+            assert!(is_legacy_hwid(ext));
+            assert!(check_legacy_hwid(true, false, true).is_ok());
+            assert!(check_legacy_hwid(false, true, true).is_ok());
+            let err = check_legacy_hwid(true, true, true).unwrap_err();
+            assert!(err.to_string().contains("--no-legacy-certs"));
+        }
+
+        #[test]
+        fn test_check_cert_bytes() {
+            let (cert_bytes, val) = cert_and_hw_id();
+
+            let dummy_x509: X509Certificate = X509Certificate::from_der(&cert_bytes).unwrap().1;
+            let extensions = dummy_x509.extensions_map().unwrap();
+
+            let ext = extensions.get(&SnpOid::HwId.oid()).unwrap();
+
+            assert!(check_cert_bytes(ext, val.as_slice(), val.len()).unwrap());
+            assert!(!is_legacy_hwid(ext));
+        }
+
+        #[test]
+        fn test_checks() {
+            let failure = |name: &str| Err::<(), _>(anyhow::anyhow!("{name} mismatch"));
+
+            let mut checks = Checks::new(false);
+            assert!(checks.run("check", failure("SNP")).is_err());
+
+            let mut checks = Checks::new(true);
+            assert_eq!(checks.run("check", Ok(1)).unwrap(), Some(1));
+            assert_eq!(checks.run("check", failure("SNP")).unwrap(), None);
+            assert_eq!(checks.run("check", failure("TEE")).unwrap(), None);
+            let err = checks.finish().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "2 checks failed:\n  - SNP mismatch\n  - TEE mismatch"
+            );
+
+            assert_eq!(
+                checks.to_json(),
+                serde_json::json!([
+                    { "check": "check", "passed": true },
+                    { "check": "check", "passed": false, "error": "SNP mismatch" },
+                    { "check": "check", "passed": false, "error": "TEE mismatch" },
+                ])
+            );
+
+            // A failure returned in fail-fast mode is only recorded where it happened
+            let mut checks = Checks::new(false);
+            let err = checks.run("tcb_snp", failure("SNP")).unwrap_err();
+            assert!(checks.run("tcb_layout", Err::<(), _>(err)).is_err());
+            assert_eq!(checks.to_json().as_array().unwrap().len(), 1);
+
+            let mut checks = Checks::new(true);
+            checks.run("check", failure("SNP")).unwrap();
+            assert_eq!(checks.finish().unwrap_err().to_string(), "SNP mismatch");
+            assert!(Checks::new(true).finish().is_ok());
+        }
+
+        #[test]
+        fn test_extra_oids() {
+            let path =
+                std::env::temp_dir().join(format!("snpguest-extra-oids-{}", std::process::id()));
+            fs::write(
+                &path,
+                r#"{"1.3.6.1.4.1.3704.1.3.8": "reported_tcb.microcode", "1.3.6.1.4.1.3704.1.4": "chip_id"}"#,
+            )
+            .unwrap();
+            let extra_oids = ExtraOids::read(&path).unwrap();
+            assert_eq!(
+                extra_oids.0[0],
+                (SnpOid::Ucode.oid(), "reported_tcb.microcode".into())
+            );
+
+            let (cert_bytes, hw_id) = cert_and_hw_id();
+            let (_, vek) = X509Certificate::from_der(&cert_bytes).unwrap();
+            let extensions = vek.extensions_map().unwrap();
+            let mut att_report = AttestationReport::default();
+            att_report.version = 2;
+            att_report.chip_id = hw_id;
+            att_report.reported_tcb.microcode = 0x1E;
+
+            let mut checks = Checks::new(true);
+            check_extra_oids(
+                &extra_oids,
+                &extensions,
+                &att_report,
+                true,
+                &mut checks,
+                true,
+            )
+            .unwrap();
+            assert!(checks.finish().is_ok());
+
+            att_report.chip_id[0] ^= 1;
+            check_extra_oids(
+                &extra_oids,
+                &extensions,
+                &att_report,
+                true,
+                &mut checks,
+                true,
+            )
+            .unwrap();
+            assert!(checks
+                .finish()
+                .unwrap_err()
+                .to_string()
+                .starts_with("Report chip_id and Certificate extension"));
+
+            fs::write(&path, r#"{"1.2.3": "no_such_field"}"#).unwrap();
+            let extra_oids = ExtraOids::read(&path).unwrap();
+            assert!(check_extra_oids(
+                &extra_oids,
+                &extensions,
+                &att_report,
+                true,
+                &mut checks,
+                true
+            )
+            .is_err());
+            fs::write(&path, r#"{"not an oid": "chip_id"}"#).unwrap();
+            assert!(ExtraOids::read(&path).is_err());
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn test_vek_info() {
+            let (cert_bytes, _) = cert_and_hw_id();
+
+            let dummy_x509: X509Certificate = X509Certificate::from_der(&cert_bytes).unwrap().1;
+            let extensions = dummy_x509.extensions_map().unwrap();
+
+            let vek_info = VekInfo::new(&dummy_x509, &extensions);
+            assert_eq!(vek_info.common_name, "SEV-VCEK");
+            assert_eq!(
+                vek_info
+                    .tcb
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>(),
+                ["Boot Loader", "TEE", "SNP", "Microcode"]
+            );
+
+            let claims = claims::Claims(vec![vek_info.claim()]).to_json();
+            assert_eq!(claims["vek_info"]["common_name"], "SEV-VCEK");
+            assert_eq!(claims["vek_info"]["serial"], vek_info.serial);
+        }
+
+        #[test]
+        fn test_turin_chip_id() {
+            use x509_parser::extensions::ParsedExtension;
+
+            // Turin reports an 8-byte chip ID, zero padded to the size of the field
+            let mut att_report = AttestationReport::default();
+            att_report.chip_id[..8].copy_from_slice(&[0xAB; 8]);
+
+            let chip_id = report_chip_id(&att_report, &ProcType::Turin).unwrap();
+            assert_eq!(chip_id, &[0xAB; 8]);
+            let err = report_chip_id(&att_report, &ProcType::Milan).unwrap_err();
+            assert!(err.to_string().contains("8 bytes long"));
+
+            let mut value = vec![0x4, 0x8];
+            value.extend_from_slice(&[0xAB; 8]);
+            let ext = X509Extension::new(
+                SnpOid::HwId.oid(),
+                false,
+                &value,
+                ParsedExtension::UnsupportedExtension {
+                    oid: SnpOid::HwId.oid(),
+                },
+            );
+            assert_eq!(cert_hwid(&ext), &[0xAB; 8]);
+            assert!(check_cert_bytes(&ext, chip_id, 8).unwrap());
+            assert!(
+                compare_hwid(&ext, chip_id, &ProcType::Turin)
+                    .unwrap()
+                    .matched
+            );
+
+            // A hardware ID of another length is a mismatch, whichever side is shorter
+            assert!(!check_cert_bytes(&ext, &[0xAB; 64], 64).unwrap());
+            assert!(
+                !compare_hwid(&ext, chip_id, &ProcType::Milan)
+                    .unwrap()
+                    .matched
+            );
+            let mut value = vec![0x4, 0x40];
+            value.extend_from_slice(&[0xAB; 64]);
+            let ext = X509Extension::new(
+                SnpOid::HwId.oid(),
+                false,
+                &value,
+                ParsedExtension::UnsupportedExtension {
+                    oid: SnpOid::HwId.oid(),
+                },
+            );
+            assert!(!check_cert_bytes(&ext, chip_id, 8).unwrap());
+            assert!(
+                !compare_hwid(&ext, chip_id, &ProcType::Turin)
+                    .unwrap()
+                    .matched
+            );
+
+            att_report.chip_id = [0xAB; 64];
+            assert!(report_chip_id(&att_report, &ProcType::Turin).is_err());
+            att_report.chip_id = [0; 64];
+            assert!(report_chip_id(&att_report, &ProcType::Genoa).is_err());
+        }
+
+        #[test]
+        fn test_verify_signature_openssl_reason() {
+            // Move the x coordinate of the VEK public key off the curve
+            let (mut cert_bytes, _) = cert_and_hw_id();
+            let point = cert_bytes
+                .windows(4)
+                .position(|window| window == [0x03, 0x62, 0x00, 0x04])
+                .unwrap()
+                + 4;
+            cert_bytes[point + 1] ^= 0xff;
+            let vek = Certificate::from_der(&cert_bytes).unwrap();
+
+            let mut att_report = AttestationReport::default();
+            att_report.version = 2;
+            let err = verify_attestation_signature(vek, att_report, true).unwrap_err();
+            let message = format!("{err:#}");
+            assert!(message.starts_with("Failed to get the public key from the VEK.: OpenSSL: "));
+            assert!(message.contains("decode error"));
+        }
+
+        #[test]
+        fn test_check_vek_key_usage() {
+            let (cert_bytes, _) = cert_and_hw_id();
+            let vek = Certificate::from_der(&cert_bytes).unwrap();
+            assert!(check_vek_key_usage(&vek).is_ok());
+        }
+
+        #[test]
+        fn test_find_vek_in_dir() {
+            let dir = std::env::temp_dir().join(format!("snpguest-vek-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("vcek.der"), cert_and_hw_id().0).unwrap();
+            let mut att_report = AttestationReport::default();
+
+            assert!(find_vek_in_dir(&dir, Some(&Endorsement::Vcek), &att_report).is_ok());
+            assert!(find_vek_in_dir(&dir, None, &att_report).is_ok());
+            let err = find_vek_in_dir(&dir, Some(&Endorsement::Vlek), &att_report).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "The report was VLEK-signed but only a VCEK is present."
+            );
+
+            // A VCEK named by the chip ID is preferred, and an unparseable one shows it was used
+            att_report.chip_id = [0xab; 64];
+            fs::write(dir.join(format!("{}.pem", "ab".repeat(64))), b"not a cert").unwrap();
+            assert!(find_vek_in_dir(&dir, Some(&Endorsement::Vcek), &att_report).is_err());
+            att_report.chip_id = [0xcd; 64];
+            assert!(find_vek_in_dir(&dir, Some(&Endorsement::Vcek), &att_report).is_ok());
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn test_verify_fetched_local_vek() {
+            let dir = std::env::temp_dir().join(format!("snpguest-fetched-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let att_report = AttestationReport::default();
+            let vlek = Some(&Endorsement::Vlek);
+            assert!(!vek_in_dir(&dir, vlek, &att_report).unwrap());
+
+            let args = Args::parse_from(["verify", "--fetch", dir.to_str().unwrap(), "report.bin"]);
+            let err = verify_fetched(&args, att_report, vlek, true).unwrap_err();
+            assert!(
+                err.to_string().contains("no VLEK in the certs-dir"),
+                "{err}"
+            );
+
+            // A VEK that is present but fails to load is an error, not a reason to fetch one
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("vlek.pem"), b"not a cert").unwrap();
+            assert!(vek_in_dir(&dir, vlek, &att_report).unwrap());
+            assert!(!vek_in_dir(&dir, Some(&Endorsement::Vcek), &att_report).unwrap());
+            let err = verify_fetched(&args, att_report, vlek, true).unwrap_err();
+            assert!(!err.to_string().contains("KDS"), "{err}");
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn test_check_missing_oids() {
+            assert!(check_missing_oids(&[], true, true).is_ok());
+
+            // Without the Microcode OID, only strict mode fails.
+            assert!(check_missing_oids(&[SnpOid::Ucode], false, true).is_ok());
+            let err = check_missing_oids(&[SnpOid::Ucode], true, true).unwrap_err();
+            assert!(err.to_string().contains("Microcode"));
+        }
+
+        #[test]
+        fn test_allowed_tcb_mismatches() {
+            let allowed: AllowedTcbMismatches = "microcode, bl".parse().unwrap();
+            assert_eq!(
+                allowed,
+                AllowedTcbMismatches {
+                    bootloader: true,
+                    microcode: true,
+                    ..Default::default()
+                }
+            );
+            assert!("pubkey".parse::<AllowedTcbMismatches>().is_err());
+
+            assert!(tcb_mismatch("Microcode", allowed.microcode, true).is_ok());
+            let err = tcb_mismatch("SNP", allowed.snp, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Report TCB SNP and Certificate SNP mismatch encountered."
+            );
+        }
+
+        #[test]
+        fn test_check_tcb_ordering() {
+            let (cert_bytes, _) = cert_and_hw_id();
+            let (_, vek) = X509Certificate::from_der(&cert_bytes).unwrap();
+            let extensions = vek.extensions_map().unwrap();
+            let cert_value = |snp_oid: SnpOid| cert_int(extensions[&snp_oid.oid()]).unwrap() as u8;
+            let vek_tcb = Tcb {
+                fmc: None,
+                bootloader: cert_value(SnpOid::BootLoader),
+                tee: cert_value(SnpOid::Tee),
+                snp: cert_value(SnpOid::Snp),
+                microcode: cert_value(SnpOid::Ucode),
+            };
+            let ordering =
+                |tcb: &Tcb| check_tcb_ordering(&compare_tcb(tcb, &extensions).unwrap(), true);
+            assert!(ordering(&vek_tcb).is_ok());
+
+            // A report ahead of the VEK is caught by the equality check, not this one
+            let newer = Tcb {
+                snp: vek_tcb.snp + 1,
+                ..vek_tcb
+            };
+            assert!(ordering(&newer).is_ok());
+
+            let older = Tcb {
+                microcode: vek_tcb.microcode - 1,
+                ..vek_tcb
+            };
+            assert_eq!(
+                ordering(&older).unwrap_err().to_string(),
+                format!(
+                    "The VEK encodes a newer TCB than the attestation report: Microcode {} > {}.",
+                    vek_tcb.microcode, older.microcode
+                )
+            );
+        }
+
+        // A report of the dummy VCEK chip at the TCB the VCEK was issued for.
+        #[test]
+        fn test_verify_attestation_tcb() {
+            let (cert_bytes, hw_id) = cert_and_hw_id();
+            let vek = Certificate::from_der(&cert_bytes).unwrap();
+            let mut att_report = dummy_vcek_report();
+            let verify = |att_report: &AttestationReport| {
+                verify_attestation_tcb(&vek, att_report, &ProcType::Milan).unwrap()
+            };
+
+            let tcb = verify(&att_report);
+            assert_eq!(tcb.vek_type, CertType::VCEK);
+            assert!(tcb.missing_oids.is_empty());
+            assert!(!tcb.vek_fmc && !tcb.legacy_hwid && tcb.hwid_error.is_none());
+            let components: Vec<SnpOid> = tcb.fields.iter().map(|field| field.component).collect();
+            assert_eq!(
+                components,
+                [
+                    SnpOid::BootLoader,
+                    SnpOid::Tee,
+                    SnpOid::Snp,
+                    SnpOid::Ucode,
+                    SnpOid::HwId
+                ]
+            );
+            assert!(tcb
+                .fields
+                .iter()
+                .all(|field| field.matched && !field.vek_newer));
+            assert_eq!(
+                tcb.fields.last(),
+                Some(&TcbField {
+                    component: SnpOid::HwId,
+                    expected: hex::encode(hw_id),
+                    actual: hex::encode(hw_id),
+                    matched: true,
+                    vek_newer: false,
+                })
+            );
+
+            // Only the fields that differ are recorded as mismatched, and nothing fails
+            att_report.reported_tcb.snp += 1;
+            att_report.reported_tcb.microcode -= 1;
+            att_report.chip_id[0] ^= 1;
+            let tcb = verify(&att_report);
+            let mismatched: Vec<(SnpOid, bool)> = tcb
+                .fields
+                .iter()
+                .filter(|field| !field.matched)
+                .map(|field| (field.component, field.vek_newer))
+                .collect();
+            assert_eq!(
+                mismatched,
+                [
+                    (SnpOid::Snp, false),
+                    (SnpOid::Ucode, true),
+                    (SnpOid::HwId, false)
+                ]
+            );
+            let snp = &tcb.fields[2];
+            assert_eq!(snp.actual, att_report.reported_tcb.snp.to_string());
+            assert_eq!(snp.expected, (att_report.reported_tcb.snp - 1).to_string());
+
+            // A masked chip ID can't match, and says why
+            att_report.chip_id = [0; 64];
+            let tcb = verify(&att_report);
+            assert!(!tcb.fields.last().unwrap().matched);
+            assert!(tcb.hwid_error.unwrap().contains("masked"));
+
+            // The layout of the processor model is still enforced
+            assert!(verify_attestation_tcb(&vek, &att_report, &ProcType::Turin).is_err());
+        }
+
+        #[test]
+        fn test_with_record() {
+            let failed = || Err::<(), _>(anyhow::anyhow!("The report was tampered with."));
+            let unwritable = || Err(anyhow::anyhow!("Could not write verification record."));
+
+            // The verification error wins over the record one
+            let err = with_record(failed(), unwritable()).unwrap_err();
+            assert_eq!(err.to_string(), "The report was tampered with.");
+            let err = with_record(failed(), Ok(())).unwrap_err();
+            assert_eq!(err.to_string(), "The report was tampered with.");
+            // A passing verification still fails without its record
+            let err = with_record(Ok(()), unwritable()).unwrap_err();
+            assert_eq!(err.to_string(), "Could not write verification record.");
+            assert!(with_record(Ok(()), Ok(())).is_ok());
+        }
+
+        #[test]
+        fn test_skip_hwid() {
+            let (cert_bytes, _) = cert_and_hw_id();
+            // The report of a migrated guest, taken on another chip
+            let mut att_report = dummy_vcek_report();
+            att_report.chip_id[0] ^= 1;
+
+            let check = |att_report: AttestationReport, extra: &[&str]| {
+                let args = Args::parse_from(
+                    [&["verify", "--tcb", "--report-all"], extra, &["report.bin"]].concat(),
+                );
+                let vek = Certificate::from_der(&cert_bytes).unwrap();
+                let mut checks = Checks::new(true);
+                let result =
+                    check_report(&args, att_report, vek, &ProcType::Milan, &mut checks, true);
+                let failed: Vec<String> = checks
+                    .to_json()
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .filter(|check| check["passed"] == false)
+                    .map(|check| check["check"].as_str().unwrap().to_string())
+                    .collect();
+                (result.is_ok(), failed)
+            };
+            assert_eq!(check(att_report, &[]), (false, vec!["chip_id".to_string()]));
+            let (_, failed) = check(att_report, &["--skip-hwid"]);
+            assert!(!failed.contains(&"chip_id".to_string()));
+
+            // The TCB is still compared
+            att_report.reported_tcb.snp += 1;
+            let (passed, failed) = check(att_report, &["--skip-hwid"]);
+            assert!(!passed);
+            assert!(failed.contains(&"tcb_snp".to_string()));
+        }
+
+        #[test]
+        fn test_signature_keeps_report_tcb_checks() {
+            let (cert_bytes, _) = cert_and_hw_id();
+            let mut att_report = dummy_vcek_report();
+            att_report.current_tcb = att_report.reported_tcb;
+            att_report.current_tcb.snp += 1;
+
+            let failed = |extra: &[&str]| {
+                let args = Args::parse_from(
+                    [
+                        &["verify", "--signature", "--report-all"],
+                        extra,
+                        &["report.bin"],
+                    ]
+                    .concat(),
+                );
+                let vek = Certificate::from_der(&cert_bytes).unwrap();
+                let mut checks = Checks::new(true);
+                // The dummy VCEK never signed the report
+                assert!(
+                    check_report(&args, att_report, vek, &ProcType::Milan, &mut checks, true)
+                        .is_err()
+                );
+                let failed: Vec<String> = checks
+                    .to_json()
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .filter(|check| check["passed"] == false)
+                    .map(|check| check["check"].as_str().unwrap().to_string())
+                    .collect();
+                failed
+            };
+            assert_eq!(failed(&[]), ["signature"]);
+            assert_eq!(
+                failed(&["--min-tcb", "snp=255", "--no-downgrade"]),
+                ["min_tcb", "signature", "tcb_downgrade"]
+            );
+
+            // Comparisons with the VEK TCB can't be asked of a signature-only verification
+            for flag in ["--skip-hwid", "--check-tcb-ordering", "--strict-tcb"] {
+                assert!(Args::try_parse_from(["verify", "-s", flag, "report.bin"]).is_err());
+            }
+        }
+
+        #[test]
+        fn test_model_from_vek() {
+            let vek = Certificate::from_der(&cert_and_hw_id().0).unwrap();
+            let (model, reason) = model_from_vek(&vek).unwrap();
+            assert_eq!(model, ProcType::Milan);
+            assert_eq!(reason, "common name SEV-Milan of the VEK issuer");
+
+            // A version 2 report does not identify its processor model
+            let mut att_report = AttestationReport::default();
+            att_report.version = 2;
+            let model =
+                verified_processor_model(None, &att_report, Some(&vek), false, true).unwrap();
+            assert_eq!(model, ProcType::Milan);
+            let model = verified_processor_model(
+                Some(&ProcType::Genoa),
+                &att_report,
+                Some(&vek),
+                false,
+                true,
+            )
+            .unwrap();
+            assert_eq!(model, ProcType::Genoa);
+        }
+
+        #[test]
+        fn test_min_tcb() {
+            let min_tcb: MinTcb = "bootloader=3, snp=8".parse().unwrap();
+            assert_eq!(
+                min_tcb,
+                MinTcb {
+                    bootloader: Some(3),
+                    snp: Some(8),
+                    ..Default::default()
+                }
+            );
+
+            let mut tcb = Tcb {
+                fmc: None,
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            };
+            assert!(min_tcb.check(&tcb).is_ok());
+
+            tcb.snp = 7;
+            assert!(min_tcb.check(&tcb).is_err());
+
+            assert!("fmc=1".parse::<MinTcb>().unwrap().check(&tcb).is_err());
+            assert!("bootloader".parse::<MinTcb>().is_err());
+            assert!("pubkey=1".parse::<MinTcb>().is_err());
+        }
+
+        #[test]
+        fn test_tcb_downgrades() {
+            let current_tcb = Tcb {
+                fmc: None,
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            };
+            assert!(tcb_downgrades(&current_tcb, &current_tcb).is_empty());
+
+            let reported_tcb = Tcb {
+                snp: 7,
+                ..current_tcb
+            };
+            assert_eq!(
+                tcb_downgrades(&reported_tcb, &current_tcb),
+                vec!["SNP 7 < 8".to_string()]
+            );
+            assert!(tcb_downgrades(&current_tcb, &reported_tcb).is_empty());
+        }
+
+        #[test]
+        fn test_check_fmc_layout() {
+            let turin_tcb = Tcb {
+                fmc: Some(1),
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            };
+            assert_eq!(
+                check_fmc_layout(&ProcType::Turin, &turin_tcb, true).unwrap(),
+                Some(1)
+            );
+
+            // A Turin report whose reported TCB lacks an FMC
+            let no_fmc = Tcb {
+                fmc: None,
+                ..turin_tcb
+            };
+            let err = check_fmc_layout(&ProcType::Turin, &no_fmc, true).unwrap_err();
+            assert!(err.to_string().contains("but the report does not"));
+
+            assert_eq!(
+                check_fmc_layout(&ProcType::Milan, &no_fmc, false).unwrap(),
+                None
+            );
+            assert!(check_fmc_layout(&ProcType::Milan, &no_fmc, true).is_err());
+        }
+
+        #[test]
+        fn test_verify_no_debug() {
+            let mut att_report = AttestationReport::default();
+            att_report.policy = sev::firmware::guest::GuestPolicy(0x30000);
+            assert!(verify_no_debug(&att_report, true).is_ok());
+
+            att_report.policy = sev::firmware::guest::GuestPolicy(0xB0000);
+            assert!(verify_no_debug(&att_report, true)
+                .unwrap_err()
+                .to_string()
+                .starts_with("The guest policy allows debugging (bit 19 is set)"));
+        }
+
+        #[test]
+        fn test_resolve_args_paths() {
+            let resolve = |args: &[&str]| {
+                let mut args = Args::try_parse_from(args)?;
+                resolve_args_paths(&mut args).map(|_| (args.certs_dir, args.att_report_path))
+            };
+            let certs_and_report = (Some(PathBuf::from("certs")), PathBuf::from("report.bin"));
+
+            for args in [
+                &["attestation", "certs", "report.bin"][..],
+                &[
+                    "attestation",
+                    "--report",
+                    "report.bin",
+                    "--certs-dir",
+                    "certs",
+                ],
+                &["attestation", "--certs-dir", "certs", "report.bin"],
+                &["attestation", "--report", "report.bin", "certs"],
+            ] {
+                assert_eq!(resolve(args).unwrap(), certs_and_report, "{args:?}");
+            }
+            assert_eq!(
+                resolve(&["attestation", "--report", "report.bin"]).unwrap(),
+                (None, PathBuf::from("report.bin"))
+            );
+
+            // The report or certs-dir can't be given twice
+            assert!(resolve(&[
+                "attestation",
+                "certs",
+                "report.bin",
+                "--report",
+                "other.bin"
+            ])
+            .is_err());
+            assert!(
+                resolve(&["attestation", "certs", "report.bin", "--certs-dir", "other"]).is_err()
+            );
+            assert!(
+                resolve(&["attestation", "--certs-dir", "certs", "--vek", "vcek.pem"]).is_err()
+            );
+        }
+
+        #[test]
+        fn test_verify_min_abi() {
+            let mut att_report = AttestationReport::default();
+            att_report.policy = sev::firmware::guest::GuestPolicy(0x30133);
+            let min_abi: AbiVersion = "1.51".parse().unwrap();
+            assert!(verify_min_abi(&att_report, min_abi, true).is_ok());
+
+            att_report.policy = sev::firmware::guest::GuestPolicy(0x30100);
+            assert_eq!(
+                verify_min_abi(&att_report, min_abi, true)
+                    .unwrap_err()
+                    .to_string(),
+                "The guest policy requires firmware ABI 1.0, but at least ABI 1.51 is required."
+            );
+        }
+
+        #[test]
+        fn test_verify_vmpl() {
+            let mut att_report = AttestationReport::default();
+            att_report.vmpl = 2;
+
+            assert!(verify_vmpl(&att_report, 2, true).is_ok());
+            let err = verify_vmpl(&att_report, 0, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "The report was requested at VMPL 2, but VMPL 0 is required."
+            );
+        }
+
+        #[test]
+        fn test_verify_signer() {
+            assert!(verify_signer(&Endorsement::Vcek, &Endorsement::Vcek, true).is_ok());
+            let err = verify_signer(&Endorsement::Vlek, &Endorsement::Vcek, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "The report was signed by the VLEK, but the VCEK is required."
+            );
+        }
+
+        #[test]
+        fn test_verify_key_digest() {
+            let digest = [0xAB; 48];
+            let expected = hex::encode(digest);
+
+            assert!(verify_key_digest("author key", &digest, true, &expected, true).is_ok());
+            let err =
+                verify_key_digest("author key", &[0xCD; 48], true, &expected, true).unwrap_err();
+            assert!(err.to_string().contains("does not match"));
+            // AUTHOR_KEY_EN is clear
+            let err = verify_key_digest("author key", &digest, false, &expected, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "An author key digest was expected, but the report has none."
+            );
+            assert!(verify_key_digest("ID key", &digest, true, "abcd", true).is_err());
+        }
+
+        #[test]
+        fn test_verify_launch_id() {
+            let family_id = [0x5A; 16];
+            let expected = hex::encode(family_id);
+
+            assert!(verify_launch_id("family ID", &family_id, &expected, true).is_ok());
+            let err = verify_launch_id("family ID", &[0; 16], &expected, true).unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("does not match the expected family ID"));
+            let err = verify_launch_id("image ID", &family_id, "5a5a", true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Expected image ID must be 16 bytes, but 2 were given."
+            );
+
+            // Files may hold the ID raw or hex encoded
+            let path = std::env::temp_dir().join(format!("snpguest-id-{}", std::process::id()));
+            for contents in [family_id.to_vec(), format!("{expected}\n").into_bytes()] {
+                fs::write(&path, contents).unwrap();
+                assert!(
+                    verify_launch_id("family ID", &family_id, path.to_str().unwrap(), true).is_ok()
+                );
+            }
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_require_current_equals_reported() {
+            let mut att_report = AttestationReport::default();
+            att_report.reported_tcb.snp = 8;
+            att_report.current_tcb.snp = 8;
+            assert!(verify_tcb_downgrade(&att_report, &ProcType::Milan, false, true, true).is_ok());
+
+            // A newer current TCB is a downgrade of the report, only a warning by default
+            att_report.current_tcb.snp = 9;
+            assert!(
+                verify_tcb_downgrade(&att_report, &ProcType::Milan, false, false, true).is_ok()
+            );
+            let err =
+                verify_tcb_downgrade(&att_report, &ProcType::Milan, false, true, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Current TCB differs from the reported TCB: SNP 8 reported, 9 current (downgrade)"
+            );
+        }
+
+        #[test]
+        fn test_tcb_relation_matches_no_downgrade() {
+            let mut att_report = AttestationReport::default();
+            att_report.reported_tcb.snp = 8;
+            att_report.current_tcb.snp = 9;
+            let decode =
+                |tcb: &sev::firmware::host::TcbVersion| ProcType::Milan.decode_tcb(tcb).unwrap();
+            let (reported_tcb, current_tcb) = (
+                decode(&att_report.reported_tcb),
+                decode(&att_report.current_tcb),
+            );
+            let verify = |att_report: &AttestationReport, require_equal| {
+                verify_tcb_downgrade(att_report, &ProcType::Milan, true, require_equal, true)
+            };
+
+            // The component the table flags as a downgrade is the one --no-downgrade rejects
+            let relations: Vec<TcbRelation> = reported_tcb
+                .compare(&current_tcb)
+                .into_iter()
+                .map(|(_, _, _, relation)| relation)
+                .collect();
+            assert_eq!(
+                relations,
+                [
+                    TcbRelation::Equal,
+                    TcbRelation::Equal,
+                    TcbRelation::Downgrade,
+                    TcbRelation::Equal
+                ]
+            );
+            assert_eq!(tcb_downgrades(&reported_tcb, &current_tcb), ["SNP 8 < 9"]);
+            assert_eq!(
+                verify(&att_report, false).unwrap_err().to_string(),
+                "Reported TCB is older than the current TCB: SNP 8 < 9"
+            );
+
+            // A report newer than the platform is no downgrade
+            att_report.current_tcb.snp = 7;
+            let current_tcb = decode(&att_report.current_tcb);
+            assert_eq!(
+                reported_tcb.compare(&current_tcb)[2].3,
+                TcbRelation::ReportedNewer
+            );
+            assert!(tcb_downgrades(&reported_tcb, &current_tcb).is_empty());
+            assert!(verify(&att_report, false).is_ok());
+            assert!(verify(&att_report, true)
+                .unwrap_err()
+                .to_string()
+                .contains("SNP 8 reported, 7 current (reported newer)"));
+        }
+
+        #[test]
+        fn test_check_cert_bytes_integer() {
+            let (cert_bytes, _) = cert_and_hw_id();
+            let val = 0x1Eu8;
+            let dummy_x509: X509Certificate = X509Certificate::from_der(&cert_bytes).unwrap().1;
+            let extensions = dummy_x509.extensions_map().unwrap();
+            let ext = extensions.get(&SnpOid::Ucode.oid()).unwrap();
+            assert!(check_cert_bytes(ext, &val.to_ne_bytes(), 1).unwrap());
+        }
+
+        #[test]
+        fn test_check_cert_bytes_malformed() {
+            use x509_parser::extensions::ParsedExtension;
+
+            let ext = |value| {
+                X509Extension::new(
+                    SnpOid::HwId.oid(),
+                    false,
+                    value,
+                    ParsedExtension::UnsupportedExtension {
+                        oid: SnpOid::HwId.oid(),
+                    },
+                )
+            };
+
+            // Truncated extensions fail, naming the OID, instead of panicking
+            for value in [
+                &[][..],
+                &[0x2],
+                &[0x2, 0x1],
+                &[0x4],
+                &[0x4, 0x8, 0xAB],
+                &[0x5; 12],
+            ] {
+                let err = check_cert_bytes(&ext(value), &[0xAB; 8], 8).unwrap_err();
+                assert!(
+                    err.to_string().contains(&SnpOid::HwId.oid().to_string()),
+                    "{err}"
+                );
+            }
+            assert!(check_cert_bytes(&ext(&[0x2, 0x2, 0x0, 0x1E]), &[0x1E], 1).unwrap());
+            assert!(!check_cert_bytes(&ext(&[0x2, 0x1, 0x1E]), &[0x1E, 0x0], 1).unwrap());
+        }
+    }
+}
+
+mod all {
+    use super::*;
+
+    use sev::firmware::guest::AttestationReport;
+
+    #[derive(Parser)]
+    #[command(allow_missing_positional = true)]
+    #[group(id = "all")]
+    pub struct Args {
+        #[command(flatten)]
+        pub attestation: attestation::Args,
+
+        /// Path to a pinned AMD ARK, or "-" to read it from stdin. The ARK in the directory must
+        /// match it.
+        #[arg(long, value_name = "trusted-ark")]
+        pub trusted_ark: Option<PathBuf>,
+
+        /// Verify the attestation report even if the certificate chain is invalid.
+        #[arg(long)]
+        pub continue_on_chain_failure: bool,
+
+        /// Testing only: skip the certificate chain and only verify the attestation report
+        /// against its VEK. Never use it in production.
+        #[arg(long, conflicts_with_all = ["trusted_ark", "continue_on_chain_failure", "ca_chain"])]
+        pub insecure_skip_chain: bool,
+    }
+
+    #[derive(Parser)]
+    #[command(allow_missing_positional = true)]
+    #[group(id = "verify_extended")]
+    pub struct ExtendedArgs {
+        #[command(flatten)]
+        pub attestation: attestation::Args,
+
+        /// Path to a pinned AMD ARK, or "-" to read it from stdin. The embedded ARK must match it,
+        /// or else one of the ARKs bundled with snpguest.
+        #[arg(long, value_name = "trusted-ark")]
+        pub trusted_ark: Option<PathBuf>,
+    }
+
+    // Get the chain embedded in an extended report, if it holds the ARK, ASK and VEK.
+    fn embedded_chain(
+        embedded: &[CertTableEntry],
+        signing_key: Option<&Endorsement>,
+    ) -> Result<Option<(Chain, &'static str, &'static str)>> {
+        let (Some(ark), Some(ask), Some(vek)) = (
+            embedded_cert(embedded, CertType::ARK)?,
+            embedded_cert(embedded, CertType::ASK)?,
+            embedded_vek(embedded, signing_key)?,
+        ) else {
+            return Ok(None);
+        };
+
+        let (vek_type, sign_type) = match cert_type(&vek)? {
+            CertType::VLEK => ("vlek", "asvk"),
+            _ => ("vcek", "ask"),
+        };
+
+        Ok(Some((
+            Chain {
+                ca: ca::Chain { ark, ask },
+                vek,
+            },
+            vek_type,
+            sign_type,
+        )))
+    }
+
+    // Roles of the chain of the signing key the certificate table of a report is missing.
+    fn missing_roles(
+        embedded: &[CertTableEntry],
+        signing_key: Option<&Endorsement>,
+    ) -> Vec<&'static str> {
+        let has = |cert_type: CertType| embedded.iter().any(|cert| cert.cert_type == cert_type);
+        let mut missing = Vec::new();
+        if !has(CertType::ARK) {
+            missing.push("ARK");
+        }
+        if !has(CertType::ASK) {
+            missing.push(match signing_key {
+                Some(Endorsement::Vlek) => "ASVK",
+                _ => "ASK",
+            });
+        }
+        match signing_key {
+            Some(Endorsement::Vcek) if !has(CertType::VCEK) => missing.push("VCEK"),
+            Some(Endorsement::Vlek) if !has(CertType::VLEK) => missing.push("VLEK"),
+            None if !has(CertType::VCEK) && !has(CertType::VLEK) => missing.push("VCEK or VLEK"),
+            _ => (),
+        }
+
+        missing
+    }
+
+    // Get the chain embedded in an extended report, with its ARK pinned. The host writes the
+    // certificate table, so without a --trusted-ark (checked along with the chain) the ARK must
+    // be one bundled with snpguest.
+    pub fn pinned_embedded_chain(
+        embedded: &[CertTableEntry],
+        signing_key: Option<&Endorsement>,
+        trusted_ark: Option<&Path>,
+        quiet: bool,
+    ) -> Result<Option<(Chain, &'static str, &'static str)>> {
+        let Some(chain) = embedded_chain(embedded, signing_key)? else {
+            return Ok(None);
+        };
+        if trusted_ark.is_none() {
+            let Some(model) = bundled_ark_model(&chain.0.ca.ark)? else {
+                return Err(anyhow::anyhow!(
+                    "The embedded ARK is not an AMD ARK bundled with snpguest. Pin the ARK with --trusted-ark, or provide the chain with a certs-dir or --chain-file."
+                ));
+            };
+            if !quiet {
+                check_passed(&format!(
+                    "The embedded ARK matches the AMD {model} ARK bundled with snpguest!"
+                ));
+            }
+        }
+
+        Ok(Some(chain))
+    }
+
+    // Verify an extended report with nothing but the certificates embedded in it. The host
+    // writes the certificate table, so the embedded ARK must be AMD's: the --trusted-ark, or
+    // else one bundled with snpguest.
+    pub fn verify_extended(mut args: ExtendedArgs, quiet: bool) -> Result<()> {
+        attestation::resolve_args_paths(&mut args.attestation)?;
+        let attestation = &mut args.attestation;
+        if attestation.certs_dir.is_some()
+            || attestation.chain_file.is_some()
+            || attestation.vek.is_some()
+            || attestation.ca_chain.is_some()
+            || attestation.fetch
+        {
+            return Err(anyhow::anyhow!(
+                "verify extended only uses the certificates embedded in the report. Use verify all to verify it against other certificates."
+            ));
+        }
+        check_single_stdin(&[
+            Some(attestation.att_report_path.as_path()),
+            args.trusted_ark.as_deref(),
+        ])?;
+        attestation.extended = true;
+
+        let (att_report, embedded) = attestation::read_args_report(attestation)?;
+        let signing_key = report::get_signing_key(&att_report)?;
+        let missing = missing_roles(&embedded, signing_key.as_ref());
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The certificate table of the extended report is missing the {}.",
+                missing.join(", ")
+            ));
+        }
+
+        let args = Args {
+            attestation: args.attestation,
+            trusted_ark: args.trusted_ark,
+            continue_on_chain_failure: false,
+            insecure_skip_chain: false,
+        };
+        verify_chain_and_report(&args, att_report, &embedded, quiet)
+    }
+
+    pub fn verify_all(mut args: Args, quiet: bool) -> Result<()> {
+        attestation::resolve_args_paths(&mut args.attestation)?;
+        check_single_stdin(&[
+            Some(args.attestation.att_report_path.as_path()),
+            args.attestation.chain_file.as_deref(),
+            args.attestation.vek.as_deref(),
+            args.attestation.ca_chain.as_deref(),
+            args.trusted_ark.as_deref(),
+        ])?;
+        attestation::resolve_args_certs_dir(&mut args.attestation)?;
+
+        // Printed even with --quiet, so a skipped chain never goes unnoticed
+        if args.insecure_skip_chain {
+            eprintln!(
+                "WARNING: --insecure-skip-chain is set, the certificate chain is NOT verified. \
+                The VEK is trusted as is, which is only fit for testing."
+            );
+            return attestation::verify_resolved(&args.attestation, quiet);
+        }
+
+        if args.attestation.vek.is_some() && args.attestation.ca_chain.is_none() {
+            return Err(anyhow::anyhow!(
+                "--vek only provides the VEK. Verify the whole chain with a certs-dir, --chain-file or --ca-chain."
+            ));
+        }
+        if args.attestation.fetch {
+            return Err(anyhow::anyhow!(
+                "--fetch already verifies the certificate chain. Use it with verify attestation."
+            ));
+        }
+
+        if let (true, Some(certs_dir)) = (args.attestation.strict_dir, &args.attestation.certs_dir)
+        {
+            check_strict_dir(certs_dir)?;
+        }
+
+        let (att_report, embedded) = attestation::read_args_report(&args.attestation)?;
+
+        let result = verify_chain_and_report(&args, att_report, &embedded, quiet);
+        if let (Err(_), true) = (&result, args.attestation.print_urls) {
+            attestation::print_report_urls(&args.attestation, &att_report);
+        }
+
+        result
+    }
+
+    fn verify_chain_and_report(
+        args: &Args,
+        att_report: AttestationReport,
+        embedded: &[CertTableEntry],
+        quiet: bool,
+    ) -> Result<()> {
+        let signing_key = report::get_signing_key(&att_report)?;
+
+        // The claims are the only output in JSON and EAT formats
+        let text_quiet = quiet || args.attestation.output != VerifyOutput::Text;
+
+        // Pick the chain of the key the report was signed with. The embedded one is only used
+        // when no other was given.
+        let given = args.attestation.certs_dir.is_some()
+            || args.attestation.chain_file.is_some()
+            || args.attestation.ca_chain.is_some();
+        let embedded_chain = if given {
+            None
+        } else {
+            pinned_embedded_chain(
+                embedded,
+                signing_key.as_ref(),
+                args.trusted_ark.as_deref(),
+                text_quiet,
+            )?
+        };
+        let (cert_chain, vek_type, sign_type) = match embedded_chain {
+            Some(chain) => chain,
+            None if args.attestation.extended && !given => {
+                return Err(anyhow::anyhow!(
+                    "The extended report doesn't embed the full certificate chain. Provide it with a certs-dir or --chain-file."
+                ));
+            }
+            None => certificate_chain::load_chain(
+                args.attestation.certs_dir.as_deref(),
+                args.attestation.chain_file.as_deref(),
+                args.attestation
+                    .ca_chain
+                    .as_deref()
+                    .zip(args.attestation.vek.as_deref()),
+                signing_key.as_ref(),
+            )?,
+        };
+
+        // An ARK CRL next to the chain is checked along with it, as verify certs does
+        let chain_result = certificate_chain::check_chain(
+            &cert_chain,
+            vek_type,
+            sign_type,
+            args.trusted_ark.as_deref(),
+            text_quiet,
+        )
+        .and_then(|()| {
+            match args
+                .attestation
+                .certs_dir
+                .as_deref()
+                .and_then(find_crl_in_dir)
+            {
+                Some(crl_path) => {
+                    certificate_chain::check_crl(&crl_path, &cert_chain, sign_type, text_quiet)
+                }
+                None => Ok(()),
+            }
+        });
+        match &chain_result {
+            Err(_) if !args.continue_on_chain_failure => {
+                return chain_result.context("Certificate chain verification failed");
+            }
+            Err(err) if !quiet => {
+                eprintln!("WARNING: Certificate chain verification failed, continuing: {err}");
+            }
+            _ => (),
+        }
+
+        attestation::verify_report(&args.attestation, att_report, cert_chain.vek, quiet)
+            .context("Attestation report verification failed")?;
+        chain_result
+            .context("Attestation report verified, but the certificate chain is invalid")?;
+
+        if !text_quiet {
+            check_passed(&format!(
+                "The certificate chain and the attestation report were verified using the {}!",
+                vek_type.to_uppercase()
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_missing_roles() {
+            let entry = |cert_type| CertTableEntry::new(cert_type, vec![]);
+            let table = [
+                entry(CertType::ARK),
+                entry(CertType::ASK),
+                entry(CertType::VCEK),
+            ];
+
+            assert!(missing_roles(&table, Some(&Endorsement::Vcek)).is_empty());
+            assert!(missing_roles(&table, None).is_empty());
+            assert_eq!(
+                missing_roles(&table, Some(&Endorsement::Vlek)),
+                vec!["VLEK"]
+            );
+            assert_eq!(
+                missing_roles(&table[2..], Some(&Endorsement::Vlek)),
+                vec!["ARK", "ASVK", "VLEK"]
+            );
+            assert_eq!(missing_roles(&[], None), vec!["ARK", "ASK", "VCEK or VLEK"]);
+        }
+
+        #[test]
+        fn test_bundled_ark_model() {
+            use sev::certs::snp::builtin::{genoa, milan};
+
+            let ark = Certificate::from_pem(genoa::ARK).unwrap();
+            assert_eq!(bundled_ark_model(&ark).unwrap(), Some("Genoa"));
+            // The ASK is signed by the ARK but is not one
+            let ask = Certificate::from_pem(milan::ASK).unwrap();
+            assert_eq!(bundled_ark_model(&ask).unwrap(), None);
+        }
+
+        #[test]
+        fn test_pinned_embedded_chain() {
+            use sev::certs::snp::builtin::milan;
+
+            let der = |pem| Certificate::from_pem(pem).unwrap().to_der().unwrap();
+            let vcek = attestation::cert_and_hw_id().0.to_vec();
+            let table = |ark| {
+                vec![
+                    CertTableEntry::new(CertType::ARK, ark),
+                    CertTableEntry::new(CertType::ASK, der(milan::ASK)),
+                    CertTableEntry::new(CertType::VCEK, vcek.clone()),
+                ]
+            };
+
+            let chain = pinned_embedded_chain(&table(der(milan::ARK)), None, None, true).unwrap();
+            assert_eq!(chain.map(|(_, vek_type, _)| vek_type), Some("vcek"));
+            // A chain of the host's making is rejected unless its ARK is pinned
+            let forged = table(der(milan::ASK));
+            assert!(pinned_embedded_chain(&forged, None, None, true).is_err());
+            assert!(
+                pinned_embedded_chain(&forged, None, Some(Path::new("ark.pem")), true)
+                    .unwrap()
+                    .is_some()
+            );
+            assert!(
+                pinned_embedded_chain(&table(der(milan::ARK))[1..], None, None, true)
+                    .unwrap()
+                    .is_none()
+            );
+        }
+    }
+}
+
+mod attestation_batch {
+    use super::*;
+
+    use sev::firmware::guest::AttestationReport;
+    use std::{
+        collections::HashMap,
+        io::{Read, Write},
+        ops::Range,
+    };
+
+    // Source of the VEK of every report given as certs-dir-or-kds.
+    const KDS_SOURCE: &str = "kds";
+
+    // Bytes of the length prefix of every frame of a report stream.
+    const FRAME_PREFIX_SIZE: usize = 4;
+
+    // Offsets of the report fields the VEK of a report is chosen by: the key info, which holds
+    // the signing key, the reported TCB and the chip ID.
+    const KEY_INFO: Range<usize> = 0x48..0x4C;
+    const REPORTED_TCB: Range<usize> = 0x180..0x188;
+    const CHIP_ID: Range<usize> = 0x1A0..0x1E0;
+
+    #[derive(Parser)]
+    pub struct Args {
+        /// Directory holding the VEK, or "kds" to fetch the VCEK of every report from the KDS.
+        #[arg(value_name = "certs-dir-or-kds", required = true)]
+        pub source: String,
+
+        /// Glob matching the attestation reports to verify, e.g. 'reports/*.bin'.
+        #[arg(value_name = "reports-glob", required = true)]
+        pub reports: String,
+
+        /// Format to print the results in. EAT is only available for a single report.
+        #[arg(short, long, value_enum, default_value_t = VerifyOutput::Text)]
+        pub output: VerifyOutput,
+
+        /// Processor model that produced the reports. Detected from version 3 reports when omitted.
+        #[arg(short, long, value_name = "processor-model")]
+        pub processor_model: Option<ProcType>,
+
+        /// Run the TCB Verification Exclusively.
+        #[arg(short, long, conflicts_with = "signature")]
+        pub tcb: bool,
+
+        /// Run the Signature Verification Exclusively.
+        #[arg(short, long, conflicts_with = "tcb")]
+        pub signature: bool,
+
+        #[command(flatten)]
+        pub kds: KdsArgs,
+
+        #[command(flatten)]
+        pub cache: CacheArgs,
+    }
+
+    #[derive(Parser)]
+    pub struct StreamArgs {
+        /// Directory holding the VEK, or "kds" to fetch the VCEK of every report from the KDS.
+        #[arg(value_name = "certs-dir-or-kds", required_unless_present = "vek")]
+        pub source: Option<String>,
+
+        /// Path to the VEK to verify every report with, instead of a certs-dir.
+        #[arg(long, value_name = "vek", conflicts_with = "source")]
+        pub vek: Option<PathBuf>,
+
+        /// Processor model that produced the reports. Detected from version 3 reports when omitted.
+        #[arg(short, long, value_name = "processor-model")]
+        pub processor_model: Option<ProcType>,
+
+        /// Run the TCB Verification Exclusively.
+        #[arg(short, long, conflicts_with = "signature")]
+        pub tcb: bool,
+
+        /// Run the Signature Verification Exclusively.
+        #[arg(short, long, conflicts_with = "tcb")]
+        pub signature: bool,
+
+        #[command(flatten)]
+        pub kds: KdsArgs,
+
+        #[command(flatten)]
+        pub cache: CacheArgs,
+    }
+
+    // The same checks as verify attestation, with every option but the selectors unset.
+    fn report_args(
+        certs_dir: Option<PathBuf>,
+        output: VerifyOutput,
+        processor_model: Option<ProcType>,
+        tcb: bool,
+        signature: bool,
+        kds: KdsArgs,
+        cache: CacheArgs,
+    ) -> attestation::Args {
+        attestation::Args {
+            certs_dir,
+            positional_report: None,
+            certs_dir_flag: None,
+            report: None,
+            att_report_path: PathBuf::new(),
+            chain_file: None,
+            vek: None,
+            ca_chain: None,
+            output,
+            processor_model,
+            tcb,
+            strict_dir: false,
+            strict_tcb: false,
+            no_legacy_certs: false,
+            skip_hwid: false,
+            min_tcb: None,
+            extra_oids: None,
+            allow_tcb_mismatch: None,
+            check_tcb_ordering: false,
+            no_downgrade: false,
+            require_current_equals_reported: false,
+            policy_require: None,
+            require_no_debug: false,
+            print_claims: false,
+            min_abi: None,
+            assume_model_from_cert: false,
+            report_data: None,
+            require_vmpl: None,
+            require_signed_by: None,
+            id_key_digest: None,
+            author_key_digest: None,
+            expected_family_id: None,
+            expected_image_id: None,
+            strict_reserved: false,
+            print_vek_info: false,
+            report_all: false,
+            report_out: None,
+            print_urls: false,
+            signature,
+            extended: false,
+            fetch: false,
+            kds,
+            cache,
+        }
+    }
+
+    // Paths of the reports matching the glob, in order.
+    fn report_paths(pattern: &str) -> Result<Vec<PathBuf>> {
+        let mut paths = glob::glob(pattern)
+            .context(format!("Invalid reports glob {pattern}"))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!("No attestation reports match {pattern}."));
+        }
+
+        Ok(paths)
+    }
+
+    // VEKs loaded for the reports so far, by the report fields they are chosen by, along with
+    // the CA chain fetched for each processor model the VCEKs are fetched for.
+    #[derive(Default)]
+    struct Veks {
+        veks: HashMap<Vec<u8>, Certificate>,
+        ca_chains: HashMap<&'static str, ca::Chain>,
+    }
+
+    impl Veks {
+        // Get the VEK of a report from the certs-dir, or otherwise the KDS. A fetched VCEK is
+        // only kept once it is verified against the CA chain of its processor model.
+        fn load(
+            &mut self,
+            args: &attestation::Args,
+            att_report: &AttestationReport,
+        ) -> Result<Certificate> {
+            let report_bytes = bincode::serialize(att_report)
+                .context("Could not serialize attestation report.")?;
+            let key = [
+                &report_bytes[KEY_INFO],
+                &report_bytes[REPORTED_TCB],
+                &report_bytes[CHIP_ID],
+            ]
+            .concat();
+            if let Some(vek) = self.veks.get(&key) {
+                return Ok(vek.clone());
+            }
+
+            let signing_key = report::get_signing_key(att_report)?;
+            let vek = match &args.certs_dir {
+                Some(certs_dir) => {
+                    attestation::find_vek_in_dir(certs_dir, signing_key.as_ref(), att_report)?
+                }
+                None => {
+                    let vek = attestation::fetch_kds_vek(args, att_report, signing_key.as_ref())?;
+                    let cert_chain = Chain {
+                        ca: self.ca_chain(args, att_report)?,
+                        vek,
+                    };
+                    certificate_chain::check_chain(&cert_chain, "vcek", "ask", None, true)
+                        .context("Certificate chain verification failed")?;
+                    cert_chain.vek
+                }
+            };
+            self.veks.insert(key, vek.clone());
+
+            Ok(vek)
+        }
+
+        // Fetch the VCEK CA chain of the processor model of a report, once per model.
+        fn ca_chain(
+            &mut self,
+            args: &attestation::Args,
+            att_report: &AttestationReport,
+        ) -> Result<ca::Chain> {
+            let proc_model = report_model(args, att_report)?;
+            if let Some(ca_chain) = self.ca_chains.get(proc_model.kds_path()) {
+                return Ok(ca_chain.clone());
+            }
+
+            let (ark, ask) =
+                fetch::fetch_ca_chain(&args.kds, &args.cache, &proc_model, &Endorsement::Vcek)
+                    .context("Fetching the CA chain from the KDS failed")?;
+            let ca_chain = ca::Chain {
+                ark: ark.into(),
+                ask: ask.into(),
+            };
+            self.ca_chains
+                .insert(proc_model.kds_path(), ca_chain.clone());
+
+            Ok(ca_chain)
+        }
+    }
+
+    // Processor model of a report, as given or detected. Only version 3 reports identify it,
+    // and the TCB layout depends on it, so it is never assumed.
+    fn report_model(args: &attestation::Args, att_report: &AttestationReport) -> Result<ProcType> {
+        match &args.processor_model {
+            Some(proc_model) => Ok(proc_model.clone()),
+            None => report::get_processor_model(att_report).context(
+                "Provide the processor model with --processor-model to verify the report.",
+            ),
         }
+    }
 
-        /// Important note that this is NOT a valid certificate,
-        /// and the signature will NOT match at all.
-        fn cert_and_hw_id() -> ([u8; 1362], [u8; 64]) {
-            (
-                [
-                    0x30, 0x82, 0x05, 0x4e, 0x30, 0x82, 0x02, 0xfd, 0xa0, 0x03, 0x02, 0x01, 0x02,
-                    0x02, 0x01, 0x00, 0x30, 0x46, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d,
-                    0x01, 0x01, 0x0a, 0x30, 0x39, 0xa0, 0x0f, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86,
-                    0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0xa1, 0x1c, 0x30, 0x1a,
-                    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x08, 0x30, 0x0d,
-                    0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00,
-                    0xa2, 0x03, 0x02, 0x01, 0x30, 0xa3, 0x03, 0x02, 0x01, 0x01, 0x30, 0x7b, 0x31,
-                    0x14, 0x30, 0x12, 0x06, 0x03, 0x55, 0x04, 0x0b, 0x0c, 0x0b, 0x45, 0x6e, 0x67,
-                    0x69, 0x6e, 0x65, 0x65, 0x72, 0x69, 0x6e, 0x67, 0x31, 0x0b, 0x30, 0x09, 0x06,
-                    0x03, 0x55, 0x04, 0x06, 0x13, 0x02, 0x55, 0x53, 0x31, 0x14, 0x30, 0x12, 0x06,
-                    0x03, 0x55, 0x04, 0x07, 0x0c, 0x0b, 0x53, 0x61, 0x6e, 0x74, 0x61, 0x20, 0x43,
-                    0x6c, 0x61, 0x72, 0x61, 0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x08,
-                    0x0c, 0x02, 0x43, 0x41, 0x31, 0x1f, 0x30, 0x1d, 0x06, 0x03, 0x55, 0x04, 0x0a,
-                    0x0c, 0x16, 0x41, 0x64, 0x76, 0x61, 0x6e, 0x63, 0x65, 0x64, 0x20, 0x4d, 0x69,
-                    0x63, 0x72, 0x6f, 0x20, 0x44, 0x65, 0x76, 0x69, 0x63, 0x65, 0x73, 0x31, 0x12,
-                    0x30, 0x10, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x09, 0x53, 0x45, 0x56, 0x2d,
-                    0x4d, 0x69, 0x6c, 0x61, 0x6e, 0x30, 0x1e, 0x17, 0x0d, 0x32, 0x33, 0x30, 0x38,
-                    0x31, 0x37, 0x31, 0x34, 0x32, 0x37, 0x30, 0x39, 0x5a, 0x17, 0x0d, 0x33, 0x30,
-                    0x30, 0x38, 0x31, 0x37, 0x31, 0x34, 0x32, 0x37, 0x30, 0x39, 0x5a, 0x30, 0x7a,
-                    0x31, 0x14, 0x30, 0x12, 0x06, 0x03, 0x55, 0x04, 0x0b, 0x0c, 0x0b, 0x45, 0x6e,
-                    0x67, 0x69, 0x6e, 0x65, 0x65, 0x72, 0x69, 0x6e, 0x67, 0x31, 0x0b, 0x30, 0x09,
-                    0x06, 0x03, 0x55, 0x04, 0x06, 0x13, 0x02, 0x55, 0x53, 0x31, 0x14, 0x30, 0x12,
-                    0x06, 0x03, 0x55, 0x04, 0x07, 0x0c, 0x0b, 0x53, 0x61, 0x6e, 0x74, 0x61, 0x20,
-                    0x43, 0x6c, 0x61, 0x72, 0x61, 0x31, 0x0b, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04,
-                    0x08, 0x0c, 0x02, 0x43, 0x41, 0x31, 0x1f, 0x30, 0x1d, 0x06, 0x03, 0x55, 0x04,
-                    0x0a, 0x0c, 0x16, 0x41, 0x64, 0x76, 0x61, 0x6e, 0x63, 0x65, 0x64, 0x20, 0x4d,
-                    0x69, 0x63, 0x72, 0x6f, 0x20, 0x44, 0x65, 0x76, 0x69, 0x63, 0x65, 0x73, 0x31,
-                    0x11, 0x30, 0x0f, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x08, 0x53, 0x45, 0x56,
-                    0x2d, 0x56, 0x43, 0x45, 0x4b, 0x30, 0x76, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86,
-                    0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22, 0x03,
-                    0x62, 0x00, 0x04, 0x07, 0x79, 0x5c, 0xaa, 0x60, 0x2f, 0x16, 0x5e, 0x8d, 0x37,
-                    0x46, 0x93, 0x87, 0xc5, 0x06, 0x4a, 0x52, 0x46, 0xc9, 0x72, 0x0b, 0xdb, 0x7a,
-                    0xd2, 0x15, 0xb2, 0xc6, 0x61, 0x3c, 0x6f, 0x9b, 0x1e, 0xd4, 0x61, 0x48, 0xee,
-                    0xbd, 0xdd, 0xef, 0x56, 0xc3, 0xb6, 0x40, 0xdf, 0xd0, 0x5e, 0xbb, 0x3c, 0x0c,
-                    0x77, 0x2e, 0xea, 0x5a, 0xb0, 0xa9, 0x4b, 0x2e, 0x9a, 0x85, 0x92, 0x08, 0x55,
-                    0x7c, 0x23, 0xc3, 0x2a, 0xe1, 0xac, 0xb0, 0x2f, 0x3d, 0x59, 0x15, 0xe9, 0xbd,
-                    0x2e, 0x64, 0xb4, 0x37, 0x73, 0xb8, 0x04, 0xd5, 0xd5, 0x1b, 0x11, 0x5e, 0x60,
-                    0x1a, 0xc1, 0xf3, 0x86, 0x9d, 0x3e, 0x32, 0xe2, 0xa3, 0x82, 0x01, 0x18, 0x30,
-                    0x82, 0x01, 0x14, 0x30, 0x10, 0x06, 0x09, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c,
-                    0x78, 0x01, 0x01, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x17, 0x06, 0x09, 0x2b,
-                    0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x02, 0x04, 0x0a, 0x16, 0x08, 0x4d,
-                    0x69, 0x6c, 0x61, 0x6e, 0x2d, 0x42, 0x30, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06,
-                    0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x01, 0x04, 0x03, 0x02, 0x01, 0x00,
-                    0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03,
-                    0x02, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01,
-                    0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x04, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30,
-                    0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x05,
-                    0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04,
-                    0x01, 0x9c, 0x78, 0x01, 0x03, 0x06, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11,
-                    0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x07, 0x04,
-                    0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01,
-                    0x9c, 0x78, 0x01, 0x03, 0x03, 0x04, 0x03, 0x02, 0x01, 0x00, 0x30, 0x11, 0x06,
-                    0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c, 0x78, 0x01, 0x03, 0x08, 0x04, 0x03,
-                    0x02, 0x01, 0x1e, 0x30, 0x4f, 0x06, 0x09, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x9c,
-                    0x78, 0x01, 0x04, 0x04, 0x42, 0x04, 0x40, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
-                    0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12,
-                    0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
-                    0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c,
-                    0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39,
-                    0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f, 0x30, 0x46, 0x06, 0x09, 0x2a, 0x86, 0x48,
-                    0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0a, 0x30, 0x39, 0xa0, 0x0f, 0x30, 0x0d, 0x06,
-                    0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0xa1,
-                    0x1c, 0x30, 0x1a, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01,
-                    0x08, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
-                    0x02, 0x05, 0x00, 0xa2, 0x03, 0x02, 0x01, 0x30, 0xa3, 0x03, 0x02, 0x01, 0x01,
-                    0x03, 0x82, 0x02, 0x01, 0x00, 0x12, 0x41, 0x24, 0x4a, 0xf3, 0xf8, 0xfb, 0x0f,
-                    0x70, 0x33, 0x9a, 0x0e, 0x36, 0x9e, 0xf5, 0x89, 0xad, 0x85, 0x6b, 0xed, 0xd1,
-                    0x25, 0x2d, 0x23, 0x89, 0x16, 0x80, 0xcb, 0xee, 0xbd, 0x70, 0x97, 0x92, 0x24,
-                    0x76, 0x0b, 0xf9, 0x15, 0x9e, 0x8e, 0x4c, 0xb4, 0x9d, 0x61, 0x9d, 0x3d, 0xfe,
-                    0x3a, 0xf3, 0x36, 0xb4, 0xc8, 0xb7, 0x56, 0xad, 0x1a, 0x1f, 0x35, 0xf5, 0x36,
-                    0xf9, 0xb5, 0xed, 0x8f, 0x95, 0x0d, 0x37, 0x0f, 0xa8, 0x89, 0xba, 0x1c, 0x96,
-                    0x91, 0x97, 0x62, 0x4f, 0xc7, 0x93, 0x87, 0x6d, 0x23, 0xdc, 0xc0, 0xbb, 0xcd,
-                    0x17, 0x38, 0xae, 0xbd, 0x0d, 0xc4, 0xcc, 0xa4, 0x3f, 0xc8, 0x7d, 0x0d, 0x0b,
-                    0x5c, 0xf1, 0xba, 0x9b, 0x20, 0x29, 0x95, 0xb0, 0x96, 0x02, 0x4d, 0x9d, 0xcd,
-                    0x82, 0x0a, 0x60, 0x92, 0x51, 0xa1, 0x3c, 0x69, 0xec, 0x27, 0x81, 0x8e, 0x28,
-                    0xc7, 0x4e, 0x34, 0xbb, 0x9f, 0xb0, 0x49, 0xc7, 0x6e, 0xe6, 0xb7, 0x6b, 0x1f,
-                    0x91, 0x20, 0x0a, 0x80, 0xd2, 0x9f, 0x67, 0x24, 0xe0, 0x75, 0x40, 0x9b, 0x4a,
-                    0xdd, 0xeb, 0xab, 0x34, 0x5f, 0x59, 0x3d, 0x3b, 0x06, 0xf0, 0x4d, 0x7d, 0xf9,
-                    0x26, 0xeb, 0x35, 0xcb, 0x08, 0x35, 0x7b, 0xbf, 0x02, 0x4e, 0xa5, 0x50, 0xf8,
-                    0x91, 0xf3, 0x60, 0xed, 0x80, 0x0d, 0xe1, 0x7e, 0x2b, 0x86, 0x75, 0x3d, 0x0c,
-                    0x83, 0xea, 0x64, 0x50, 0x6c, 0xbd, 0xe2, 0x17, 0x6e, 0x45, 0xaa, 0x10, 0xe8,
-                    0x84, 0xcc, 0xa1, 0x06, 0xb6, 0x8b, 0xa5, 0x96, 0xb0, 0x83, 0xba, 0x61, 0xe6,
-                    0xa4, 0x14, 0xd3, 0x26, 0xf3, 0x19, 0x31, 0xbe, 0x40, 0x2a, 0x18, 0x53, 0x58,
-                    0x75, 0x1d, 0x46, 0xe2, 0xfe, 0x47, 0xa3, 0xa9, 0x39, 0x68, 0xee, 0x37, 0x8f,
-                    0x57, 0xe6, 0x12, 0x92, 0x34, 0xa6, 0x0a, 0x51, 0xcb, 0x4c, 0xce, 0x54, 0xe2,
-                    0xbe, 0x8b, 0x8c, 0x02, 0xe5, 0x3c, 0x3a, 0x7b, 0x7f, 0x7b, 0x3b, 0x80, 0x44,
-                    0x98, 0x9c, 0x52, 0x1d, 0x29, 0x42, 0xce, 0x9f, 0x95, 0xc5, 0x79, 0xbe, 0xd8,
-                    0x06, 0x71, 0xff, 0xa2, 0x0a, 0xe2, 0x21, 0xa9, 0x59, 0xda, 0xac, 0x05, 0xe8,
-                    0x2e, 0xa5, 0x1f, 0x01, 0xaf, 0xae, 0xc6, 0x90, 0xbb, 0x5d, 0x7b, 0xa9, 0x84,
-                    0xff, 0x1c, 0x11, 0x78, 0x07, 0x89, 0x0a, 0x09, 0x4f, 0xc8, 0x4c, 0xb1, 0x7e,
-                    0x68, 0x12, 0xa6, 0x3d, 0xae, 0x6b, 0x69, 0x8d, 0xc9, 0x03, 0x5f, 0x4d, 0x45,
-                    0x47, 0xde, 0xf0, 0xa5, 0x1a, 0x19, 0x97, 0x37, 0x0e, 0xe8, 0x8a, 0xd2, 0x30,
-                    0x07, 0xbf, 0xb4, 0x09, 0x80, 0x93, 0xa4, 0x91, 0x28, 0x40, 0xe3, 0x2c, 0xf3,
-                    0x46, 0xf0, 0x22, 0xb3, 0xb7, 0xc5, 0x92, 0x69, 0x7a, 0x4d, 0xdb, 0xf7, 0x67,
-                    0x97, 0x6f, 0x83, 0xcf, 0x5d, 0x29, 0x8b, 0x55, 0x72, 0xd3, 0xa2, 0xcb, 0x65,
-                    0x21, 0x76, 0x84, 0xed, 0x75, 0xd5, 0xf3, 0x74, 0xff, 0xc1, 0x1a, 0x8d, 0x65,
-                    0xac, 0x4f, 0xb0, 0x8c, 0x87, 0xae, 0x6a, 0xf0, 0xf9, 0x56, 0x23, 0xfc, 0x29,
-                    0x5a, 0x1c, 0xd4, 0x12, 0xf9, 0x79, 0x66, 0x97, 0xad, 0x95, 0xc1, 0xa9, 0x0e,
-                    0xf3, 0x2b, 0x94, 0x17, 0xc3, 0xfd, 0x51, 0x1f, 0x94, 0x35, 0xad, 0xa7, 0xf9,
-                    0x61, 0x57, 0xf3, 0x67, 0x53, 0x17, 0xc7, 0xee, 0x1f, 0x54, 0x11, 0x1a, 0xd4,
-                    0xc9, 0x33, 0x4b, 0x3a, 0x71, 0x27, 0xd7, 0xbb, 0x9f, 0x96, 0xba, 0xfa, 0x8a,
-                    0x9c, 0x1e, 0x80, 0x6e, 0xfa, 0xa5, 0xd6, 0xba, 0xd7, 0x92, 0x71, 0xe9, 0x4e,
-                    0x82, 0xa9, 0x02, 0x2a, 0x3b, 0xb8, 0x4e, 0x01, 0x53, 0x34, 0xa6, 0x70, 0x61,
-                    0x56, 0x95, 0x1b, 0x59, 0xfe, 0x46, 0x94, 0x84, 0x8c, 0xa2, 0x2a, 0x16, 0x0c,
-                    0xc2, 0x59, 0x9e, 0xac, 0xca, 0xa9, 0x93, 0xe6, 0x84, 0xf4,
-                ],
-                [
-                    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
-                    0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19,
-                    0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26,
-                    0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33,
-                    0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
-                ],
-            )
+    // Read one report and verify it against its VEK, returning its claims when the report and
+    // VEK could be loaded.
+    fn verify_one(
+        args: &attestation::Args,
+        veks: &mut Veks,
+    ) -> (Option<claims::Claims>, Result<()>) {
+        let loaded = attestation::read_args_report(args).and_then(|(att_report, _)| {
+            let vek = veks.load(args, &att_report)?;
+            Ok((att_report, vek))
+        });
+
+        verify_loaded(args, loaded)
+    }
+
+    fn verify_loaded(
+        args: &attestation::Args,
+        loaded: Result<(AttestationReport, Certificate)>,
+    ) -> (Option<claims::Claims>, Result<()>) {
+        let (att_report, vek) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => return (None, Err(err)),
+        };
+        match report_model(args, &att_report) {
+            Ok(proc_model) => {
+                let (claims, result) =
+                    attestation::report_claims(args, att_report, vek, &proc_model);
+                (Some(claims), result)
+            }
+            Err(err) => (None, Err(err)),
+        }
+    }
+
+    // Claims of a verified report, along with the error when it failed.
+    fn result_entry(claims: Option<&claims::Claims>, result: &Result<()>) -> serde_json::Value {
+        let mut entry = match claims {
+            Some(claims) => claims.to_json(),
+            None => serde_json::json!({ "verified": false }),
+        };
+        if let Err(err) = result {
+            entry["error"] = format!("{err:#}").into();
+        }
+        entry
+    }
+
+    pub fn verify_batch(args: Args, quiet: bool) -> Result<()> {
+        if args.output == VerifyOutput::Eat {
+            return Err(anyhow::anyhow!(
+                "EAT output is only available for a single report. Use json instead."
+            ));
         }
+        let kds = args.source.eq_ignore_ascii_case(KDS_SOURCE);
+        let paths = report_paths(&args.reports)?;
+        attestation::warn_skipped(args.tcb, args.signature);
+
+        let mut report_args = report_args(
+            (!kds).then(|| PathBuf::from(&args.source)),
+            args.output,
+            args.processor_model,
+            args.tcb,
+            args.signature,
+            args.kds,
+            args.cache,
+        );
+
+        let mut veks = Veks::default();
+        let mut results = Vec::new();
+        let mut failed = 0;
+        for path in &paths {
+            report_args.att_report_path = path.clone();
+            let (claims, result) = verify_one(&report_args, &mut veks);
+            if result.is_err() {
+                failed += 1;
+            }
+
+            match args.output {
+                VerifyOutput::Json | VerifyOutput::Yaml => {
+                    let mut entry = result_entry(claims.as_ref(), &result);
+                    entry["report"] = path.display().to_string().into();
+                    results.push(entry);
+                }
+                _ if quiet => (),
+                _ => match &result {
+                    Ok(()) => check_passed(&format!("PASS {}", path.display())),
+                    Err(err) => check_failed(&format!("FAIL {}: {err:#}", path.display())),
+                },
+            }
+        }
+
+        if !quiet {
+            match args.output {
+                VerifyOutput::Json => println!("{}", json::to_json(&results)?),
+                VerifyOutput::Yaml => print!("{}", serde_yaml::to_string(&results)?),
+                _ => println!("{} passed, {failed} failed", paths.len() - failed),
+            }
+        }
+
+        if failed > 0 {
+            return Err(anyhow::anyhow!(
+                "{failed} of {} attestation reports failed verification.",
+                paths.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Read the next frame of a stream: a 4 byte big endian length, followed by that many bytes
+    // of report. None at the end of the stream, and the inner error for a truncated frame or
+    // one that isn't report sized, whose body is skipped without being kept.
+    fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Result<Vec<u8>>>> {
+        let mut prefix = Vec::new();
+        reader
+            .take(FRAME_PREFIX_SIZE as u64)
+            .read_to_end(&mut prefix)
+            .context("Could not read the report stream.")?;
+        if prefix.is_empty() {
+            return Ok(None);
+        }
+        let Ok(prefix) = <[u8; FRAME_PREFIX_SIZE]>::try_from(prefix.as_slice()) else {
+            return Ok(Some(Err(anyhow::anyhow!(
+                "The stream ends within the length prefix of a frame."
+            ))));
+        };
+
+        let len = u32::from_be_bytes(prefix) as usize;
+        if len != report::REPORT_SIZE {
+            let skipped = std::io::copy(&mut reader.take(len as u64), &mut std::io::sink())
+                .context("Could not read the report stream.")?;
+            return Ok(Some(Err(if (skipped as usize) < len {
+                anyhow::anyhow!("The stream ends {skipped} bytes into a frame of {len} bytes.")
+            } else {
+                anyhow::anyhow!(
+                    "A frame holds {len} bytes, but an attestation report is {} bytes long.",
+                    report::REPORT_SIZE
+                )
+            })));
+        }
+        let mut frame = Vec::new();
+        reader
+            .take(len as u64)
+            .read_to_end(&mut frame)
+            .context("Could not read the report stream.")?;
+        if frame.len() < len {
+            return Ok(Some(Err(anyhow::anyhow!(
+                "The stream ends {} bytes into a frame of {len} bytes.",
+                frame.len()
+            ))));
+        }
+
+        Ok(Some(Ok(frame)))
+    }
+
+    // Verify the report of a frame, against the --vek or else the VEK loaded for its signing
+    // key, chip ID and reported TCB, which is kept for the next frames.
+    fn verify_frame(
+        args: &attestation::Args,
+        vek: Option<&Certificate>,
+        veks: &mut Veks,
+        frame: &[u8],
+    ) -> (Option<claims::Claims>, Result<()>) {
+        let loaded = report::parse_report(frame).and_then(|att_report| {
+            let vek = match vek {
+                Some(vek) => vek.clone(),
+                None => veks.load(args, &att_report)?,
+            };
+            Ok((att_report, vek))
+        });
+
+        verify_loaded(args, loaded)
+    }
+
+    // Verify every frame of the stream, writing one JSON line per frame, and return the number
+    // of frames and of failed ones.
+    fn verify_frames<R: Read, W: Write>(
+        args: StreamArgs,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(usize, usize)> {
+        let vek = args
+            .vek
+            .as_ref()
+            .map(|vek_path| convert_path_to_cert(vek_path, "vek"))
+            .transpose()?;
+        let certs_dir = args
+            .source
+            .filter(|source| !source.eq_ignore_ascii_case(KDS_SOURCE))
+            .map(PathBuf::from);
+        let report_args = report_args(
+            certs_dir,
+            VerifyOutput::Json,
+            args.processor_model,
+            args.tcb,
+            args.signature,
+            args.kds,
+            args.cache,
+        );
+
+        let mut veks = Veks::default();
+        let (mut frames, mut failed) = (0, 0);
+        while let Some(frame) = read_frame(reader)? {
+            frames += 1;
+            let (claims, result) = match frame {
+                Ok(frame) => verify_frame(&report_args, vek.as_ref(), &mut veks, &frame),
+                Err(err) => (None, Err(err)),
+            };
+            if result.is_err() {
+                failed += 1;
+            }
+
+            let mut entry = result_entry(claims.as_ref(), &result);
+            entry["frame"] = frames.into();
+            writeln!(writer, "{entry}").context("Could not write the verification result.")?;
+            writer
+                .flush()
+                .context("Could not write the verification result.")?;
+        }
+
+        Ok((frames, failed))
+    }
+
+    pub fn verify_stream(args: StreamArgs, quiet: bool) -> Result<()> {
+        attestation::warn_skipped(args.tcb, args.signature);
+        let (frames, failed) = if quiet {
+            verify_frames(args, &mut std::io::stdin().lock(), &mut std::io::sink())?
+        } else {
+            verify_frames(
+                args,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stdout().lock(),
+            )?
+        };
+
+        if failed > 0 {
+            return Err(anyhow::anyhow!(
+                "{failed} of {frames} attestation reports failed verification."
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
 
         #[test]
-        fn test_check_cert_bytes_legacy() {
-            let (legacy_cert_bytes, val) = cert_and_hw_id_legacy();
+        fn test_verify_frames() {
+            let dir = std::env::temp_dir().join(format!("snpguest-stream-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
 
-            let dummy_x509: X509Certificate =
-                X509Certificate::from_der(&legacy_cert_bytes).unwrap().1;
-            let extensions = dummy_x509.extensions_map().unwrap();
+            // A frame too short to be a report, a report without a VEK and a truncated frame
+            let report = bincode::serialize(&AttestationReport::default()).unwrap();
+            let mut stream = Vec::new();
+            for frame in [&b"short"[..], &report] {
+                stream.extend((frame.len() as u32).to_be_bytes());
+                stream.extend(frame);
+            }
+            stream.extend(100u32.to_be_bytes());
+            stream.extend(b"cut");
 
-            let ext = extensions.get(&SnpOid::HwId.oid()).unwrap();
+            let args = StreamArgs::parse_from(["stream", dir.to_str().unwrap()]);
+            let mut out = Vec::new();
+            assert_eq!(
+                verify_frames(args, &mut stream.as_slice(), &mut out).unwrap(),
+                (3, 3)
+            );
 
-            assert!(check_cert_bytes(ext, &val));
+            let lines: Vec<serde_json::Value> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(lines.len(), 3);
+            for (frame, line) in lines.iter().enumerate() {
+                assert_eq!(line["frame"], frame + 1);
+                assert_eq!(line["verified"], false);
+            }
+            assert_eq!(
+                lines[0]["error"],
+                "A frame holds 5 bytes, but an attestation report is 1184 bytes long."
+            );
+            assert_eq!(
+                lines[2]["error"],
+                "The stream ends 3 bytes into a frame of 100 bytes."
+            );
+
+            fs::remove_dir_all(&dir).unwrap();
         }
 
         #[test]
-        fn test_check_cert_bytes() {
-            let (cert_bytes, val) = cert_and_hw_id();
+        fn test_verify_frames_valid() {
+            let dir =
+                std::env::temp_dir().join(format!("snpguest-stream-valid-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("vcek.der"), attestation::cert_and_hw_id().0).unwrap();
 
-            let dummy_x509: X509Certificate = X509Certificate::from_der(&cert_bytes).unwrap().1;
-            let extensions = dummy_x509.extensions_map().unwrap();
+            // The same report twice, the second one verified against the VEK kept in memory
+            let report = bincode::serialize(&attestation::dummy_vcek_report()).unwrap();
+            let mut stream = Vec::new();
+            for _ in 0..2 {
+                stream.extend((report.len() as u32).to_be_bytes());
+                stream.extend(&report);
+            }
 
-            let ext = extensions.get(&SnpOid::HwId.oid()).unwrap();
+            // The report doesn't identify its processor model, which is never assumed
+            let args = StreamArgs::parse_from(["stream", dir.to_str().unwrap(), "--tcb"]);
+            let mut out = Vec::new();
+            assert_eq!(
+                verify_frames(args, &mut stream.as_slice(), &mut out).unwrap(),
+                (2, 2)
+            );
+            let line: serde_json::Value =
+                serde_json::from_str(String::from_utf8(out).unwrap().lines().next().unwrap())
+                    .unwrap();
+            let err = line["error"].as_str().unwrap();
+            assert!(err.starts_with("Provide the processor model"), "{err}");
 
-            assert!(check_cert_bytes(ext, val.as_slice()));
+            let args =
+                StreamArgs::parse_from(["stream", dir.to_str().unwrap(), "--tcb", "-p", "milan"]);
+            let mut out = Vec::new();
+            assert_eq!(
+                verify_frames(args, &mut stream.as_slice(), &mut out).unwrap(),
+                (2, 0)
+            );
+            for line in String::from_utf8(out).unwrap().lines() {
+                let line: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(line["verified"], true, "{line}");
+            }
+
+            fs::remove_dir_all(&dir).unwrap();
         }
 
         #[test]
-        fn test_check_cert_bytes_integer() {
-            let (cert_bytes, _) = cert_and_hw_id();
-            let val = 0x1Eu8;
-            let dummy_x509: X509Certificate = X509Certificate::from_der(&cert_bytes).unwrap().1;
-            let extensions = dummy_x509.extensions_map().unwrap();
-            let ext = extensions.get(&SnpOid::Ucode.oid()).unwrap();
-            assert!(check_cert_bytes(ext, &val.to_ne_bytes()));
+        fn test_report_paths() {
+            let dir = std::env::temp_dir()
+                .join(format!("snpguest-attestation-batch-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("nested.bin")).unwrap();
+            fs::write(dir.join("b.bin"), b"").unwrap();
+            fs::write(dir.join("a.bin"), b"").unwrap();
+            fs::write(dir.join("notes.txt"), b"").unwrap();
+
+            let pattern = format!("{}/*.bin", dir.display());
+            assert_eq!(
+                report_paths(&pattern).unwrap(),
+                vec![dir.join("a.bin"), dir.join("b.bin")]
+            );
+            let pattern = format!("{}/*.json", dir.display());
+            assert!(report_paths(&pattern).is_err());
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn test_verify_batch_failures() {
+            let dir =
+                std::env::temp_dir().join(format!("snpguest-batch-fail-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            // Neither report can be read, and there is no VEK to verify them with
+            fs::write(dir.join("a.bin"), b"short").unwrap();
+            fs::write(dir.join("b.bin"), b"short").unwrap();
+
+            let args = Args::parse_from([
+                "batch",
+                dir.to_str().unwrap(),
+                &format!("{}/*.bin", dir.display()),
+            ]);
+            let err = verify_batch(args, true).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "2 of 2 attestation reports failed verification."
+            );
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Create an empty scratch directory containing the provided (empty) files.
+    fn scratch_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snpguest-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::File::create(dir.join(file)).unwrap();
+        }
+        dir
+    }
+
+    // Build a throwaway self-signed P-384 certificate with the given subject common name.
+    fn dummy_cert(common_name: &str) -> X509 {
+        dummy_cert_signed(common_name, None).0
+    }
+
+    // Build a throwaway P-384 certificate signed with the given key, or self-signed, along
+    // with its own key.
+    fn dummy_cert_signed(
+        common_name: &str,
+        signer: Option<(&X509, &openssl::pkey::PKey<openssl::pkey::Private>)>,
+    ) -> (X509, openssl::pkey::PKey<openssl::pkey::Private>) {
+        use openssl::{
+            ec::{EcGroup, EcKey},
+            hash::MessageDigest,
+            pkey::PKey,
+            x509::X509NameBuilder,
+        };
+
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder
+            .set_issuer_name(signer.map_or(&name, |(issuer, _)| issuer.subject_name()))
+            .unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder
+            .sign(
+                signer.map_or(&key, |(_, signer)| signer),
+                MessageDigest::sha384(),
+            )
+            .unwrap();
+        (builder.build(), key)
+    }
+
+    // Write the concatenated PEM encoding of the certificates into a scratch file.
+    fn chain_file(name: &str, certs: &[&[u8]]) -> PathBuf {
+        let dir = scratch_dir(name, &[]);
+        let path = dir.join("chain.pem");
+        fs::write(&path, certs.concat()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_common_name() {
+        let common_name = |cn: &str| {
+            let der = dummy_cert(cn).to_der().unwrap();
+            let (_, x509) = X509Certificate::from_der(&der).unwrap();
+            parse_common_name(x509.subject()).ok()
+        };
+
+        for model in ["Milan", "Genoa", "Turin"] {
+            assert_eq!(common_name(&format!("ARK-{model}")), Some(CertType::ARK));
+            assert_eq!(common_name(&format!("SEV-{model}")), Some(CertType::ASK));
+            assert_eq!(
+                common_name(&format!("SEV-VLEK-{model}")),
+                Some(CertType::ASK)
+            );
+        }
+        assert_eq!(common_name("SEV-VCEK"), Some(CertType::VCEK));
+        assert_eq!(common_name("SEV-VLEK"), Some(CertType::VLEK));
+
+        assert_eq!(common_name("SEV-Unknown"), None);
+        assert_eq!(common_name("ARK-VCEK"), None);
+        assert_eq!(common_name("my-ask"), None);
+    }
+
+    #[test]
+    fn test_chain_from_file() {
+        use sev::certs::snp::builtin::milan::{ARK, ASK};
+
+        let vcek = dummy_cert("SEV-VCEK").to_pem().unwrap();
+
+        // Certificates may appear in any order.
+        let path = chain_file("chain-ok", &[&vcek, ASK, ARK]);
+        let (chain, vek_type) = chain_from_file(&path).unwrap();
+        assert_eq!(vek_type, CertType::VCEK);
+        assert_eq!(
+            chain.ca.ark.to_der().unwrap(),
+            Certificate::from_pem(ARK).unwrap().to_der().unwrap()
+        );
+        assert!(verify_cert_chain(&chain).unwrap().ask_signed_by_ark);
+
+        let path = chain_file("chain-missing", &[ASK, ARK]);
+        assert!(chain_from_file(&path).is_err());
+
+        let path = chain_file("chain-duplicate", &[&vcek, ASK, ARK, ARK]);
+        assert!(chain_from_file(&path).is_err());
+
+        for name in ["chain-ok", "chain-missing", "chain-duplicate"] {
+            fs::remove_dir_all(scratch_dir(name, &[])).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_chain_from_ca_file() {
+        use sev::certs::snp::builtin::milan::{ARK, ASK};
+
+        let vcek = Certificate::from(dummy_cert("SEV-VCEK"));
+
+        // The KDS serves the ASK before the ARK
+        let path = chain_file("ca-chain-ok", &[ASK, ARK]);
+        let (chain, vek_type) = chain_from_ca_file(&path, vcek.clone()).unwrap();
+        assert_eq!(vek_type, CertType::VCEK);
+        assert_eq!(
+            chain.ca.ask.to_der().unwrap(),
+            Certificate::from_pem(ASK).unwrap().to_der().unwrap()
+        );
+
+        let path = chain_file("ca-chain-vek", &[&vcek.to_pem().unwrap(), ASK, ARK]);
+        assert!(chain_from_ca_file(&path, vcek.clone()).is_err());
+
+        let path = chain_file("ca-chain-missing", &[ARK]);
+        assert_eq!(
+            chain_from_ca_file(&path, vcek).err().unwrap().to_string(),
+            "CA chain file is missing the ASK."
+        );
+
+        for name in ["ca-chain-ok", "ca-chain-vek", "ca-chain-missing"] {
+            fs::remove_dir_all(scratch_dir(name, &[])).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_find_cert_in_dir_priority() {
+        let dir = scratch_dir("find-priority", &["ark.cert", "ark.der", "ark.crt"]);
+        assert_eq!(find_cert_in_dir(&dir, "ark").unwrap(), dir.join("ark.der"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_cert_in_dir_case_insensitive() {
+        let dir = scratch_dir("find-case", &["ARK.PEM", "VCEK.crt"]);
+        assert_eq!(find_cert_in_dir(&dir, "ark").unwrap(), dir.join("ARK.PEM"));
+        assert_eq!(
+            find_cert_in_dir(&dir, "vcek").unwrap(),
+            dir.join("VCEK.crt")
+        );
+        assert!(find_cert_in_dir(&dir, "ask").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_vlek_signer() {
+        let dir = scratch_dir("vlek-signer", &[]);
+        let (asvk, asvk_key) = dummy_cert_signed("SEV-VLEK-Genoa", None);
+        let (vlek, _) = dummy_cert_signed("SEV-VLEK", Some((&asvk, &asvk_key)));
+        fs::write(dir.join("vlek.pem"), vlek.to_pem().unwrap()).unwrap();
+        let att_report = sev::firmware::guest::AttestationReport::default();
+        let find = || attestation::find_vek_in_dir(&dir, Some(&Endorsement::Vlek), &att_report);
+
+        let err = find().unwrap_err();
+        assert!(err.to_string().contains("no asvk certificate"));
+
+        fs::write(
+            dir.join("asvk.pem"),
+            dummy_cert("SEV-VLEK-Genoa").to_pem().unwrap(),
+        )
+        .unwrap();
+        let err = find().unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("The VLEK was NOT signed by the ASVK"));
+
+        fs::write(dir.join("asvk.pem"), asvk.to_pem().unwrap()).unwrap();
+        assert!(find().is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_single_stdin() {
+        let stdin = Some(Path::new("-"));
+        assert!(check_single_stdin(&[stdin, Some(Path::new("vek.pem")), None]).is_ok());
+        assert!(check_single_stdin(&[stdin, None, stdin]).is_err());
+    }
+
+    #[test]
+    fn test_check_strict_dir() {
+        let dir = scratch_dir("strict-dir", &[]);
+        fs::write(
+            dir.join("ark.pem"),
+            dummy_cert("ARK-Milan").to_pem().unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("vcek.der"),
+            dummy_cert("SEV-VCEK").to_der().unwrap(),
+        )
+        .unwrap();
+        assert!(check_strict_dir(&dir).is_ok());
+
+        fs::write(
+            dir.join("host-b.pem"),
+            dummy_cert("SEV-VCEK").to_pem().unwrap(),
+        )
+        .unwrap();
+        let err = check_strict_dir(&dir).unwrap_err();
+        assert!(err.to_string().ends_with("are both VCEK certificates."));
+        fs::remove_file(dir.join("host-b.pem")).unwrap();
+
+        fs::write(dir.join("vcek.crl"), b"not a certificate").unwrap();
+        let err = check_strict_dir(&dir).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("vcek.crl is not an expected certificate"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        let at = |secs| utc_timestamp(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_827_696), "2000-02-29T12:34:56Z");
+        assert_eq!(at(1_735_689_599), "2024-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_find_crl_in_dir() {
+        let dir = scratch_dir("find-crl", &["ark.pem", "vcek.crl"]);
+        assert_eq!(find_crl_in_dir(&dir), None);
+
+        fs::write(dir.join("vcek-crl.der"), b"not a crl").unwrap();
+        let crl_path = find_crl_in_dir(&dir).unwrap();
+        assert_eq!(crl_path, dir.join("vcek-crl.der"));
+        let err = read_crl(&crl_path).err().unwrap();
+        assert!(err.to_string().starts_with("Could not parse CRL"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // Build a DER CRL issued by the subject of the certificate and signed with the key, revoking
+    // the given certificates.
+    fn dummy_crl(
+        issuer: &X509,
+        key: &openssl::pkey::PKey<openssl::pkey::Private>,
+        next_update: &str,
+        revoked: &[&X509],
+    ) -> Vec<u8> {
+        use openssl::{hash::MessageDigest, sign::Signer};
+
+        fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+            let len = content.len().to_be_bytes();
+            let len = &len[len
+                .iter()
+                .position(|byte| *byte != 0)
+                .unwrap_or(len.len() - 1)..];
+            let mut der = vec![tag];
+            match len {
+                [short] if *short < 0x80 => der.push(*short),
+                _ => {
+                    der.push(0x80 | len.len() as u8);
+                    der.extend_from_slice(len);
+                }
+            }
+            der.extend_from_slice(content);
+            der
         }
+        let time = |time: &str| tlv(0x17, time.as_bytes());
+        // ecdsa-with-SHA384
+        let algorithm = tlv(
+            0x30,
+            &tlv(0x06, &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x03]),
+        );
+
+        let entries: Vec<u8> = revoked
+            .iter()
+            .flat_map(|cert| {
+                let mut serial = cert.serial_number().to_bn().unwrap().to_vec();
+                if serial.first().map_or(true, |byte| byte & 0x80 != 0) {
+                    serial.insert(0, 0);
+                }
+                tlv(0x30, &[tlv(0x02, &serial), time("250101000000Z")].concat())
+            })
+            .collect();
+        let mut tbs = [
+            tlv(0x02, &[1]),
+            algorithm.clone(),
+            issuer.subject_name().to_der().unwrap(),
+            time("250101000000Z"),
+            time(next_update),
+        ]
+        .concat();
+        if !entries.is_empty() {
+            tbs.extend(tlv(0x30, &entries));
+        }
+        let tbs = tlv(0x30, &tbs);
+
+        let mut signer = Signer::new(MessageDigest::sha384(), key).unwrap();
+        signer.update(&tbs).unwrap();
+        let signature = [vec![0], signer.sign_to_vec().unwrap()].concat();
+        tlv(0x30, &[tbs, algorithm, tlv(0x03, &signature)].concat())
+    }
+
+    #[test]
+    fn test_check_crl() {
+        use certificate_chain::{check_crl, crl_stale};
+
+        let (ark, ark_key) = dummy_cert_signed("ARK-Milan", None);
+        let (ask, _) = dummy_cert_signed("SEV-Milan", Some((&ark, &ark_key)));
+        let (other, other_key) = dummy_cert_signed("ARK-Genoa", None);
+        let cert = |x509: &X509| Certificate::from_der(&x509.to_der().unwrap()).unwrap();
+        let cert_chain = Chain {
+            ca: ca::Chain {
+                ark: cert(&ark),
+                ask: cert(&ask),
+            },
+            vek: cert(&ask),
+        };
+
+        let dir = scratch_dir("check-crl", &[]);
+        let crl_path = dir.join("crl.der");
+        let check = |crl: Vec<u8>| {
+            fs::write(&crl_path, crl).unwrap();
+            check_crl(&crl_path, &cert_chain, "ask", true).map_err(|err| err.to_string())
+        };
+
+        assert!(check(dummy_crl(&ark, &ark_key, "491231235959Z", &[])).is_ok());
+        let err = check(dummy_crl(&other, &ark_key, "491231235959Z", &[])).unwrap_err();
+        assert!(err.contains("was not issued by the AMD ARK"));
+        let err = check(dummy_crl(&ark, &other_key, "491231235959Z", &[])).unwrap_err();
+        assert!(err.contains("was not signed by the AMD ARK"));
+        let err = check(dummy_crl(&ark, &ark_key, "491231235959Z", &[&ask])).unwrap_err();
+        assert_eq!(err, "The AMD ASK was revoked by the CRL!");
+
+        // A CRL past its next update is only warned about
+        assert!(!crl_stale(&read_crl(&crl_path).unwrap()).unwrap());
+        let stale = dummy_crl(&ark, &ark_key, "250102000000Z", &[]);
+        assert!(check(stale).is_ok());
+        assert!(crl_stale(&read_crl(&crl_path).unwrap()).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_cert_in_missing_dir() {
+        let dir = scratch_dir("find-missing", &["ark.pem"]);
+        let err = find_cert_in_dir(&dir, "ask").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("ask certificate not found in directory"));
+
+        let err = find_cert_in_dir(&dir.join("ark.pem"), "ark").unwrap_err();
+        assert!(err.to_string().ends_with("is not a directory"));
+
+        fs::remove_dir_all(&dir).unwrap();
+        let err = find_cert_in_dir(&dir, "ark").unwrap_err();
+        assert!(err.to_string().ends_with("does not exist"));
+    }
+
+    #[test]
+    fn test_embedded_vek() {
+        let vcek = dummy_cert("SEV-VCEK").to_der().unwrap();
+        let vlek = dummy_cert("SEV-VLEK").to_pem().unwrap();
+        let certs = vec![
+            CertTableEntry::new(CertType::VCEK, vcek.clone()),
+            CertTableEntry::new(CertType::VLEK, vlek),
+        ];
+        let vek_type = |vek: Option<Certificate>| cert_type(&vek.unwrap()).unwrap();
+
+        assert_eq!(
+            vek_type(embedded_vek(&certs, Some(&Endorsement::Vcek)).unwrap()),
+            CertType::VCEK
+        );
+        assert_eq!(
+            vek_type(embedded_vek(&certs, None).unwrap()),
+            CertType::VLEK
+        );
+
+        let certs = vec![CertTableEntry::new(CertType::VCEK, vcek)];
+        assert!(embedded_vek(&certs, Some(&Endorsement::Vlek))
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            vek_type(embedded_vek(&certs, None).unwrap()),
+            CertType::VCEK
+        );
+
+        let certs = vec![CertTableEntry::new(CertType::VCEK, b"garbage".to_vec())];
+        assert!(embedded_vek(&certs, None).is_err());
+    }
+
+    #[test]
+    fn test_check_ca_constraints() {
+        use openssl::{
+            ec::{EcGroup, EcKey},
+            hash::MessageDigest,
+            pkey::{PKey, Private},
+            x509::{extension::BasicConstraints, X509NameBuilder},
+        };
+        use sev::certs::snp::builtin::milan;
+
+        let ark = Certificate::from_pem(milan::ARK).unwrap();
+        let ask = Certificate::from_pem(milan::ASK).unwrap();
+        assert!(check_ca_constraints(&ark, "ARK", 1).is_ok());
+        assert!(check_ca_constraints(&ask, "ASK", 0).is_ok());
+        // The ASK has a path length of 0
+        let err = check_ca_constraints(&ask, "ASK", 1).unwrap_err();
+        assert!(err.to_string().contains("path length constraint of 0"));
+
+        // An ASK lacking CA:TRUE, yet properly signed by the ARK and signing the VEK
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let keys: Vec<PKey<Private>> = (0..3)
+            .map(|_| PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap())
+            .collect();
+        let issue = |common_name: &str, key: usize, issuer: usize, ca: bool| {
+            let mut name = X509NameBuilder::new().unwrap();
+            name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+                .unwrap();
+            let name = name.build();
+
+            let mut builder = X509::builder().unwrap();
+            builder.set_version(2).unwrap();
+            builder.set_subject_name(&name).unwrap();
+            builder.set_pubkey(&keys[key]).unwrap();
+            builder
+                .set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap())
+                .unwrap();
+            builder
+                .set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap())
+                .unwrap();
+            let mut constraints = BasicConstraints::new();
+            if ca {
+                constraints.ca();
+            }
+            builder
+                .append_extension(constraints.critical().build().unwrap())
+                .unwrap();
+            builder
+                .sign(&keys[issuer], MessageDigest::sha384())
+                .unwrap();
+            Certificate::from_der(&builder.build().to_der().unwrap()).unwrap()
+        };
+
+        let chain = Chain {
+            ca: sev::certs::snp::ca::Chain {
+                ark: issue("ARK-Milan", 0, 0, true),
+                ask: issue("SEV-Milan", 1, 0, false),
+            },
+            vek: issue("SEV-VCEK", 2, 1, false),
+        };
+        let result = verify_cert_chain(&chain).unwrap();
+        assert!(result.ask_signed_by_ark && result.vek_signed_by_ask);
+        let err = certificate_chain::check_chain(&chain, "vcek", "ask", None, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The AMD ASK is not a CA certificate (CA:FALSE)."
+        );
+
+        // A chain that links, but is signed with ECDSA instead of RSASSA-PSS
+        let chain = Chain {
+            ca: sev::certs::snp::ca::Chain {
+                ark: issue("ARK-Milan", 0, 0, true),
+                ask: issue("SEV-Milan", 1, 0, true),
+            },
+            vek: issue("SEV-VCEK", 2, 1, false),
+        };
+        let err = certificate_chain::check_chain(&chain, "vcek", "ask", None, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The AMD ARK is signed with 1.2.840.10045.4.3.3, but AMD signs it with RSASSA-PSS (1.2.840.113549.1.1.10)."
+        );
+    }
+
+    #[test]
+    fn test_check_algorithms() {
+        use sev::certs::snp::builtin::milan;
+
+        let ark = Certificate::from_pem(milan::ARK).unwrap();
+        assert!(check_algorithms(&ark, "ARK", false).is_ok());
+        // The ARK holds an RSA key, not the P-384 key of a VEK
+        let err = check_algorithms(&ark, "VCEK", true).unwrap_err();
+        assert!(err.to_string().contains("ECDSA P-384 key"));
+
+        let err =
+            check_algorithms(&Certificate::from(dummy_cert("SEV-VCEK")), "VCEK", true).unwrap_err();
+        assert!(err.to_string().contains("but AMD signs it with RSASSA-PSS"));
+    }
+
+    #[test]
+    fn test_chain_from_dir_roles() {
+        let write = |dir: &Path, name: &str, common_name: &str| {
+            let pem = dummy_cert(common_name).to_pem().unwrap();
+            fs::write(dir.join(format!("{name}.pem")), pem).unwrap();
+        };
+        let load = |dir: &Path, vek: Option<&Endorsement>| {
+            certificate_chain::load_chain(Some(dir), None, None, vek)
+                .map(|(_, vek_type, sign_type)| (vek_type, sign_type))
+        };
+
+        // A VLEK provisioned with an ASK instead of its ASVK
+        let dir = scratch_dir("roles-misprovisioned", &[]);
+        write(&dir, "ark", "ARK-Milan");
+        write(&dir, "ask", "SEV-Milan");
+        write(&dir, "vlek", "SEV-VLEK");
+        let err = load(&dir, None).unwrap_err();
+        assert!(err.to_string().contains("signed by an ASVK"));
+
+        // Both chains side by side
+        write(&dir, "asvk", "SEV-VLEK-Milan");
+        write(&dir, "vcek", "SEV-VCEK");
+        assert_eq!(load(&dir, None).unwrap(), ("vlek", "asvk"));
+        assert_eq!(
+            load(&dir, Some(&Endorsement::Vcek)).unwrap(),
+            ("vcek", "ask")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        // File names don't matter, the subjects do
+        let dir = scratch_dir("roles-mislabelled", &[]);
+        write(&dir, "ark", "ARK-Genoa");
+        write(&dir, "ask", "SEV-VLEK-Genoa");
+        write(&dir, "vcek", "SEV-VLEK");
+        assert_eq!(load(&dir, None).unwrap(), ("vlek", "asvk"));
+        let err = load(&dir, Some(&Endorsement::Vcek)).unwrap_err();
+        assert!(err.to_string().contains("only a VLEK"));
+        fs::remove_dir_all(&dir).unwrap();
     }
 }